@@ -0,0 +1,254 @@
+use num_traits::{One, Zero};
+use secp256k1_math::{
+    point::{Compressed, Point, G},
+    scalar::Scalar,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::common::Signature;
+use crate::util::hash_to_scalar;
+
+// BIP-340 keys and nonces are x-only, and implicitly refer to the point with even Y
+pub fn has_even_y(p: &Point) -> bool {
+    p.compress().as_bytes()[0] == 2
+}
+
+pub fn x_only(p: &Point) -> [u8; 32] {
+    let mut x = [0u8; 32];
+    x.copy_from_slice(&p.compress().as_bytes()[1..]);
+    x
+}
+
+// multiplying by this moves a point, or the secret behind it, onto the even Y side
+pub fn parity(p: &Point) -> Scalar {
+    if has_even_y(p) {
+        Scalar::one()
+    } else {
+        -Scalar::one()
+    }
+}
+
+// sha256(sha256(tag) || sha256(tag) || ...)
+pub fn tagged_hash(tag: &str) -> Sha256 {
+    let tag_hash = Sha256::digest(tag.as_bytes());
+    let mut hasher = Sha256::new();
+
+    hasher.update(tag_hash);
+    hasher.update(tag_hash);
+
+    hasher
+}
+
+#[allow(non_snake_case)]
+pub fn challenge(publicKey: &Point, R: &Point, msg: &[u8]) -> Scalar {
+    challenge_x_only(&x_only(publicKey), &x_only(R), msg)
+}
+
+fn challenge_x_only(public_key: &[u8; 32], r: &[u8; 32], msg: &[u8]) -> Scalar {
+    let mut hasher = tagged_hash("BIP0340/challenge");
+
+    hasher.update(r);
+    hasher.update(public_key);
+    hasher.update(msg);
+
+    hash_to_scalar(&mut hasher)
+}
+
+// A BIP-340 signature, which serializes to x(R) || s
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct SchnorrProof {
+    pub r: [u8; 32],
+    pub s: Scalar,
+}
+
+impl SchnorrProof {
+    // the signature must already have an even R, which the aggregators ensure in BIP-340 mode
+    pub fn new(sig: &Signature) -> Self {
+        Self {
+            r: x_only(&sig.R),
+            s: sig.z,
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; 64] {
+        let mut bytes = [0u8; 64];
+        bytes[..32].copy_from_slice(&self.r);
+        bytes[32..].copy_from_slice(self.s.as_bytes());
+        bytes
+    }
+
+    // fails if s is not less than the curve order
+    pub fn from_bytes(bytes: &[u8; 64]) -> Option<Self> {
+        let mut r = [0u8; 32];
+        let mut s_bytes = [0u8; 32];
+        r.copy_from_slice(&bytes[..32]);
+        s_bytes.copy_from_slice(&bytes[32..]);
+
+        let s = Scalar::from(s_bytes);
+        if *s.as_bytes() != s_bytes {
+            return None;
+        }
+
+        Some(Self { r, s })
+    }
+
+    // verify as specified in BIP-340: R = s * G - e * P must be finite, have even Y, and x(R) == r.
+    // A public key which is not the x coordinate of a point on the curve never verifies
+    #[allow(non_snake_case)]
+    pub fn verify(&self, public_key: &[u8; 32], msg: &[u8]) -> bool {
+        let mut compressed = [2u8; 33];
+        compressed[1..].copy_from_slice(public_key);
+        let P = match Point::try_from(&Compressed::from(&compressed[..])) {
+            Ok(P) => P,
+            Err(_) => return false,
+        };
+
+        let e = challenge_x_only(public_key, &self.r, msg);
+        let R = self.s * G + (-e) * P;
+
+        !R.is_zero() && has_even_y(&R) && x_only(&R) == self.r
+    }
+}
+
+// check a signature with libsecp256k1, which shares no code with SchnorrProof::verify
+#[cfg(test)]
+pub fn libsecp256k1_verify(proof: &SchnorrProof, public_key: &[u8; 32], msg: &[u8; 32]) -> bool {
+    use secp256k1::{schnorr, Message, Secp256k1, XOnlyPublicKey};
+
+    let sig = schnorr::Signature::from_slice(&proof.to_bytes());
+    let key = XOnlyPublicKey::from_slice(public_key);
+    match (sig, key) {
+        (Ok(sig), Ok(key)) => Secp256k1::verification_only()
+            .verify_schnorr(&sig, &Message::from_digest(*msg), &key)
+            .is_ok(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bip340::{self, SchnorrProof};
+
+    // (public key, message, signature, valid) from
+    // https://github.com/bitcoin/bips/blob/master/bip-0340/test-vectors.csv
+    const VECTORS: &[(&str, &str, &str, bool)] = &[
+        (
+            "F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9",
+            "0000000000000000000000000000000000000000000000000000000000000000",
+            "E907831F80848D1069A5371B402410364BDF1C5F8307B0084C55F1CE2DCA821525F66A4A85EA8B71E482A74F382D2CE5EBEEE8FDB2172F477DF4900D310536C0",
+            true,
+        ),
+        (
+            "DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659",
+            "243F6A8885A308D313198A2E03707344A4093822299F31D0082EFA98EC4E6C89",
+            "6896BD60EEAE296DB48A229FF71DFE071BDE413E6D43F917DC8DCF8C78DE33418906D11AC976ABCCB20B091292BFF4EA897EFCB639EA871CFA95F6DE339E4B0A",
+            true,
+        ),
+        (
+            "DD308AFEC5777E13121FA72B9CC1B7CC0139715309B086C960E18FD969774EB8",
+            "7E2D58D8B3BCDF1ABADEC7829054F90DDA9805AAB56C77333024B9D0A508B75C",
+            "5831AAEED7B44BB74E5EAB94BA9D4294C49BCF2A60728D8B4C200F50DD313C1BAB745879A5AD954A72C45A91C3A51D3C7ADEA98D82F8481E0E1E03674A6F3FB7",
+            true,
+        ),
+        (
+            "25D1DFF95105F5253C4022F628A996AD3A0D95FBF21D468A1B33F8C160D8F517",
+            "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF",
+            "7EB0509757E246F19449885651611CB965ECC1A187DD51B64FDA1EDC9637D5EC97582B9CB13DB3933705B32BA982AF5AF25FD78881EBB32771FC5922EFC66EA3",
+            true,
+        ),
+        (
+            "D69C3509BB99E412E68B0FE8544E72837DFA30746D8BE2AA65975F29D22DC7B9",
+            "4DF3C3F68FCC83B27E9D42C90431A72499F17875C81A599B566C9889B9696703",
+            "00000000000000000000003B78CE563F89A0ED9414F5AA28AD0D96D6795F9C6376AFB1548AF603B3EB45C9F8207DEE1060CB71C04E80F593060B07D28308D7F4",
+            true,
+        ),
+        (
+            "EEFDEA4CDB677750A420FEE807EACF21EB9898AE79B9768766E4FAA04A2D4A34",
+            "243F6A8885A308D313198A2E03707344A4093822299F31D0082EFA98EC4E6C89",
+            "6CFF5C3BA86C69EA4B7376F31A9BCB4F74C1976089B2D9963DA2E5543E17776969E89B4C5564D00349106B8497785DD7D1D713A8AE82B32FA79D5F7FC407D39B",
+            false,
+        ),
+        (
+            "DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659",
+            "243F6A8885A308D313198A2E03707344A4093822299F31D0082EFA98EC4E6C89",
+            "FFF97BD5755EEEA420453A14355235D382F6472F8568A18B2F057A14602975563CC27944640AC607CD107AE10923D9EF7A73C643E166BE5EBEAFA34B1AC553E2",
+            false,
+        ),
+        (
+            "DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659",
+            "243F6A8885A308D313198A2E03707344A4093822299F31D0082EFA98EC4E6C89",
+            "1FA62E331EDBC21C394792D2AB1100A7B432B013DF3F6FF4F99FCB33E0E1515F28890B3EDB6E7189B630448B515CE4F8622A954CFE545735AAEA5134FCCDB2BD",
+            false,
+        ),
+        (
+            "DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659",
+            "243F6A8885A308D313198A2E03707344A4093822299F31D0082EFA98EC4E6C89",
+            "6CFF5C3BA86C69EA4B7376F31A9BCB4F74C1976089B2D9963DA2E5543E177769961764B3AA9B2FFCB6EF947B6887A226E8D7C93E00C5ED0C1834FF0D0C2E6DA6",
+            false,
+        ),
+        (
+            "DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659",
+            "243F6A8885A308D313198A2E03707344A4093822299F31D0082EFA98EC4E6C89",
+            "0000000000000000000000000000000000000000000000000000000000000000123DDA8328AF9C23A94C1FEECFD123BA4FB73476F0D594DCB65C6425BD186051",
+            false,
+        ),
+        (
+            "DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659",
+            "243F6A8885A308D313198A2E03707344A4093822299F31D0082EFA98EC4E6C89",
+            "00000000000000000000000000000000000000000000000000000000000000017615FBAF5AE28864013C099742DEADB4DBA87F11AC6754F93780D5A1837CF197",
+            false,
+        ),
+        (
+            "DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659",
+            "243F6A8885A308D313198A2E03707344A4093822299F31D0082EFA98EC4E6C89",
+            "4A298DACAE57395A15D0795DDBFD1DCB564DA82B0F269BC70A74F8220429BA1D69E89B4C5564D00349106B8497785DD7D1D713A8AE82B32FA79D5F7FC407D39B",
+            false,
+        ),
+        (
+            "DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659",
+            "243F6A8885A308D313198A2E03707344A4093822299F31D0082EFA98EC4E6C89",
+            "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEFFFFFC2F69E89B4C5564D00349106B8497785DD7D1D713A8AE82B32FA79D5F7FC407D39B",
+            false,
+        ),
+        (
+            "DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659",
+            "243F6A8885A308D313198A2E03707344A4093822299F31D0082EFA98EC4E6C89",
+            "6CFF5C3BA86C69EA4B7376F31A9BCB4F74C1976089B2D9963DA2E5543E177769FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEBAAEDCE6AF48A03BBFD25E8CD0364141",
+            false,
+        ),
+        (
+            "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEFFFFFC30",
+            "243F6A8885A308D313198A2E03707344A4093822299F31D0082EFA98EC4E6C89",
+            "6CFF5C3BA86C69EA4B7376F31A9BCB4F74C1976089B2D9963DA2E5543E17776969E89B4C5564D00349106B8497785DD7D1D713A8AE82B32FA79D5F7FC407D39B",
+            false,
+        ),
+    ];
+
+    #[test]
+    fn test_vectors() {
+        for (public_key, msg, sig, valid) in VECTORS {
+            let public_key: [u8; 32] = hex::decode(public_key).unwrap().try_into().unwrap();
+            let msg = hex::decode(msg).unwrap();
+            let sig: [u8; 64] = hex::decode(sig).unwrap().try_into().unwrap();
+
+            let verified = match SchnorrProof::from_bytes(&sig) {
+                Some(proof) => proof.verify(&public_key, &msg),
+                None => false,
+            };
+            assert_eq!(verified, *valid, "vector {}", hex::encode(sig));
+
+            // and libsecp256k1 agrees
+            if let (Some(proof), Ok(msg)) = (SchnorrProof::from_bytes(&sig), msg.try_into()) {
+                assert_eq!(
+                    bip340::libsecp256k1_verify(&proof, &public_key, &msg),
+                    *valid
+                );
+            }
+
+            if let Some(proof) = SchnorrProof::from_bytes(&sig) {
+                assert_eq!(proof.to_bytes(), sig);
+            }
+        }
+    }
+}
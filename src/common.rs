@@ -6,8 +6,10 @@ use secp256k1_math::{
     scalar::Scalar,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-use crate::compute::challenge;
+use crate::compute::{self, challenge};
+use crate::errors::SigningPackageError;
 use crate::schnorr::ID;
 
 #[derive(Clone, Deserialize, Serialize)]
@@ -18,8 +20,9 @@ pub struct PolyCommitment {
 }
 
 impl PolyCommitment {
-    pub fn verify(&self) -> bool {
-        self.id.verify(&self.A[0])
+    // an empty commitment has no constant term to prove knowledge of
+    pub fn verify(&self, ctx: &[u8]) -> bool {
+        !self.A.is_empty() && self.id.verify(&self.A[0], ctx)
     }
 }
 
@@ -30,10 +33,25 @@ pub struct Nonce {
 }
 
 impl Nonce {
-    pub fn random<RNG: RngCore + CryptoRng>(rng: &mut RNG) -> Self {
+    // hedged with the secret key share, see compute::nonce
+    pub fn random<RNG: RngCore + CryptoRng>(secret: &Scalar, ctx: &[u8], rng: &mut RNG) -> Self {
+        let mut hiding = [0u8; 32];
+        let mut binding = [0u8; 32];
+        rng.fill_bytes(&mut hiding);
+        rng.fill_bytes(&mut binding);
+
+        Self::from_randomness(secret, &hiding, &binding, ctx)
+    }
+
+    pub fn from_randomness(
+        secret: &Scalar,
+        hiding: &[u8; 32],
+        binding: &[u8; 32],
+        ctx: &[u8],
+    ) -> Self {
         Self {
-            d: Scalar::random(rng),
-            e: Scalar::random(rng),
+            d: compute::nonce(secret, hiding, ctx),
+            e: compute::nonce(secret, binding, ctx),
         }
     }
 }
@@ -78,15 +96,186 @@ impl PublicNonce {
     }
 }
 
-// TODO: Remove public key from here
-// The SA should get that as usual
-#[derive(Debug, Deserialize, Serialize)]
+// Everything a signing round binds to: the message, and the public nonce and key_ids of each
+// signer, kept sorted by signer id so no caller can pair a nonce with the wrong signer.  In v1
+// each signer's only key_id is its own id
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "SigningPackageData", into = "SigningPackageData")]
+pub struct SigningPackage {
+    msg: Vec<u8>,
+    nonces: BTreeMap<usize, PublicNonce>,
+    signer_key_ids: BTreeMap<usize, Vec<usize>>,
+    key_ids: Vec<usize>,
+}
+
+impl SigningPackage {
+    // each signer holds the single key with its own id, as in v1
+    pub fn new(
+        msg: &[u8],
+        nonces: &[(usize, PublicNonce)],
+        threshold: usize,
+    ) -> Result<Self, SigningPackageError> {
+        let key_ids: Vec<(usize, Vec<usize>)> =
+            nonces.iter().map(|(id, _)| (*id, vec![*id])).collect();
+        Self::with_key_ids(msg, nonces, &key_ids, threshold)
+    }
+
+    // key_ids has the key_ids each signer signs with, and must cover the same signers as nonces
+    pub fn with_key_ids(
+        msg: &[u8],
+        nonces: &[(usize, PublicNonce)],
+        key_ids: &[(usize, Vec<usize>)],
+        threshold: usize,
+    ) -> Result<Self, SigningPackageError> {
+        let mut nonce_map = BTreeMap::new();
+        for (id, nonce) in nonces {
+            if nonce_map.insert(*id, nonce.clone()).is_some() {
+                return Err(SigningPackageError::DuplicateSigner(*id));
+            }
+        }
+
+        let mut signer_key_ids = BTreeMap::new();
+        for (id, signer_keys) in key_ids {
+            let mut signer_keys = signer_keys.clone();
+            signer_keys.sort();
+            if signer_key_ids.insert(*id, signer_keys).is_some() {
+                return Err(SigningPackageError::DuplicateSigner(*id));
+            }
+        }
+        let mut mismatched_ids: Vec<usize> = nonce_map
+            .keys()
+            .filter(|id| !signer_key_ids.contains_key(*id))
+            .chain(
+                signer_key_ids
+                    .keys()
+                    .filter(|id| !nonce_map.contains_key(*id)),
+            )
+            .cloned()
+            .collect();
+        if !mismatched_ids.is_empty() {
+            mismatched_ids.sort();
+            return Err(SigningPackageError::MismatchedKeyIds(mismatched_ids));
+        }
+
+        let mut all_key_ids: Vec<usize> = signer_key_ids.values().flatten().cloned().collect();
+        all_key_ids.sort();
+        if let Some(k) = all_key_ids.windows(2).find(|k| k[0] == k[1]) {
+            return Err(SigningPackageError::DuplicateKey(k[0]));
+        }
+        if all_key_ids.len() < threshold {
+            return Err(SigningPackageError::BelowThreshold(
+                threshold,
+                all_key_ids.len(),
+            ));
+        }
+
+        // an identity nonce would let its signer's share leak the signer's key
+        let identity_nonces: Vec<usize> = nonce_map
+            .iter()
+            .filter(|(_, n)| n.D.is_zero() || n.E.is_zero())
+            .map(|(id, _)| *id)
+            .collect();
+        if !identity_nonces.is_empty() {
+            return Err(SigningPackageError::IdentityNonces(identity_nonces));
+        }
+
+        Ok(Self {
+            msg: msg.to_vec(),
+            nonces: nonce_map,
+            signer_key_ids,
+            key_ids: all_key_ids,
+        })
+    }
+
+    pub fn msg(&self) -> &[u8] {
+        &self.msg
+    }
+
+    // the signer ids in sorted order
+    pub fn signers(&self) -> Vec<usize> {
+        self.nonces.keys().cloned().collect()
+    }
+
+    // the public nonces in signers order
+    pub fn nonces(&self) -> Vec<PublicNonce> {
+        self.nonces.values().cloned().collect()
+    }
+
+    pub fn nonce(&self, id: usize) -> Option<&PublicNonce> {
+        self.nonces.get(&id)
+    }
+
+    // all the signing key ids in sorted order
+    pub fn key_ids(&self) -> &[usize] {
+        &self.key_ids
+    }
+
+    // the key ids signer id signs with in sorted order, empty if it is not signing
+    pub fn signer_key_ids(&self, id: usize) -> &[usize] {
+        self.signer_key_ids.get(&id).map_or(&[], |k| k.as_slice())
+    }
+
+    // every package in a batch must have the same signers with the same key ids
+    pub fn check_batch(packages: &[SigningPackage]) -> Result<(), SigningPackageError> {
+        match packages.split_first() {
+            Some((first, rest))
+                if rest
+                    .iter()
+                    .any(|p| p.signer_key_ids != first.signer_key_ids) =>
+            {
+                Err(SigningPackageError::MismatchedBatch)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// The serialized form of a SigningPackage, which is checked again when it is deserialized.
+// The threshold is not part of the package, so each signer checks it against its own
+#[derive(Deserialize, Serialize)]
+struct SigningPackageData {
+    msg: Vec<u8>,
+    nonces: Vec<(usize, PublicNonce)>,
+    key_ids: Vec<(usize, Vec<usize>)>,
+}
+
+impl From<SigningPackage> for SigningPackageData {
+    fn from(package: SigningPackage) -> Self {
+        Self {
+            msg: package.msg,
+            nonces: package.nonces.into_iter().collect(),
+            key_ids: package.signer_key_ids.into_iter().collect(),
+        }
+    }
+}
+
+impl TryFrom<SigningPackageData> for SigningPackage {
+    type Error = SigningPackageError;
+
+    fn try_from(data: SigningPackageData) -> Result<Self, Self::Error> {
+        Self::with_key_ids(&data.msg, &data.nonces, &data.key_ids, 0)
+    }
+}
+
+// The SA computes the public keys from the poly commitments, so the share only says which
+// key_ids it signed for.  In v1 that is just the party's own id.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub struct SignatureShare {
     pub id: usize,
     pub z_i: Scalar,
-    pub public_key: Point,
+    pub key_ids: Vec<usize>,
 }
 
+// The shares of one signer for a batch of packages, in package order.  The packages in a
+// batch all have the same signers and key_ids, so the key_ids are only sent once
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct BatchSignatureShare {
+    pub id: usize,
+    pub z_i: Vec<Scalar>,
+    pub key_ids: Vec<usize>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 #[allow(non_snake_case)]
 pub struct Signature {
     pub R: Point,
@@ -96,12 +285,61 @@ pub struct Signature {
 impl Signature {
     // verify: R' = z * G + -c * publicKey, pass if R' == R
     #[allow(non_snake_case)]
-    pub fn verify(&self, public_key: &Point, msg: &[u8]) -> bool {
-        let c = challenge(public_key, &self.R, msg);
+    pub fn verify(&self, public_key: &Point, msg: &[u8], ctx: &[u8]) -> bool {
+        let c = challenge(public_key, &self.R, msg, ctx);
         let R = &self.z * G + (-c) * public_key;
 
-        println!("Verification R = {}", R);
-
         R == self.R
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use num_traits::Zero;
+    use secp256k1_math::scalar::Scalar;
+
+    use crate::common::Nonce;
+    use crate::util::{decode_scalar, encode_scalar};
+
+    // (secret, hiding randomness, binding randomness, context, d, e)
+    const VECTORS: &[(&str, [u8; 32], [u8; 32], &str, &str, &str)] = &[
+        (
+            "0d004150d27c3bf2a42f312683d35fac7394b1e9e318249c1bfe7f0795a83114",
+            [
+                0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22,
+                23, 24, 25, 26, 27, 28, 29, 30, 31,
+            ],
+            [0xff; 32],
+            "",
+            "4c0b415aed7a563a7a625ae960ae460dc2f2dfa56f6e7fc5ae03495b992cbcdd",
+            "e1d31b77e95b714f8a7555c7d9955f2ba1f1013b78585691c7669e3cd0a4bbd9",
+        ),
+        (
+            "0d004150d27c3bf2a42f312683d35fac7394b1e9e318249c1bfe7f0795a83114",
+            [
+                0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22,
+                23, 24, 25, 26, 27, 28, 29, 30, 31,
+            ],
+            [0xff; 32],
+            "chain 1",
+            "044022ec5840defe9b86ff5519d2851bddcc46e02d4b8e5365d78dff7c8a25d7",
+            "d5185aba713f044e150f2e2dc0d74ff38de54ce8e8db844c6f8b6035932db0fc",
+        ),
+    ];
+
+    #[test]
+    fn hedged_nonce_vectors() {
+        for (secret, hiding, binding, ctx, d, e) in VECTORS {
+            let secret = decode_scalar(&secret.to_string());
+            let nonce = Nonce::from_randomness(&secret, hiding, binding, ctx.as_bytes());
+
+            assert_eq!(encode_scalar(&nonce.d), *d);
+            assert_eq!(encode_scalar(&nonce.e), *e);
+
+            // the same randomness under another key share gives an unrelated nonce
+            let other = Nonce::from_randomness(&Scalar::zero(), hiding, binding, ctx.as_bytes());
+            assert_ne!(other.d, nonce.d);
+            assert_ne!(other.e, nonce.e);
+        }
+    }
+}
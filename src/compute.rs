@@ -1,15 +1,51 @@
 use core::iter::zip;
 use num_traits::{One, Zero};
-use secp256k1_math::{point::Point, scalar::Scalar};
+use secp256k1_math::{
+    point::{Compressed, Point, G},
+    scalar::Scalar,
+};
 use sha3::{Digest, Sha3_256};
 
-use crate::common::PublicNonce;
+use crate::bip340;
+use crate::common::{PublicNonce, SigningPackage};
+use crate::taproot;
 use crate::util::hash_to_scalar;
 
-#[allow(non_snake_case)]
-pub fn binding(id: &Scalar, B: &[PublicNonce], msg: &[u8]) -> Scalar {
+// Every hash is prefixed with the ciphersuite context string and a tag for its use, then the
+// application context (e.g. a chain id or group id), so hashes from one protocol version,
+// purpose or deployment can never be valid in another
+pub const CONTEXT_STRING: &[u8] = b"FROST-secp256k1-SHA3-256-v1";
+pub const BINDING_TAG: &[u8] = b"rho";
+pub const CHALLENGE_TAG: &[u8] = b"chal";
+pub const POK_TAG: &[u8] = b"pok";
+pub const NONCE_TAG: &[u8] = b"nonce";
+
+pub fn hasher(tag: &[u8], ctx: &[u8]) -> Sha3_256 {
     let mut hasher = Sha3_256::new();
 
+    hasher.update(CONTEXT_STRING);
+    hasher.update(tag);
+    hasher.update((ctx.len() as u64).to_be_bytes());
+    hasher.update(ctx);
+
+    hasher
+}
+
+// Hedged nonce derivation in the style of RFC 9591 nonce_generate: the fresh randomness is
+// hashed with the secret key share, so a weak or repeated rng does not repeat the nonce
+pub fn nonce(secret: &Scalar, random_bytes: &[u8; 32], ctx: &[u8]) -> Scalar {
+    let mut hasher = hasher(NONCE_TAG, ctx);
+
+    hasher.update(random_bytes);
+    hasher.update(secret.as_bytes());
+
+    hash_to_scalar(&mut hasher)
+}
+
+#[allow(non_snake_case)]
+pub fn binding(id: &Scalar, B: &[PublicNonce], msg: &[u8], ctx: &[u8]) -> Scalar {
+    let mut hasher = hasher(BINDING_TAG, ctx);
+
     hasher.update(id.as_bytes());
     for b in B {
         hasher.update(b.D.compress().as_bytes());
@@ -21,8 +57,8 @@ pub fn binding(id: &Scalar, B: &[PublicNonce], msg: &[u8]) -> Scalar {
 }
 
 #[allow(non_snake_case)]
-pub fn challenge(publicKey: &Point, R: &Point, msg: &[u8]) -> Scalar {
-    let mut hasher = Sha3_256::new();
+pub fn challenge(publicKey: &Point, R: &Point, msg: &[u8], ctx: &[u8]) -> Scalar {
+    let mut hasher = hasher(CHALLENGE_TAG, ctx);
 
     hasher.update(publicKey.compress().as_bytes());
     hasher.update(R.compress().as_bytes());
@@ -31,6 +67,67 @@ pub fn challenge(publicKey: &Point, R: &Point, msg: &[u8]) -> Scalar {
     hash_to_scalar(&mut hasher)
 }
 
+// The challenge, and the signs applied to the nonce and key parts of each share.
+// With a tweak the shares are for a BIP-340 signature under taproot::tweaked_public_key,
+// so a zero tweak signs for the even Y version of the untweaked key.  The BIP-340 challenge
+// is fixed by the standard, so ctx only applies without a tweak
+#[allow(non_snake_case)]
+pub fn tweaked_challenge(
+    publicKey: &Point,
+    R: &Point,
+    msg: &[u8],
+    tweak: Option<Scalar>,
+    ctx: &[u8],
+) -> (Scalar, Scalar, Scalar) {
+    match tweak {
+        Some(t) => {
+            let Q = taproot::tweaked_public_key(publicKey, &t);
+            (
+                bip340::challenge(&Q, R, msg),
+                bip340::parity(R),
+                bip340::parity(publicKey) * bip340::parity(&Q),
+            )
+        }
+        None => (
+            challenge(publicKey, R, msg, ctx),
+            Scalar::one(),
+            Scalar::one(),
+        ),
+    }
+}
+
+// evaluate a polynomial commitment at id: sum(A[j] * id^j)
+#[allow(non_snake_case)]
+pub fn poly(id: &Scalar, A: &[Point]) -> Point {
+    (0..A.len()).fold(Point::zero(), |s, j| s + (*id ^ j) * A[j])
+}
+
+// check a share against a polynomial commitment
+#[allow(non_snake_case)]
+pub fn check_share(id: &Scalar, s: &Scalar, A: &[Point]) -> bool {
+    s * G == poly(id, A)
+}
+
+// The x coordinate of the BIP-341 NUMS point, which has even Y and no known discrete log
+// with respect to G
+const NUMS_X: [u8; 32] = [
+    0x50, 0x92, 0x9b, 0x74, 0xc1, 0xa0, 0x49, 0x54, 0xb7, 0x8b, 0x4b, 0x60, 0x35, 0xe9, 0x7a, 0x5e,
+    0x07, 0x8a, 0x5a, 0x0f, 0x28, 0xec, 0x96, 0xd5, 0x47, 0xbf, 0xee, 0x9a, 0xce, 0x80, 0x3a, 0xc0,
+];
+
+// the second generator for Pedersen commitments
+pub fn pedersen_generator() -> Point {
+    let mut compressed = [2u8; 33];
+    compressed[1..].copy_from_slice(&NUMS_X);
+    Point::from(Compressed::from(&compressed[..]))
+}
+
+// check a share and its blinding share against a Pedersen commitment
+#[allow(non_snake_case)]
+pub fn check_pedersen_share(id: &Scalar, s: &Scalar, blinding: &Scalar, C: &[Point]) -> bool {
+    s * G + blinding * pedersen_generator() == poly(id, C)
+}
+
 pub fn lambda(i: &usize, indices: &[usize]) -> Scalar {
     let mut lambda = Scalar::one();
     let i_scalar = Scalar::from((i + 1) as u32);
@@ -43,15 +140,57 @@ pub fn lambda(i: &usize, indices: &[usize]) -> Scalar {
     lambda
 }
 
-// Is this the best way to return these values?
+// the lagrange coefficient for i when interpolating at x rather than at zero
+pub fn lambda_at(i: &usize, indices: &[usize], x: &Scalar) -> Scalar {
+    let mut lambda = Scalar::one();
+    let i_scalar = Scalar::from((i + 1) as u32);
+    for j in indices {
+        if i != j {
+            let j_scalar = Scalar::from((j + 1) as u32);
+            lambda *= (*x - j_scalar) / (i_scalar - j_scalar);
+        }
+    }
+    lambda
+}
+
+// The coefficients of the polynomial through points, each a key_id and the polynomial's value
+// at that key_id, whose degree is one less than the number of points
+pub fn interpolate(points: &[(usize, Scalar)]) -> Vec<Scalar> {
+    let mut coefficients = vec![Scalar::zero(); points.len()];
+    for (i, (key_id, y)) in points.iter().enumerate() {
+        let x = Scalar::from((key_id + 1) as u32);
+        // the Lagrange basis polynomial for key_id, multiplied out one root at a time
+        let mut basis = vec![Scalar::one()];
+        let mut denominator = Scalar::one();
+        for (j, (other, _)) in points.iter().enumerate() {
+            if i != j {
+                let root = Scalar::from((other + 1) as u32);
+                let mut next = vec![Scalar::zero(); basis.len() + 1];
+                for (k, b) in basis.iter().enumerate() {
+                    next[k] -= root * b;
+                    next[k + 1] += b;
+                }
+                basis = next;
+                denominator *= x - root;
+            }
+        }
+        let scale = *y / denominator;
+        for (c, b) in zip(coefficients.iter_mut(), &basis) {
+            *c += scale * b;
+        }
+    }
+    coefficients
+}
+
+// The commitment R_i of each signer, in package.signers() order, and the group commitment R
 #[allow(non_snake_case)]
-pub fn intermediate(msg: &[u8], signers: &[usize], nonces: &[PublicNonce]) -> (Vec<Point>, Point) {
-    let rhos: Vec<Scalar> = signers
-        .iter()
-        .map(|&i| binding(&Scalar::from((i + 1) as u32), nonces, msg))
-        .collect();
-    let R_vec: Vec<Point> = zip(nonces, rhos)
-        .map(|(nonce, rho)| nonce.D + rho * nonce.E)
+pub fn intermediate(package: &SigningPackage, ctx: &[u8]) -> (Vec<Point>, Point) {
+    let nonces = package.nonces();
+    let R_vec: Vec<Point> = zip(package.signers(), &nonces)
+        .map(|(i, nonce)| {
+            let rho = binding(&Scalar::from((i + 1) as u32), &nonces, package.msg(), ctx);
+            nonce.D + rho * nonce.E
+        })
         .collect();
 
     let R = R_vec.iter().fold(Point::zero(), |R, &R_i| R + R_i);
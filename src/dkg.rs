@@ -0,0 +1,1028 @@
+use core::iter::zip;
+use hashbrown::HashSet;
+use num_traits::Zero;
+use polynomial::Polynomial;
+use rand_core::{CryptoRng, RngCore};
+use secp256k1_math::{
+    point::{Point, G},
+    scalar::Scalar,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::common::PolyCommitment;
+use crate::compute;
+use crate::errors::DkgError;
+use crate::schnorr::ID;
+use crate::util::{decrypt, encrypt, make_shared_secret};
+use crate::vss::VSS;
+use std::collections::{BTreeMap, BTreeSet};
+
+// With Feldman the poly commitments are broadcast first, so the last party to commit can
+// pick its polynomial after seeing everyone else's and bias the group key.  With Pedersen
+// round one only broadcasts hiding commitments, and the poly commitments are revealed in an
+// extraction round once every share is in, as in the DKG of Gennaro, Jarecki, Krawczyk and
+// Rabin
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum DkgScheme {
+    Feldman,
+    Pedersen,
+}
+
+// Round one of a Feldman Dkg, broadcast to every party.  In a Pedersen Dkg this is broadcast
+// in the extraction round instead
+#[derive(Clone, Deserialize, Serialize)]
+pub struct DkgCommitment {
+    pub sender: usize,
+    pub commitment: PolyCommitment,
+}
+
+// Round one of a Refresh, broadcast to every party.  The sender's polynomial has a zero
+// constant term, so A[0] is the identity and there is nothing to prove knowledge of
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[allow(non_snake_case)]
+pub struct RefreshCommitment {
+    pub sender: usize,
+    pub A: Vec<Point>,
+}
+
+// Round two of a Dkg or Refresh, sent privately to the owner of key_id, or encrypted to it as an
+// EncryptedShare.  In v1 the key_id is the recipient's party id.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct DkgShare {
+    pub sender: usize,
+    pub key_id: usize,
+    pub share: Scalar,
+    pub blinding: Option<Scalar>, // the blinding polynomial's share, only in a Pedersen Dkg
+}
+
+// Round one of a Pedersen Dkg, broadcast to every party.  C[k] = a[k] * G + b[k] * H, where
+// a is the sender's polynomial, b is its blinding polynomial and H is
+// compute::pedersen_generator
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[allow(non_snake_case)]
+pub struct PedersenCommitment {
+    pub sender: usize,
+    pub C: Vec<Point>,
+}
+
+impl PedersenCommitment {
+    #[allow(non_snake_case)]
+    pub fn new(sender: usize, f: &Polynomial<Scalar>, blinding: &Polynomial<Scalar>) -> Self {
+        let H = compute::pedersen_generator();
+        let C = zip(f.data(), blinding.data())
+            .map(|(a, b)| a * G + b * H)
+            .collect();
+
+        Self { sender, C }
+    }
+}
+
+// Broadcast in the extraction round of a Pedersen Dkg when a dealer's commitment is missing
+// or doesn't match the shares it dealt.  It reveals the share and blinding the sender got from
+// the dealer for key_id, which anyone can check against the dealer's PedersenCommitment, and
+// with threshold of them the dealer's polynomial is rebuilt without it, as in GJKR
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct RecoveryShare {
+    pub sender: usize,
+    pub dealer: usize,
+    pub key_id: usize,
+    pub share: Scalar,
+    pub blinding: Scalar,
+}
+
+// Shares are refused until a party has every commitment and has sent its own shares.
+// Only a Pedersen Dkg has an Extraction round, which starts once a party has every share
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum DkgState {
+    Commitments,
+    Shares,
+    Extraction,
+    Done,
+}
+
+impl DkgState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Commitments => "commitments",
+            Self::Shares => "shares",
+            Self::Extraction => "extraction",
+            Self::Done => "done",
+        }
+    }
+}
+
+// A commitment must be for the sender's id, have a coefficient for each degree of the
+// polynomial, and have a good proof of knowledge of its constant term
+pub fn check_commitment(msg: &DkgCommitment, threshold: usize, ctx: &[u8]) -> bool {
+    let commitment = &msg.commitment;
+    commitment.A.len() == threshold
+        && commitment.id.id == Scalar::from((msg.sender + 1) as u32)
+        && commitment.verify(ctx)
+}
+
+// A refresh commitment must have a coefficient for each degree of the polynomial, and a zero
+// constant term so the group key is unchanged
+pub fn check_refresh_commitment(msg: &RefreshCommitment, threshold: usize) -> bool {
+    msg.A.len() == threshold && msg.A[0].is_zero()
+}
+
+// What a sender broadcasts before its shares, which they are checked against
+pub trait Commitment {
+    fn points(&self) -> &[Point];
+}
+
+impl Commitment for PolyCommitment {
+    fn points(&self) -> &[Point] {
+        &self.A
+    }
+}
+
+impl Commitment for Vec<Point> {
+    fn points(&self) -> &[Point] {
+        self
+    }
+}
+
+// The rounds of every Dkg, Refresh and Reshare, in v1 and v2.  Each sender broadcasts a
+// commitment, then sends a share for each key_id this party owns, which in v1 is just its own
+// party id.  A message can be received again, but a different one from the same sender is
+// refused
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Rounds<C> {
+    state: DkgState,
+    senders: Vec<usize>,
+    key_ids: BTreeSet<usize>,
+    commitments: BTreeMap<usize, C>,
+    shares: BTreeMap<usize, BTreeMap<usize, Scalar>>, // key is key_id, then sender
+}
+
+impl<C: Clone + Commitment> Rounds<C> {
+    pub fn new<I: IntoIterator<Item = usize>>(senders: Vec<usize>, key_ids: I) -> Self {
+        Self {
+            state: DkgState::Commitments,
+            senders,
+            key_ids: key_ids.into_iter().collect(),
+            commitments: BTreeMap::new(),
+            shares: BTreeMap::new(),
+        }
+    }
+
+    // start with this party's own commitment
+    pub fn with_commitment(mut self, sender: usize, commitment: C) -> Self {
+        self.commitments.insert(sender, commitment);
+        self
+    }
+
+    pub fn state(&self) -> DkgState {
+        self.state
+    }
+
+    pub fn commitments(&self) -> &BTreeMap<usize, C> {
+        &self.commitments
+    }
+
+    pub fn shares(&self) -> &BTreeMap<usize, BTreeMap<usize, Scalar>> {
+        &self.shares
+    }
+
+    // the sum of the shares for key_id
+    pub fn share_sum(&self, key_id: usize) -> Scalar {
+        self.shares.get(&key_id).map_or(Scalar::zero(), |s| {
+            s.values().fold(Scalar::zero(), |k, s| k + s)
+        })
+    }
+
+    pub fn check_round(&self, round: DkgState, msg: &'static str) -> Result<(), DkgError> {
+        if self.state != round {
+            return Err(DkgError::OutOfOrder(msg, self.state.name()));
+        }
+        Ok(())
+    }
+
+    pub fn check_sender(&self, sender: usize) -> Result<(), DkgError> {
+        if !self.senders.contains(&sender) {
+            return Err(DkgError::UnknownParty(sender));
+        }
+        Ok(())
+    }
+
+    // the senders which have not sent everything for the current round
+    pub fn missing(&self) -> Vec<usize> {
+        let senders = self.senders.iter().cloned();
+        match self.state {
+            DkgState::Commitments | DkgState::Extraction => senders
+                .filter(|i| !self.commitments.contains_key(i))
+                .collect(),
+            DkgState::Shares => senders
+                .filter(|i| {
+                    self.key_ids
+                        .iter()
+                        .any(|key_id| !self.shares.get(key_id).is_some_and(|s| s.contains_key(i)))
+                })
+                .collect(),
+            DkgState::Done => Vec::new(),
+        }
+    }
+
+    // valid is only asked about a commitment from a known sender in round
+    pub fn receive_commitment<F: FnOnce(&C) -> bool>(
+        &mut self,
+        round: DkgState,
+        sender: usize,
+        commitment: &C,
+        valid: F,
+    ) -> Result<(), DkgError> {
+        self.check_round(round, "commitment")?;
+        self.check_sender(sender)?;
+        if !valid(commitment) {
+            return Err(DkgError::BadPolyCommitments(vec![sender]));
+        }
+        if let Some(c) = self.commitments.get(&sender) {
+            if c.points() != commitment.points() {
+                return Err(DkgError::DuplicateMessage(sender));
+            }
+        }
+
+        self.commitments.insert(sender, commitment.clone());
+        Ok(())
+    }
+
+    // Once every commitment is in, move on to the shares.  Shares can be dealt again until the
+    // rounds are done
+    pub fn start_shares(&mut self) -> Result<(), DkgError> {
+        match self.state {
+            DkgState::Commitments => {
+                let missing = self.missing();
+                if !missing.is_empty() {
+                    return Err(DkgError::MissingPolyCommitments(missing));
+                }
+                self.state = DkgState::Shares;
+            }
+            DkgState::Shares | DkgState::Extraction => (),
+            DkgState::Done => return Err(DkgError::OutOfOrder("shares", self.state.name())),
+        }
+        Ok(())
+    }
+
+    // The shares of f for key_ids 0..num_keys, with the blinding polynomial's in a Pedersen
+    // Dkg.  The shares for this party's own key_ids are kept
+    pub fn deal(
+        &mut self,
+        sender: usize,
+        f: &Polynomial<Scalar>,
+        blinding: Option<&Polynomial<Scalar>>,
+        num_keys: usize,
+    ) -> Vec<DkgShare> {
+        let mut shares = Vec::new();
+        for key_id in 0..num_keys {
+            let id = Scalar::from((key_id + 1) as u32);
+            let share = f.eval(id);
+            if self.key_ids.contains(&key_id) {
+                self.shares.entry(key_id).or_default().insert(sender, share);
+            } else {
+                shares.push(DkgShare {
+                    sender,
+                    key_id,
+                    share,
+                    blinding: blinding.map(|b| b.eval(id)),
+                });
+            }
+        }
+        shares
+    }
+
+    // valid is given the share's key_id as a scalar, and is only asked about a share from a
+    // known sender for one of this party's key_ids
+    pub fn receive_share<F>(&mut self, msg: &DkgShare, valid: F) -> Result<(), DkgError>
+    where
+        F: FnOnce(&Self, &Scalar) -> Result<bool, DkgError>,
+    {
+        self.check_round(DkgState::Shares, "share")?;
+        self.check_sender(msg.sender)?;
+        if !self.key_ids.contains(&msg.key_id) {
+            return Err(DkgError::WrongKeyId(msg.key_id));
+        }
+        let id = Scalar::from((msg.key_id + 1) as u32);
+        if !valid(self, &id)? {
+            return Err(DkgError::BadShares(vec![msg.sender]));
+        }
+        let key_shares = self.shares.entry(msg.key_id).or_default();
+        if let Some(share) = key_shares.get(&msg.sender) {
+            if *share != msg.share {
+                return Err(DkgError::DuplicateMessage(msg.sender));
+            }
+        }
+
+        key_shares.insert(msg.sender, msg.share);
+        Ok(())
+    }
+
+    // a share checked against its sender's commitment, which is every share but a Pedersen
+    // Dkg's
+    pub fn receive_feldman_share(&mut self, msg: &DkgShare) -> Result<(), DkgError> {
+        self.receive_share(msg, |rounds, id| {
+            if msg.blinding.is_some() {
+                return Err(DkgError::WrongScheme(msg.sender));
+            }
+            let commitment = rounds.commitments[&msg.sender].points();
+            Ok(compute::check_share(id, &msg.share, commitment))
+        })
+    }
+
+    // every share must be in before the rounds can finish
+    pub fn check_finish(&self) -> Result<(), DkgError> {
+        self.check_round(DkgState::Shares, "finish")?;
+        let missing = self.missing();
+        if !missing.is_empty() {
+            return Err(DkgError::MissingShares(missing));
+        }
+        Ok(())
+    }
+
+    pub fn done(&mut self) {
+        self.state = DkgState::Done;
+    }
+}
+
+// The rounds of a Dkg, in v1 and v2.  A Pedersen Dkg broadcasts PedersenCommitments before
+// the shares, and only reveals the poly commitments in the extraction round.  A dealer which
+// then withholds its commitment or reveals a bad one can't abort the Dkg, since its polynomial
+// is rebuilt from RecoveryShares
+#[derive(Clone, Deserialize, Serialize)]
+pub struct DkgRounds {
+    id: usize,
+    scheme: DkgScheme,
+    context: Vec<u8>,
+    pedersen_commitments: BTreeMap<usize, Vec<Point>>,
+    blindings: BTreeMap<usize, BTreeMap<usize, Scalar>>, // key is key_id, then sender
+    recovered: BTreeMap<usize, BTreeMap<usize, Scalar>>, // key is dealer, then key_id
+    rounds: Rounds<PolyCommitment>,
+}
+
+impl DkgRounds {
+    // commitment is this party's own, and so is pedersen_commitment, which only a Pedersen
+    // Dkg has
+    pub fn new<I: IntoIterator<Item = usize>>(
+        id: usize,
+        num_parties: usize,
+        key_ids: I,
+        commitment: PolyCommitment,
+        pedersen_commitment: Option<PedersenCommitment>,
+        context: &[u8],
+    ) -> Self {
+        let scheme = match pedersen_commitment {
+            Some(_) => DkgScheme::Pedersen,
+            None => DkgScheme::Feldman,
+        };
+        Self {
+            id,
+            scheme,
+            context: context.to_vec(),
+            pedersen_commitments: pedersen_commitment.into_iter().map(|c| (id, c.C)).collect(),
+            blindings: BTreeMap::new(),
+            recovered: BTreeMap::new(),
+            rounds: Rounds::new((0..num_parties).collect(), key_ids)
+                .with_commitment(id, commitment),
+        }
+    }
+
+    pub fn scheme(&self) -> DkgScheme {
+        self.scheme
+    }
+
+    pub fn state(&self) -> DkgState {
+        self.rounds.state
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.rounds.commitments[&self.id].A.len()
+    }
+
+    // the shares received so far, keyed by key_id then sender
+    pub fn received_shares(&self) -> &BTreeMap<usize, BTreeMap<usize, Scalar>> {
+        self.rounds.shares()
+    }
+
+    // the parties which have not sent everything for the current round
+    pub fn missing(&self) -> Vec<usize> {
+        match (self.scheme, self.rounds.state) {
+            (DkgScheme::Pedersen, DkgState::Commitments) => self
+                .rounds
+                .senders
+                .iter()
+                .filter(|i| !self.pedersen_commitments.contains_key(i))
+                .cloned()
+                .collect(),
+            (DkgScheme::Pedersen, DkgState::Extraction) => self
+                .rounds
+                .missing()
+                .into_iter()
+                .filter(|i| self.dealer_points(*i).len() < self.threshold())
+                .collect(),
+            _ => self.rounds.missing(),
+        }
+    }
+
+    // every point on dealer's polynomial this party has, from its own shares and the
+    // RecoveryShares, keyed by key_id
+    fn dealer_points(&self, dealer: usize) -> BTreeMap<usize, Scalar> {
+        let mut points = self.recovered.get(&dealer).cloned().unwrap_or_default();
+        for (key_id, shares) in &self.rounds.shares {
+            if let Some(share) = shares.get(&dealer) {
+                points.insert(*key_id, *share);
+            }
+        }
+        points
+    }
+
+    // the round one broadcast of a Pedersen Dkg
+    pub fn pedersen_commitment(&self) -> Option<PedersenCommitment> {
+        self.pedersen_commitments
+            .get(&self.id)
+            .map(|commitment| PedersenCommitment {
+                sender: self.id,
+                C: commitment.clone(),
+            })
+    }
+
+    // In a Pedersen Dkg the commitment is only revealed once every share is in, and asking
+    // for it then starts the extraction round
+    pub fn commitment(&mut self) -> Result<DkgCommitment, DkgError> {
+        if self.scheme == DkgScheme::Pedersen {
+            match self.rounds.state {
+                DkgState::Commitments => {
+                    return Err(DkgError::OutOfOrder("commitment", self.state().name()))
+                }
+                DkgState::Shares => {
+                    let missing = self.missing();
+                    if !missing.is_empty() {
+                        return Err(DkgError::MissingShares(missing));
+                    }
+                    self.rounds.state = DkgState::Extraction;
+                }
+                DkgState::Extraction | DkgState::Done => (),
+            }
+        }
+
+        Ok(DkgCommitment {
+            sender: self.id,
+            commitment: self.rounds.commitments[&self.id].clone(),
+        })
+    }
+
+    pub fn receive_pedersen_commitment(
+        &mut self,
+        msg: &PedersenCommitment,
+    ) -> Result<(), DkgError> {
+        if self.scheme != DkgScheme::Pedersen {
+            return Err(DkgError::WrongScheme(msg.sender));
+        }
+        self.rounds
+            .check_round(DkgState::Commitments, "pedersen commitment")?;
+        self.rounds.check_sender(msg.sender)?;
+        if msg.C.len() != self.threshold() {
+            return Err(DkgError::BadPolyCommitments(vec![msg.sender]));
+        }
+        if let Some(commitment) = self.pedersen_commitments.get(&msg.sender) {
+            if *commitment != msg.C {
+                return Err(DkgError::DuplicateMessage(msg.sender));
+            }
+        }
+
+        self.pedersen_commitments.insert(msg.sender, msg.C.clone());
+        Ok(())
+    }
+
+    pub fn receive_commitment(&mut self, msg: &DkgCommitment) -> Result<(), DkgError> {
+        let round = match self.scheme {
+            DkgScheme::Feldman => DkgState::Commitments,
+            DkgScheme::Pedersen => DkgState::Extraction,
+        };
+        if self.scheme == DkgScheme::Pedersen {
+            self.rounds.check_round(round, "commitment")?;
+            self.rounds.check_sender(msg.sender)?;
+            // the revealed polynomial must be the one the sender's shares were dealt from
+            let dealt = self
+                .dealer_points(msg.sender)
+                .iter()
+                .all(|(key_id, share)| {
+                    let id = Scalar::from((key_id + 1) as u32);
+                    compute::check_share(&id, share, &msg.commitment.A)
+                });
+            if !dealt {
+                return Err(DkgError::BadShares(vec![msg.sender]));
+            }
+        }
+
+        let threshold = self.threshold();
+        let context = &self.context;
+        self.rounds
+            .receive_commitment(round, msg.sender, &msg.commitment, |_| {
+                check_commitment(msg, threshold, context)
+            })
+    }
+
+    // Once every commitment is in, the shares of f and the blinding polynomial for the owners
+    // of the other key_ids
+    pub fn shares(
+        &mut self,
+        f: &Polynomial<Scalar>,
+        blinding: Option<&Polynomial<Scalar>>,
+        num_keys: usize,
+    ) -> Result<Vec<DkgShare>, DkgError> {
+        // in a Pedersen Dkg the commitments so far are the PedersenCommitments
+        if self.scheme == DkgScheme::Pedersen && self.state() == DkgState::Commitments {
+            let missing = self.missing();
+            if !missing.is_empty() {
+                return Err(DkgError::MissingPolyCommitments(missing));
+            }
+            self.rounds.state = DkgState::Shares;
+        }
+        self.rounds.start_shares()?;
+        Ok(self.rounds.deal(self.id, f, blinding, num_keys))
+    }
+
+    pub fn receive_share(&mut self, msg: &DkgShare) -> Result<(), DkgError> {
+        if self.scheme == DkgScheme::Feldman {
+            return self.rounds.receive_feldman_share(msg);
+        }
+        let pedersen_commitments = &self.pedersen_commitments;
+        self.rounds.receive_share(msg, |_, id| match msg.blinding {
+            Some(blinding) => Ok(compute::check_pedersen_share(
+                id,
+                &msg.share,
+                &blinding,
+                &pedersen_commitments[&msg.sender],
+            )),
+            None => Err(DkgError::WrongScheme(msg.sender)),
+        })?;
+
+        // kept in case the sender's polynomial has to be rebuilt
+        if let Some(blinding) = msg.blinding {
+            self.blindings
+                .entry(msg.key_id)
+                .or_default()
+                .insert(msg.sender, blinding);
+        }
+        Ok(())
+    }
+
+    // The shares this party got from dealers whose commitments are missing or were refused,
+    // for every other party to rebuild their polynomials.  This party's own commitment is never
+    // missing, so it is skipped
+    pub fn recovery_shares(&self, dealers: &[usize]) -> Result<Vec<RecoveryShare>, DkgError> {
+        self.rounds
+            .check_round(DkgState::Extraction, "recovery shares")?;
+        let mut recovery_shares = Vec::new();
+        for dealer in dealers.iter().filter(|i| **i != self.id) {
+            self.rounds.check_sender(*dealer)?;
+            for (key_id, shares) in &self.rounds.shares {
+                recovery_shares.push(RecoveryShare {
+                    sender: self.id,
+                    dealer: *dealer,
+                    key_id: *key_id,
+                    share: shares[dealer],
+                    blinding: self.blindings[key_id][dealer],
+                });
+            }
+        }
+        Ok(recovery_shares)
+    }
+
+    // A recovery share which was already received can be received again.  One which doesn't
+    // match the dealer's commitment proves the commitment bad, so it is dropped and the
+    // dealer's polynomial is rebuilt instead
+    #[allow(non_snake_case)]
+    pub fn receive_recovery_share(&mut self, msg: &RecoveryShare) -> Result<(), DkgError> {
+        self.rounds
+            .check_round(DkgState::Extraction, "recovery share")?;
+        self.rounds.check_sender(msg.sender)?;
+        self.rounds.check_sender(msg.dealer)?;
+        let id = Scalar::from((msg.key_id + 1) as u32);
+        let C = &self.pedersen_commitments[&msg.dealer];
+        if !compute::check_pedersen_share(&id, &msg.share, &msg.blinding, C) {
+            return Err(DkgError::BadShares(vec![msg.sender]));
+        }
+
+        let commitments = &mut self.rounds.commitments;
+        if commitments
+            .get(&msg.dealer)
+            .is_some_and(|c| !compute::check_share(&id, &msg.share, &c.A))
+        {
+            commitments.remove(&msg.dealer);
+        }
+        self.recovered
+            .entry(msg.dealer)
+            .or_default()
+            .insert(msg.key_id, msg.share);
+        Ok(())
+    }
+
+    // Rebuild dealer's poly commitment from threshold points on its polynomial.  Its secret is
+    // public now, so anyone can make the proof of knowledge
+    #[allow(non_snake_case)]
+    fn rebuild_commitment(&self, dealer: usize) -> PolyCommitment {
+        let points: Vec<(usize, Scalar)> = self
+            .dealer_points(dealer)
+            .into_iter()
+            .take(self.threshold())
+            .collect();
+        let f = compute::interpolate(&points);
+        let id = Scalar::from((dealer + 1) as u32);
+
+        PolyCommitment {
+            id: ID::from_public(&id, &f[0], &self.context),
+            A: f.iter().map(|a| a * G).collect(),
+        }
+    }
+
+    // the poly commitments to finish with, once every message is in
+    #[allow(non_snake_case)]
+    pub fn check_finish(&self) -> Result<Vec<PolyCommitment>, DkgError> {
+        match (self.scheme, self.rounds.state) {
+            (DkgScheme::Feldman, DkgState::Shares)
+            | (DkgScheme::Pedersen, DkgState::Extraction) => {}
+            _ => return Err(DkgError::OutOfOrder("finish", self.state().name())),
+        }
+        let missing = self.missing();
+        if !missing.is_empty() {
+            return Err(match self.rounds.state {
+                DkgState::Shares => DkgError::MissingShares(missing),
+                _ => DkgError::MissingPolyCommitments(missing),
+            });
+        }
+
+        Ok(self
+            .rounds
+            .senders
+            .iter()
+            .map(|i| match self.rounds.commitments.get(i) {
+                Some(commitment) => commitment.clone(),
+                None => self.rebuild_commitment(*i),
+            })
+            .collect())
+    }
+
+    pub fn done(&mut self) {
+        self.rounds.done();
+    }
+}
+
+// Add the refresh commitments to the poly commitments.  Only the sum of the poly commitments
+// is ever used, so the refresh all goes into the first one, whose A[0] and proof of knowledge
+// are unchanged.  This works whether the keys came from a Dkg or a Reshare
+#[allow(non_snake_case)]
+pub fn refresh_poly_commitments(
+    A: &[PolyCommitment],
+    refresh: &BTreeMap<usize, Vec<Point>>,
+) -> Vec<PolyCommitment> {
+    let mut A = A.to_vec();
+    for R in refresh.values() {
+        A[0].A = zip(&A[0].A, R).map(|(a, r)| *a + *r).collect();
+    }
+    A
+}
+
+// A commitment to a fresh polynomial whose constant term is secret, and a share of it for each
+// of key_ids 0..num_keys.  The commitment has a proof of knowledge of the secret like a Dkg
+// commitment.  This is an old party's side of a Reshare, and a trusted dealer's
+#[allow(non_snake_case)]
+pub fn deal<RNG: RngCore + CryptoRng>(
+    sender: usize,
+    secret: &Scalar,
+    num_keys: usize,
+    threshold: usize,
+    ctx: &[u8],
+    rng: &mut RNG,
+) -> (DkgCommitment, Vec<DkgShare>) {
+    let mut params = VSS::random_poly(threshold - 1, rng).data().to_vec();
+    params[0] = *secret;
+    let f = Polynomial::new(params);
+
+    let id = Scalar::from((sender + 1) as u32);
+    let commitment = DkgCommitment {
+        sender,
+        commitment: PolyCommitment {
+            id: ID::new(&id, secret, ctx, rng),
+            A: f.data().iter().map(|a| a * G).collect(),
+        },
+    };
+    let shares = (0..num_keys)
+        .map(|key_id| DkgShare {
+            sender,
+            key_id,
+            share: f.eval(Scalar::from((key_id + 1) as u32)),
+            blinding: None,
+        })
+        .collect();
+
+    (commitment, shares)
+}
+
+// A trusted dealer splits secret, or a random one, into shares for key_ids 0..num_keys.  The
+// dealer is sender 0.  Unlike a Dkg the dealer knows the whole secret, so it has to be trusted
+// and should forget the secret once the shares are sent
+pub fn trusted_deal<RNG: RngCore + CryptoRng>(
+    secret: Option<Scalar>,
+    num_keys: usize,
+    threshold: usize,
+    ctx: &[u8],
+    rng: &mut RNG,
+) -> (DkgCommitment, Vec<DkgShare>) {
+    let secret = secret.unwrap_or_else(|| Scalar::random(rng));
+    deal(0, &secret, num_keys, threshold, ctx, rng)
+}
+
+// A recipient checks the trusted dealer's commitment and its share for key_id exactly as it
+// would in a Dkg, and gets the share back
+pub fn check_dealt_share(
+    commitment: &DkgCommitment,
+    share: &DkgShare,
+    key_id: usize,
+    ctx: &[u8],
+) -> Result<Scalar, DkgError> {
+    if commitment.sender != 0 {
+        return Err(DkgError::UnknownParty(commitment.sender));
+    }
+    if share.sender != 0 {
+        return Err(DkgError::UnknownParty(share.sender));
+    }
+    let threshold = commitment.commitment.A.len();
+    if threshold == 0 || !check_commitment(commitment, threshold, ctx) {
+        return Err(DkgError::BadPolyCommitments(vec![0]));
+    }
+    if share.key_id != key_id {
+        return Err(DkgError::WrongKeyId(share.key_id));
+    }
+    let id = Scalar::from((key_id + 1) as u32);
+    if !compute::check_share(&id, &share.share, &commitment.commitment.A) {
+        return Err(DkgError::BadShares(vec![0]));
+    }
+
+    Ok(share.share)
+}
+
+// the public key of key_id under the group's poly commitments
+#[allow(non_snake_case)]
+pub fn public_key(key_id: usize, A: &[PolyCommitment]) -> Point {
+    let id = Scalar::from((key_id + 1) as u32);
+    A.iter()
+        .fold(Point::zero(), |p, Ai| p + compute::poly(&id, &Ai.A))
+}
+
+// Round one of repairing the lost share for key_id, sent privately from one helper to
+// another.  The sender's Lagrange-weighted share is split into one random part per helper,
+// so no helper learns anything about it
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct RepairShare {
+    pub sender: usize,
+    pub recipient: usize,
+    pub key_id: usize,
+    pub share: Scalar,
+}
+
+// Round two of a repair, sent privately from a helper to the owner of key_id.  The sums from
+// every helper add up to the lost share
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct RepairSum {
+    pub sender: usize,
+    pub key_id: usize,
+    pub share: Scalar,
+}
+
+// split a helper's contribution to the share for key_id into random parts which sum to it
+pub fn split_repair<RNG: RngCore + CryptoRng>(
+    sender: usize,
+    key_id: usize,
+    contribution: &Scalar,
+    helpers: &[usize],
+    rng: &mut RNG,
+) -> Vec<RepairShare> {
+    let mut remainder = *contribution;
+    let mut parts = Vec::new();
+    for (i, recipient) in helpers.iter().enumerate() {
+        let share = if i + 1 == helpers.len() {
+            remainder
+        } else {
+            Scalar::random(rng)
+        };
+        remainder -= share;
+        parts.push(RepairShare {
+            sender,
+            recipient: *recipient,
+            key_id,
+            share,
+        });
+    }
+    parts
+}
+
+// Round two of a repair: a helper adds up the parts it was sent for key_id, which must be
+// exactly one from each helper
+pub fn repair_sum(
+    helper: usize,
+    helpers: &[usize],
+    key_id: usize,
+    parts: &[RepairShare],
+) -> Result<RepairSum, DkgError> {
+    let mut received = BTreeMap::new();
+    for part in parts {
+        if part.key_id != key_id {
+            return Err(DkgError::WrongKeyId(part.key_id));
+        }
+        if part.recipient != helper || !helpers.contains(&part.sender) {
+            return Err(DkgError::UnknownParty(part.sender));
+        }
+        if received.insert(part.sender, part.share).is_some() {
+            return Err(DkgError::DuplicateMessage(part.sender));
+        }
+    }
+    let missing: Vec<usize> = helpers
+        .iter()
+        .filter(|i| !received.contains_key(i))
+        .cloned()
+        .collect();
+    if !missing.is_empty() {
+        return Err(DkgError::MissingShares(missing));
+    }
+
+    Ok(RepairSum {
+        sender: helper,
+        key_id,
+        share: received.values().fold(Scalar::zero(), |s, part| s + part),
+    })
+}
+
+// The dealers of a Reshare, each with the old key_ids it holds.  A repeated dealer or key_id
+// would count the same old key twice, and fewer than the old threshold of key_ids don't add up
+// to the group secret
+pub fn check_dealers(
+    dealers: &[(usize, Vec<usize>)],
+    old_threshold: usize,
+) -> Result<(), DkgError> {
+    let mut ids = HashSet::new();
+    let mut key_ids = HashSet::new();
+    for (id, dealer_key_ids) in dealers {
+        if !ids.insert(*id) || !dealer_key_ids.iter().all(|k| key_ids.insert(*k)) {
+            return Err(DkgError::DuplicateMessage(*id));
+        }
+    }
+    if key_ids.len() < old_threshold {
+        return Err(DkgError::BelowThreshold(old_threshold, key_ids.len()));
+    }
+    Ok(())
+}
+
+// With no helpers the sum is zero, and with no poly commitments so is the public key it is
+// checked against, so neither can be allowed
+#[allow(non_snake_case)]
+pub fn check_repair(helpers: &[usize], A: &[PolyCommitment]) -> Result<(), DkgError> {
+    if helpers.is_empty() {
+        return Err(DkgError::NoHelpers);
+    }
+    if A.is_empty() || A[0].A.is_empty() {
+        return Err(DkgError::NoPolyCommitments);
+    }
+    Ok(())
+}
+
+// The owner of key_id adds up the helpers' sums, which must be exactly one from each helper,
+// and checks the result against the group's poly commitments
+#[allow(non_snake_case)]
+pub fn repair_key(
+    key_id: usize,
+    helpers: &[usize],
+    sums: &[RepairSum],
+    A: &[PolyCommitment],
+) -> Result<Scalar, DkgError> {
+    check_repair(helpers, A)?;
+    let mut received = BTreeMap::new();
+    for sum in sums.iter().filter(|s| s.key_id == key_id) {
+        if !helpers.contains(&sum.sender) {
+            return Err(DkgError::UnknownParty(sum.sender));
+        }
+        if received.insert(sum.sender, sum.share).is_some() {
+            return Err(DkgError::DuplicateMessage(sum.sender));
+        }
+    }
+    let missing: Vec<usize> = helpers
+        .iter()
+        .filter(|i| !received.contains_key(i))
+        .cloned()
+        .collect();
+    if !missing.is_empty() {
+        return Err(DkgError::MissingShares(missing));
+    }
+
+    // too few helpers, or a bad one, give a share which doesn't match
+    let private_key = received.values().fold(Scalar::zero(), |s, sum| s + sum);
+    if private_key * G != public_key(key_id, A) {
+        return Err(DkgError::BadShares(helpers.to_vec()));
+    }
+
+    Ok(private_key)
+}
+
+// A complaint is broadcast by a party which received a missing or bad share.
+// In v1 the key_id is the accuser's party id.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct Complaint {
+    pub accuser: usize,
+    pub accused: usize,
+    pub key_id: usize,
+}
+
+// The accused answers a complaint by publicly revealing the disputed share
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct Justification {
+    pub accused: usize,
+    pub key_id: usize,
+    pub share: Scalar,
+}
+
+// A share encrypted to the owner of key_id, which is safe to broadcast.
+// In v1 the key_id is the recipient's party id.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct EncryptedShare {
+    pub sender: usize,
+    pub key_id: usize,
+    pub ciphertext: Vec<u8>,
+}
+
+impl EncryptedShare {
+    // encrypt using ECDH between the sender's private key and the recipient's public key
+    pub fn new<RNG: RngCore + CryptoRng>(
+        sender: usize,
+        key_id: usize,
+        share: &Scalar,
+        private_key: &Scalar,
+        public_key: &Point,
+        rng: &mut RNG,
+    ) -> Option<Self> {
+        let key = make_shared_secret(private_key, public_key);
+        let aad = Self::aad(sender, key_id);
+        let ciphertext = encrypt(&key, share.as_bytes(), &aad, rng).ok()?;
+
+        Some(Self {
+            sender,
+            key_id,
+            ciphertext,
+        })
+    }
+
+    // decrypt using ECDH between the recipient's private key and the sender's public key
+    pub fn decrypt(&self, private_key: &Scalar, public_key: &Point) -> Option<Scalar> {
+        let key = make_shared_secret(private_key, public_key);
+        let aad = Self::aad(self.sender, self.key_id);
+        let plaintext = decrypt(&key, &self.ciphertext, &aad).ok()?;
+
+        let bytes: [u8; 32] = plaintext.try_into().ok()?;
+        Some(Scalar::from(bytes))
+    }
+
+    // bind the ciphertext to its sender and key_id so it can't be replayed to another slot
+    fn aad(sender: usize, key_id: usize) -> Vec<u8> {
+        let mut aad = Vec::new();
+        aad.extend((sender as u64).to_be_bytes());
+        aad.extend((key_id as u64).to_be_bytes());
+        aad
+    }
+}
+
+// Returns the set of parties which remain qualified after the complaint round.
+// A complaint disqualifies the accused unless it revealed a share which passes check_share,
+// in which case the accuser made a false complaint and is disqualified instead.
+// A complaint about a key_id the accuser doesn't own is never justified, since that would
+// reveal someone else's share, so it disqualifies the accuser.
+pub fn resolve_complaints<O, F>(
+    num_parties: usize,
+    complaints: &[Complaint],
+    justifications: &[Justification],
+    owns_key: O,
+    check_share: F,
+) -> HashSet<usize>
+where
+    O: Fn(&Complaint) -> bool,
+    F: Fn(&Justification) -> bool,
+{
+    let mut qualified: HashSet<usize> = (0..num_parties).collect();
+
+    for complaint in complaints {
+        if !owns_key(complaint) {
+            qualified.remove(&complaint.accuser);
+            continue;
+        }
+        let justified = justifications
+            .iter()
+            .filter(|j| j.accused == complaint.accused && j.key_id == complaint.key_id)
+            .any(&check_share);
+
+        if justified {
+            qualified.remove(&complaint.accuser);
+        } else {
+            qualified.remove(&complaint.accused);
+        }
+    }
+
+    qualified
+}
@@ -0,0 +1,146 @@
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum DkgError {
+    #[error("missing shares from {0:?}")]
+    MissingShares(Vec<usize>),
+    #[error("bad shares from {0:?}")]
+    BadShares(Vec<usize>),
+    #[error("bad proof of knowledge in poly commitments from {0:?}")]
+    BadPolyCommitments(Vec<usize>),
+    #[error("expected {0} poly commitments but got {1}")]
+    BadPolyCommitmentLen(usize, usize),
+    #[error("missing public keys for {0:?}")]
+    MissingPublicKeys(Vec<usize>),
+    #[error("failed to encrypt share for {0}")]
+    EncryptionFailure(usize),
+    #[error("failed to decrypt shares from {0:?}")]
+    BadEncryptedShares(Vec<usize>),
+    #[error("missing poly commitments from {0:?}")]
+    MissingPolyCommitments(Vec<usize>),
+    #[error("{0} is out of order in the {1} state")]
+    OutOfOrder(&'static str, &'static str),
+    #[error("already have a different message from {0}")]
+    DuplicateMessage(usize),
+    #[error("party {0} is not in the ceremony")]
+    UnknownParty(usize),
+    #[error("share for key id {0} is for another party")]
+    WrongKeyId(usize),
+    #[error("message from {0} is for the other dkg scheme")]
+    WrongScheme(usize),
+    #[error("the dealers' commitments do not add up to the group key")]
+    BadGroupKey,
+    #[error("threshold is {0} key ids but the dealers hold {1}")]
+    BelowThreshold(usize, usize),
+    #[error("a repair needs at least one helper")]
+    NoHelpers,
+    #[error("a repair needs the group's poly commitments")]
+    NoPolyCommitments,
+}
+
+#[cfg(feature = "reconstruct")]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ReconstructError {
+    #[error("share {0} was given more than once")]
+    DuplicateShare(usize),
+    #[error("no public key for share {0}")]
+    UnknownShare(usize),
+    #[error("shares {0:?} do not match their public keys")]
+    BadShares(Vec<usize>),
+    #[error("the shares do not interpolate to the group key")]
+    BadGroupKey,
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SignerError {
+    #[error("nonce index {0} out of range, only {1} nonces available")]
+    NonceIndexOutOfRange(usize, usize),
+    #[error("missing public nonces from {0:?}")]
+    MissingPublicNonces(Vec<usize>),
+    #[error("no unused nonce for party {0}")]
+    MissingNonce(usize),
+    #[error("public nonce for party {0} does not match the one it generated")]
+    BadPublicNonce(usize),
+    #[error("party {0} was asked to sign with the same nonce more than once")]
+    DuplicateNonce(usize),
+    #[error("party {0} can not sign with key ids {1:?}")]
+    BadKeyIds(usize, Vec<usize>),
+    #[error("the taproot tweak is not less than the curve order")]
+    BadTweak,
+    #[error(transparent)]
+    BadSigningPackage(#[from] SigningPackageError),
+    #[error(transparent)]
+    NoncePool(#[from] NoncePoolError),
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SigningPackageError {
+    #[error("signer {0} has more than one public nonce")]
+    DuplicateSigner(usize),
+    #[error("key {0} is signing more than once")]
+    DuplicateKey(usize),
+    #[error("signers {0:?} do not have both a public nonce and key ids")]
+    MismatchedKeyIds(Vec<usize>),
+    #[error("threshold is {0} keys but only {1} are signing")]
+    BelowThreshold(usize, usize),
+    #[error("identity public nonces from {0:?}")]
+    IdentityNonces(Vec<usize>),
+    #[error("the packages in a batch do not all have the same signers and key ids")]
+    MismatchedBatch,
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum NoncePoolError {
+    #[error("no nonce {1} in batch {0}")]
+    UnknownNonce(u64, usize),
+    #[error("nonce {1} in batch {0} has already been used")]
+    UsedNonce(u64, usize),
+    #[error("a batch must have at least one nonce")]
+    EmptyBatch,
+    #[error("nonce storage failed: {0}")]
+    Storage(String),
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum AggregatorError {
+    #[error("expected {0} poly commitments but got {1}")]
+    BadPolyCommitmentLen(usize, usize),
+    #[error("bad proof of knowledge in poly commitments from {0:?}")]
+    BadPolyCommitments(Vec<usize>),
+    #[error("expected {0} nonces but got {1}")]
+    BadNonceLen(usize, usize),
+    #[error("no unused nonces for parties {0:?}")]
+    OutOfNonces(Vec<usize>),
+    #[error("signing package nonces are not the next unused nonces of {0:?}")]
+    BadPackageNonces(Vec<usize>),
+    #[error("missing signature shares from {0:?}")]
+    MissingPartySigs(Vec<usize>),
+    #[error("bad signature shares from parties {0:?}")]
+    BadPartySigs(Vec<usize>),
+    #[error("aggregated signature failed to verify")]
+    BadGroupSig,
+    #[error("key id {0} is not the next unused one")]
+    BadKeyId(usize),
+    #[error("the taproot tweak is not less than the curve order")]
+    BadTweak,
+    #[error(transparent)]
+    BadSigningPackage(#[from] SigningPackageError),
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SessionError {
+    #[error("message is for another session")]
+    WrongSession,
+    #[error("{0} is out of order in the {1} state")]
+    OutOfOrder(&'static str, &'static str),
+    #[error("already have a message from {0}")]
+    DuplicateSigner(usize),
+    #[error("{0} is not signing in this session")]
+    UnknownSigner(usize),
+    #[error(transparent)]
+    BadSigningPackage(#[from] SigningPackageError),
+    #[error(transparent)]
+    Signer(#[from] SignerError),
+    #[error(transparent)]
+    Aggregator(#[from] AggregatorError),
+}
@@ -1,6 +1,15 @@
+pub mod bip340;
 pub mod common;
 pub mod compute;
+pub mod dkg;
+pub mod errors;
+pub mod nonce_pool;
+#[cfg(feature = "reconstruct")]
+pub mod reconstruct;
+pub mod rfc9591;
 pub mod schnorr;
+pub mod session;
+pub mod taproot;
 pub mod traits;
 pub mod util;
 pub mod v1;
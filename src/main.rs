@@ -2,32 +2,50 @@ use rand_core::{CryptoRng, OsRng, RngCore};
 use std::{env, time};
 
 use frost::{
-    common::{PolyCommitment, PublicNonce, SignatureShare},
+    common::{PolyCommitment, PublicNonce, SignatureShare, SigningPackage},
+    dkg::EncryptedShare,
     v1::{Party, SignatureAggregator},
 };
 use hashbrown::HashMap;
+use secp256k1_math::{
+    point::{Point, G},
+    scalar::Scalar,
+};
 
 // This will eventually need to be replaced by rpcs
 #[allow(non_snake_case)]
-fn distribute(parties: &mut Vec<Party>, A: &[PolyCommitment]) -> u128 {
-    // each party broadcasts their commitments
-    // these hashmaps will need to be serialized in tuples w/ the value encrypted
-    let mut broadcast_shares = Vec::new();
+fn distribute<RNG: RngCore + CryptoRng>(
+    parties: &mut Vec<Party>,
+    A: &[PolyCommitment],
+    network_private_keys: &[Scalar],
+    network_public_keys: &HashMap<usize, Point>,
+    rng: &mut RNG,
+) -> u128 {
+    // each party broadcasts their shares encrypted to each recipient
+    let mut broadcast_shares: Vec<EncryptedShare> = Vec::new();
     for party in parties.iter() {
-        broadcast_shares.push(party.get_shares());
+        broadcast_shares.extend(
+            party
+                .get_encrypted_shares(&network_private_keys[party.id], network_public_keys, rng)
+                .expect("failed to encrypt shares"),
+        );
     }
 
     let mut total_compute_secret_time = 0;
 
-    // each party collects its shares from the broadcasts
-    // maybe this should collect into a hashmap first?
+    // each party decrypts its shares from the broadcasts
     for i in 0..parties.len() {
-        let mut h = HashMap::new();
-        for j in 0..parties.len() {
-            h.insert(j, broadcast_shares[j][&i]);
-        }
+        let h = parties[i]
+            .decrypt_shares(
+                &network_private_keys[i],
+                network_public_keys,
+                &broadcast_shares,
+            )
+            .expect("failed to decrypt shares");
         let compute_secret_start = time::Instant::now();
-        parties[i].compute_secret(h, A);
+        parties[i]
+            .compute_secret(h, A)
+            .expect("failed to compute secret");
         let compute_secret_time = compute_secret_start.elapsed();
         total_compute_secret_time += compute_secret_time.as_micros();
     }
@@ -52,19 +70,14 @@ fn select_parties<RNG: RngCore + CryptoRng>(N: usize, T: usize, rng: &mut RNG) -
 }
 
 // There might be a slick one-liner for this?
-fn collect_signatures(
-    parties: &[Party],
-    signers: &[usize],
-    nonces: &[PublicNonce],
-    msg: &[u8],
-) -> Vec<SignatureShare> {
+fn collect_signatures(parties: &mut [Party], package: &SigningPackage) -> Vec<SignatureShare> {
     let mut sigs = Vec::new();
-    for i in 0..signers.len() {
-        let party = &parties[signers[i]];
+    for id in package.signers() {
+        let party = &mut parties[id];
         sigs.push(SignatureShare {
             id: party.id,
-            z_i: party.sign(msg, signers, nonces),
-            public_key: party.public_key,
+            z_i: party.sign(package).unwrap(),
+            key_ids: vec![party.id],
         });
     }
     sigs
@@ -93,7 +106,19 @@ fn main() {
         .iter()
         .map(|p| p.get_poly_commitment(&mut rng))
         .collect();
-    let total_compute_secret_time = distribute(&mut parties, &A);
+    let network_private_keys: Vec<Scalar> = (0..N).map(|_| Scalar::random(&mut rng)).collect();
+    let network_public_keys: HashMap<usize, Point> = network_private_keys
+        .iter()
+        .enumerate()
+        .map(|(i, key)| (i, key * G))
+        .collect();
+    let total_compute_secret_time = distribute(
+        &mut parties,
+        &A,
+        &network_private_keys,
+        &network_public_keys,
+        &mut rng,
+    );
 
     let mut total_sig_time = 0;
     let mut total_party_sig_time = 0;
@@ -101,25 +126,30 @@ fn main() {
         let msg = "It was many and many a year ago".as_bytes();
         let signers = select_parties(N, T, &mut rng);
 
-        let nonces: Vec<PublicNonce> = signers
+        let nonces: Vec<(usize, PublicNonce)> = signers
             .iter()
-            .map(|i| parties[*i].gen_nonce(&mut rng))
+            .map(|i| (*i, parties[*i].gen_nonce(&mut rng)))
             .collect();
+        let package =
+            SigningPackage::new(msg, &nonces, T).expect("failed to create signing package");
 
-        let mut sig_agg = SignatureAggregator::new(N, T, A.clone());
+        let mut sig_agg = SignatureAggregator::new(N, T, A.clone())
+            .expect("failed to create signature aggregator");
 
         let party_sig_start = time::Instant::now();
-        let sig_shares = collect_signatures(&parties, &signers, &nonces, msg);
+        let sig_shares = collect_signatures(&mut parties, &package);
         let party_sig_time = party_sig_start.elapsed();
         let sig_start = time::Instant::now();
-        let sig = sig_agg.sign(msg, &nonces, &sig_shares);
+        let sig = sig_agg
+            .sign(&package, &sig_shares)
+            .expect("failed to aggregate signature");
         let sig_time = sig_start.elapsed();
 
         total_party_sig_time += party_sig_time.as_micros();
         total_sig_time += sig_time.as_micros();
 
         println!("Signature (R,z) = \n({},{})", sig.R, sig.z);
-        assert!(sig.verify(&sig_agg.key, msg));
+        assert!(sig.verify(&sig_agg.key, msg, &sig_agg.context));
     }
     println!("With {} parties and {} signers:", N, T);
     println!(
@@ -0,0 +1,364 @@
+use hashbrown::HashMap;
+use rand_core::{CryptoRng, RngCore};
+use secp256k1_math::scalar::Scalar;
+use std::fs::{self, File};
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::common::{Nonce, PublicNonce};
+use crate::errors::NoncePoolError;
+
+// A nonce is identified by the batch it was generated in and its index in that batch, which
+// is also its index in the batch of public nonces that was handed out
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct NonceId {
+    pub batch: u64,
+    pub index: usize,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum NonceStatus {
+    Unused(Nonce),
+    Used,
+}
+
+// Storage for a NoncePool.  Batch ids must never be handed out twice, and mark_used must be
+// durable by the time it returns, since the pool releases the secret nonce right after
+pub trait NonceStore {
+    // store a batch of unused nonces, returning its new batch id
+    fn insert_batch(&mut self, nonces: &[Nonce]) -> Result<u64, NoncePoolError>;
+
+    // None if the nonce was never stored
+    fn get(&self, id: &NonceId) -> Result<Option<NonceStatus>, NoncePoolError>;
+
+    fn mark_used(&mut self, id: &NonceId) -> Result<(), NoncePoolError>;
+}
+
+// Keeps nonces for as long as the process runs, so it only protects against restoring
+// in-memory state such as a Party from an old snapshot
+#[derive(Debug, Default)]
+pub struct MemoryNonceStore {
+    batches: HashMap<u64, Vec<Option<Nonce>>>,
+    next_batch: u64,
+}
+
+impl MemoryNonceStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl NonceStore for MemoryNonceStore {
+    fn insert_batch(&mut self, nonces: &[Nonce]) -> Result<u64, NoncePoolError> {
+        if nonces.is_empty() {
+            return Err(NoncePoolError::EmptyBatch);
+        }
+        let batch = self.next_batch;
+        self.next_batch += 1;
+        self.batches
+            .insert(batch, nonces.iter().cloned().map(Some).collect());
+        Ok(batch)
+    }
+
+    fn get(&self, id: &NonceId) -> Result<Option<NonceStatus>, NoncePoolError> {
+        Ok(self
+            .batches
+            .get(&id.batch)
+            .and_then(|b| b.get(id.index))
+            .map(|n| match n {
+                Some(nonce) => NonceStatus::Unused(nonce.clone()),
+                None => NonceStatus::Used,
+            }))
+    }
+
+    fn mark_used(&mut self, id: &NonceId) -> Result<(), NoncePoolError> {
+        if let Some(n) = self
+            .batches
+            .get_mut(&id.batch)
+            .and_then(|b| b.get_mut(id.index))
+        {
+            *n = None;
+        }
+        Ok(())
+    }
+}
+
+// One file per nonce in a directory: <batch>.<index>.nonce holds d || e until the nonce is
+// used, when a <batch>.<index>.used marker is synced to disk and then the secret is removed.
+// The next batch id is kept in a next_batch file, which is advanced before a batch is
+// written, so a batch id is never reused even if the files of a batch are removed
+#[derive(Debug)]
+pub struct FileNonceStore {
+    dir: PathBuf,
+}
+
+impl FileNonceStore {
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, NoncePoolError> {
+        fs::create_dir_all(&dir).map_err(storage_error)?;
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    fn path(&self, id: &NonceId, ext: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}.{}", id.batch, id.index, ext))
+    }
+
+    // make renames and new files in the directory durable
+    fn sync_dir(&self) -> Result<(), NoncePoolError> {
+        File::open(&self.dir)
+            .and_then(|d| d.sync_all())
+            .map_err(storage_error)
+    }
+
+    fn counter_path(&self) -> PathBuf {
+        self.dir.join("next_batch")
+    }
+
+    // stores from before the counter was kept fall back to the batch ids of their files
+    fn next_batch(&self) -> Result<u64, NoncePoolError> {
+        let mut bytes = [0u8; 8];
+        match File::open(self.counter_path()) {
+            Ok(mut file) => {
+                file.read_exact(&mut bytes).map_err(storage_error)?;
+                return Ok(u64::from_be_bytes(bytes));
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(storage_error(e)),
+        }
+
+        let mut next_batch = 0;
+        for entry in fs::read_dir(&self.dir).map_err(storage_error)? {
+            let name = entry.map_err(storage_error)?.file_name();
+            let batch = name
+                .to_str()
+                .and_then(|n| n.split('.').next())
+                .and_then(|b| b.parse::<u64>().ok());
+            if let Some(batch) = batch {
+                next_batch = next_batch.max(batch + 1);
+            }
+        }
+        Ok(next_batch)
+    }
+
+    fn set_next_batch(&self, next_batch: u64) -> Result<(), NoncePoolError> {
+        let tmp = self.dir.join("next_batch.tmp");
+        let mut file = File::create(&tmp).map_err(storage_error)?;
+        file.write_all(&next_batch.to_be_bytes())
+            .and_then(|_| file.sync_all())
+            .map_err(storage_error)?;
+        fs::rename(&tmp, self.counter_path()).map_err(storage_error)?;
+        self.sync_dir()
+    }
+}
+
+fn storage_error(e: std::io::Error) -> NoncePoolError {
+    NoncePoolError::Storage(e.to_string())
+}
+
+impl NonceStore for FileNonceStore {
+    fn insert_batch(&mut self, nonces: &[Nonce]) -> Result<u64, NoncePoolError> {
+        if nonces.is_empty() {
+            return Err(NoncePoolError::EmptyBatch);
+        }
+        let batch = self.next_batch()?;
+        self.set_next_batch(batch + 1)?;
+        for (index, nonce) in nonces.iter().enumerate() {
+            let id = NonceId { batch, index };
+            let tmp = self.path(&id, "tmp");
+
+            let mut file = File::create(&tmp).map_err(storage_error)?;
+            file.write_all(nonce.d.as_bytes())
+                .and_then(|_| file.write_all(nonce.e.as_bytes()))
+                .and_then(|_| file.sync_all())
+                .map_err(storage_error)?;
+            fs::rename(&tmp, self.path(&id, "nonce")).map_err(storage_error)?;
+        }
+        self.sync_dir()?;
+
+        Ok(batch)
+    }
+
+    fn get(&self, id: &NonceId) -> Result<Option<NonceStatus>, NoncePoolError> {
+        if self.path(id, "used").exists() {
+            return Ok(Some(NonceStatus::Used));
+        }
+
+        let mut file = match File::open(self.path(id, "nonce")) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(storage_error(e)),
+        };
+        let mut bytes = [0u8; 64];
+        file.read_exact(&mut bytes).map_err(storage_error)?;
+
+        let mut d = [0u8; 32];
+        let mut e = [0u8; 32];
+        d.copy_from_slice(&bytes[..32]);
+        e.copy_from_slice(&bytes[32..]);
+
+        Ok(Some(NonceStatus::Unused(Nonce {
+            d: Scalar::from(d),
+            e: Scalar::from(e),
+        })))
+    }
+
+    fn mark_used(&mut self, id: &NonceId) -> Result<(), NoncePoolError> {
+        File::create(self.path(id, "used"))
+            .and_then(|f| f.sync_all())
+            .map_err(storage_error)?;
+        self.sync_dir()?;
+
+        match fs::remove_file(self.path(id, "nonce")) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(storage_error(e)),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Pregenerated nonces which can each be used once, even across restarts of the signer
+pub struct NoncePool<S: NonceStore> {
+    store: S,
+}
+
+impl<S: NonceStore> NoncePool<S> {
+    pub fn new(store: S) -> Self {
+        Self { store }
+    }
+
+    // a batch of hedged nonces, see Nonce::random.  The public nonces are in index order
+    pub fn generate<RNG: RngCore + CryptoRng>(
+        &mut self,
+        num_nonces: u32,
+        secret: &Scalar,
+        ctx: &[u8],
+        rng: &mut RNG,
+    ) -> Result<(u64, Vec<PublicNonce>), NoncePoolError> {
+        let nonces: Vec<Nonce> = (0..num_nonces)
+            .map(|_| Nonce::random(secret, ctx, rng))
+            .collect();
+        let batch = self.store.insert_batch(&nonces)?;
+
+        Ok((batch, nonces.iter().map(PublicNonce::from).collect()))
+    }
+
+    fn unused(&self, id: &NonceId) -> Result<Nonce, NoncePoolError> {
+        match self.store.get(id)? {
+            Some(NonceStatus::Unused(nonce)) => Ok(nonce),
+            Some(NonceStatus::Used) => Err(NoncePoolError::UsedNonce(id.batch, id.index)),
+            None => Err(NoncePoolError::UnknownNonce(id.batch, id.index)),
+        }
+    }
+
+    pub fn public_nonce(&self, id: &NonceId) -> Result<PublicNonce, NoncePoolError> {
+        Ok(PublicNonce::from(&self.unused(id)?))
+    }
+
+    // The secret nonce is only returned once it is durably marked used, so a crash after
+    // this can lose the nonce but never use it twice
+    pub fn take(&mut self, id: &NonceId) -> Result<Nonce, NoncePoolError> {
+        let nonce = self.unused(id)?;
+        self.store.mark_used(id)?;
+        Ok(nonce)
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand_core::{OsRng, RngCore};
+    use secp256k1_math::scalar::Scalar;
+    use std::fs;
+
+    use crate::errors::NoncePoolError;
+    use crate::nonce_pool::{
+        FileNonceStore, MemoryNonceStore, NonceId, NoncePool, NonceStatus, NonceStore,
+    };
+
+    fn use_once<S: NonceStore>(pool: &mut NoncePool<S>) {
+        let mut rng = OsRng::default();
+        let secret = Scalar::random(&mut rng);
+
+        let (batch, public_nonces) = pool.generate(3, &secret, &[], &mut rng).unwrap();
+        let (next_batch, _) = pool.generate(1, &secret, &[], &mut rng).unwrap();
+        assert_ne!(batch, next_batch);
+
+        let id = NonceId { batch, index: 1 };
+        assert_eq!(pool.public_nonce(&id).unwrap(), public_nonces[1]);
+        pool.take(&id).unwrap();
+        assert_eq!(pool.store().get(&id).unwrap(), Some(NonceStatus::Used));
+        assert_eq!(
+            pool.take(&id).err(),
+            Some(NoncePoolError::UsedNonce(batch, 1))
+        );
+        assert_eq!(
+            pool.public_nonce(&id).err(),
+            Some(NoncePoolError::UsedNonce(batch, 1))
+        );
+        assert_eq!(
+            pool.take(&NonceId { batch, index: 3 }).err(),
+            Some(NoncePoolError::UnknownNonce(batch, 3))
+        );
+
+        // the other nonces in the batch are still there
+        assert!(pool.take(&NonceId { batch, index: 0 }).is_ok());
+    }
+
+    #[test]
+    fn memory_store() {
+        use_once(&mut NoncePool::new(MemoryNonceStore::new()));
+    }
+
+    #[test]
+    fn file_store() {
+        let mut rng = OsRng::default();
+        let dir = std::env::temp_dir().join(format!("frost-nonce-pool-{}", rng.next_u64()));
+        use_once(&mut NoncePool::new(FileNonceStore::open(&dir).unwrap()));
+
+        // reopening the store keeps the used markers and never reuses a batch id
+        let mut pool = NoncePool::new(FileNonceStore::open(&dir).unwrap());
+        assert_eq!(
+            pool.take(&NonceId { batch: 0, index: 1 }).err(),
+            Some(NoncePoolError::UsedNonce(0, 1))
+        );
+        assert!(pool.take(&NonceId { batch: 0, index: 2 }).is_ok());
+        let (batch, _) = pool
+            .generate(1, &Scalar::random(&mut rng), &[], &mut rng)
+            .unwrap();
+        assert_eq!(batch, 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn file_store_restart() {
+        let mut rng = OsRng::default();
+        let secret = Scalar::random(&mut rng);
+        let dir = std::env::temp_dir().join(format!("frost-nonce-pool-{}", rng.next_u64()));
+
+        let mut pool = NoncePool::new(FileNonceStore::open(&dir).unwrap());
+        assert_eq!(
+            pool.generate(0, &secret, &[], &mut rng).err(),
+            Some(NoncePoolError::EmptyBatch)
+        );
+        let (batch, _) = pool.generate(2, &secret, &[], &mut rng).unwrap();
+        assert_eq!(batch, 0);
+
+        // removing every file of the batch must not let its id be handed out again
+        for index in 0..2 {
+            fs::remove_file(dir.join(format!("{}.{}.nonce", batch, index))).unwrap();
+        }
+        let mut pool = NoncePool::new(FileNonceStore::open(&dir).unwrap());
+        assert_eq!(
+            pool.generate(0, &secret, &[], &mut rng).err(),
+            Some(NoncePoolError::EmptyBatch)
+        );
+        let (batch, _) = pool.generate(1, &secret, &[], &mut rng).unwrap();
+        assert_eq!(batch, 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
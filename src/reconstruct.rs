@@ -0,0 +1,49 @@
+use num_traits::Zero;
+use secp256k1_math::{
+    point::{Point, G},
+    scalar::Scalar,
+};
+
+use crate::compute;
+use crate::errors::ReconstructError;
+
+use hashbrown::{HashMap, HashSet};
+
+// Interpolate the group secret at zero from at least threshold (id, share) pairs.  Each share
+// is checked against its public key first, and the result against group_key
+pub fn secret(
+    shares: &[(usize, Scalar)],
+    public_keys: &HashMap<usize, Point>,
+    group_key: &Point,
+) -> Result<Scalar, ReconstructError> {
+    let mut ids = Vec::with_capacity(shares.len());
+    let mut seen = HashSet::new();
+    let mut bad_ids = Vec::new();
+    for (id, share) in shares {
+        if !seen.insert(*id) {
+            return Err(ReconstructError::DuplicateShare(*id));
+        }
+        match public_keys.get(id) {
+            Some(public_key) => {
+                if share * G != *public_key {
+                    bad_ids.push(*id);
+                }
+            }
+            None => return Err(ReconstructError::UnknownShare(*id)),
+        }
+        ids.push(*id);
+    }
+    if !bad_ids.is_empty() {
+        bad_ids.sort();
+        return Err(ReconstructError::BadShares(bad_ids));
+    }
+
+    let secret = shares.iter().fold(Scalar::zero(), |s, (id, share)| {
+        s + compute::lambda(id, &ids) * share
+    });
+    if secret * G != *group_key {
+        return Err(ReconstructError::BadGroupKey);
+    }
+
+    Ok(secret)
+}
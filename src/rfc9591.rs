@@ -0,0 +1,401 @@
+use core::iter::zip;
+use num_traits::Zero;
+use rand_core::{CryptoRng, RngCore};
+use secp256k1_math::{
+    point::{Point, G},
+    scalar::Scalar,
+};
+use sha2::{Digest, Sha256};
+
+use crate::common::{Nonce, PublicNonce, Signature};
+use crate::compute;
+use crate::errors::SignerError;
+
+// The FROST(secp256k1, SHA-256) ciphersuite from RFC 9591 section 6.5.  Identifiers are
+// party ids plus one, as everywhere else in this crate, and the commitment lists passed in
+// may be in any order since they are sorted by identifier before being encoded
+pub const CONTEXT_STRING: &[u8] = b"FROST-secp256k1-SHA256-v1";
+
+// expand_message_xmd from RFC 9380 section 5.3.1, with SHA-256
+fn expand_message_xmd(msg: &[u8], dst: &[u8], len: usize) -> Vec<u8> {
+    let ell = len.div_ceil(32);
+    let mut dst_prime = dst.to_vec();
+    dst_prime.push(dst.len() as u8);
+
+    let mut hasher = Sha256::new();
+    hasher.update([0u8; 64]);
+    hasher.update(msg);
+    hasher.update((len as u16).to_be_bytes());
+    hasher.update([0u8]);
+    hasher.update(&dst_prime);
+    let b_0 = hasher.finalize();
+
+    let mut uniform_bytes = Vec::with_capacity(ell * 32);
+    let mut b_i = [0u8; 32];
+    for i in 1..=ell {
+        let mut hasher = Sha256::new();
+        hasher.update(zip(b_0, b_i).map(|(x, y)| x ^ y).collect::<Vec<u8>>());
+        hasher.update([i as u8]);
+        hasher.update(&dst_prime);
+        b_i.copy_from_slice(&hasher.finalize());
+        uniform_bytes.extend(b_i);
+    }
+
+    uniform_bytes.truncate(len);
+    uniform_bytes
+}
+
+// hash_to_field from RFC 9380 with L = 48, reducing the big-endian bytes hi || lo mod n
+fn hash_to_field(msg: &[u8], tag: &[u8]) -> Scalar {
+    let mut dst = CONTEXT_STRING.to_vec();
+    dst.extend(tag);
+    let bytes = expand_message_xmd(msg, &dst, 48);
+
+    let mut hi = [0u8; 32];
+    let mut lo = [0u8; 32];
+    let mut shift = [0u8; 32];
+    hi[16..].copy_from_slice(&bytes[..16]);
+    lo.copy_from_slice(&bytes[16..]);
+    shift[15] = 1;
+
+    let shift = Scalar::from(shift);
+    Scalar::from(hi) * shift * shift + Scalar::from(lo)
+}
+
+fn hash(tag: &[u8], msg: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+
+    hasher.update(CONTEXT_STRING);
+    hasher.update(tag);
+    hasher.update(msg);
+
+    hasher.finalize().into()
+}
+
+pub fn h1(msg: &[u8]) -> Scalar {
+    hash_to_field(msg, b"rho")
+}
+
+pub fn h2(msg: &[u8]) -> Scalar {
+    hash_to_field(msg, b"chal")
+}
+
+pub fn h3(msg: &[u8]) -> Scalar {
+    hash_to_field(msg, b"nonce")
+}
+
+pub fn h4(msg: &[u8]) -> [u8; 32] {
+    hash(b"msg", msg)
+}
+
+pub fn h5(msg: &[u8]) -> [u8; 32] {
+    hash(b"com", msg)
+}
+
+fn identifier(id: usize) -> Scalar {
+    Scalar::from((id + 1) as u32)
+}
+
+// the signers and their nonces, sorted by identifier
+fn sorted<'a>(signers: &[usize], nonces: &'a [PublicNonce]) -> Vec<(usize, &'a PublicNonce)> {
+    let mut list: Vec<(usize, &PublicNonce)> = zip(signers.iter().copied(), nonces).collect();
+    list.sort_by_key(|(id, _)| *id);
+    list
+}
+
+// H3(random_bytes || SerializeScalar(secret))
+pub fn nonce_generate_from(secret: &Scalar, random_bytes: &[u8; 32]) -> Scalar {
+    let mut msg = random_bytes.to_vec();
+    msg.extend(secret.as_bytes());
+    h3(&msg)
+}
+
+pub fn nonce_generate<RNG: RngCore + CryptoRng>(secret: &Scalar, rng: &mut RNG) -> Scalar {
+    let mut random_bytes = [0u8; 32];
+    rng.fill_bytes(&mut random_bytes);
+    nonce_generate_from(secret, &random_bytes)
+}
+
+// round one: d is the hiding nonce and e the binding nonce
+pub fn commit<RNG: RngCore + CryptoRng>(secret: &Scalar, rng: &mut RNG) -> Nonce {
+    Nonce {
+        d: nonce_generate(secret, rng),
+        e: nonce_generate(secret, rng),
+    }
+}
+
+pub fn encode_group_commitment_list(signers: &[usize], nonces: &[PublicNonce]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for (id, nonce) in sorted(signers, nonces) {
+        bytes.extend(identifier(id).as_bytes());
+        bytes.extend(nonce.D.compress().as_bytes());
+        bytes.extend(nonce.E.compress().as_bytes());
+    }
+    bytes
+}
+
+// one binding factor per signer, in the order of signers
+pub fn compute_binding_factors(
+    group_public_key: &Point,
+    signers: &[usize],
+    nonces: &[PublicNonce],
+    msg: &[u8],
+) -> Vec<Scalar> {
+    let mut prefix = group_public_key.compress().as_bytes().to_vec();
+    prefix.extend(h4(msg));
+    prefix.extend(h5(&encode_group_commitment_list(signers, nonces)));
+
+    signers
+        .iter()
+        .map(|id| {
+            let mut rho_input = prefix.clone();
+            rho_input.extend(identifier(*id).as_bytes());
+            h1(&rho_input)
+        })
+        .collect()
+}
+
+#[allow(non_snake_case)]
+pub fn compute_group_commitment(nonces: &[PublicNonce], binding_factors: &[Scalar]) -> Point {
+    zip(nonces, binding_factors).fold(Point::zero(), |R, (nonce, rho)| R + nonce.D + rho * nonce.E)
+}
+
+// H2(SerializeElement(R) || SerializeElement(PK) || msg)
+#[allow(non_snake_case)]
+pub fn compute_challenge(R: &Point, group_public_key: &Point, msg: &[u8]) -> Scalar {
+    let mut challenge_input = R.compress().as_bytes().to_vec();
+    challenge_input.extend(group_public_key.compress().as_bytes());
+    challenge_input.extend(msg);
+    h2(&challenge_input)
+}
+
+// round two: the signature share of signer id, whose secret nonce is nonce.  id must be one
+// of the signers, or it has no binding factor
+#[allow(non_snake_case)]
+pub fn sign(
+    id: usize,
+    private_key: &Scalar,
+    nonce: &Nonce,
+    msg: &[u8],
+    signers: &[usize],
+    nonces: &[PublicNonce],
+    group_public_key: &Point,
+) -> Result<Scalar, SignerError> {
+    let binding_factors = compute_binding_factors(group_public_key, signers, nonces, msg);
+    let R = compute_group_commitment(nonces, &binding_factors);
+    let c = compute_challenge(&R, group_public_key, msg);
+    let rho = zip(signers, binding_factors)
+        .find(|(i, _)| **i == id)
+        .map(|(_, rho)| rho)
+        .ok_or_else(|| SignerError::MissingPublicNonces(vec![id]))?;
+
+    Ok(nonce.d + nonce.e * rho + compute::lambda(&id, signers) * private_key * c)
+}
+
+// the index of each bad share, so all the bad signers can be reported at once
+#[allow(non_snake_case)]
+pub fn verify_signature_shares(
+    msg: &[u8],
+    signers: &[usize],
+    nonces: &[PublicNonce],
+    sig_shares: &[Scalar],
+    public_keys: &[Point],
+    group_public_key: &Point,
+) -> Vec<usize> {
+    let binding_factors = compute_binding_factors(group_public_key, signers, nonces, msg);
+    let R = compute_group_commitment(nonces, &binding_factors);
+    let c = compute_challenge(&R, group_public_key, msg);
+
+    (0..signers.len())
+        .filter(|&i| {
+            let R_i = nonces[i].D + binding_factors[i] * nonces[i].E;
+            let lambda = compute::lambda(&signers[i], signers);
+            sig_shares[i] * G != R_i + lambda * c * public_keys[i]
+        })
+        .collect()
+}
+
+#[allow(non_snake_case)]
+pub fn aggregate(
+    msg: &[u8],
+    signers: &[usize],
+    nonces: &[PublicNonce],
+    sig_shares: &[Scalar],
+    group_public_key: &Point,
+) -> Signature {
+    let binding_factors = compute_binding_factors(group_public_key, signers, nonces, msg);
+
+    Signature {
+        R: compute_group_commitment(nonces, &binding_factors),
+        z: sig_shares.iter().fold(Scalar::zero(), |z, z_i| z + z_i),
+    }
+}
+
+// z * G == R + c * PK
+pub fn verify(sig: &Signature, group_public_key: &Point, msg: &[u8]) -> bool {
+    let c = compute_challenge(&sig.R, group_public_key, msg);
+    sig.z * G == sig.R + c * group_public_key
+}
+
+// SerializeElement(R) || SerializeScalar(z)
+pub fn signature_to_bytes(sig: &Signature) -> [u8; 65] {
+    let mut bytes = [0u8; 65];
+    bytes[..33].copy_from_slice(sig.R.compress().as_bytes());
+    bytes[33..].copy_from_slice(sig.z.as_bytes());
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use secp256k1_math::{point::G, scalar::Scalar};
+
+    use crate::common::{Nonce, PublicNonce};
+    use crate::errors::SignerError;
+    use crate::rfc9591;
+    use crate::util::{decode_point, decode_scalar};
+
+    // FROST(secp256k1, SHA-256) vectors from RFC 9591 appendix E.5, with participants 1 and 3
+    const GROUP_SECRET_KEY: &str =
+        "0d004150d27c3bf2a42f312683d35fac7394b1e9e318249c1bfe7f0795a83114";
+    const GROUP_PUBLIC_KEY: &str =
+        "02f37c34b66ced1fb51c34a90bdae006901f10625cc06c4f64663b0eae87d87b4f";
+    const MESSAGE: &str = "74657374";
+    const SHARE_COEFFICIENT: &str =
+        "fbf85eadae3058ea14f19148bb72b45e4399c0b16028acaf0395c9b03c823579";
+    const SIG: &str = "0205b6d04d3774c8929413e3c76024d54149c372d57aae62574ed74319b5ea14d0c65dde8492a7471437e6c2fe3da49b90d23f642b5c6dbe7e36089f096dd97324";
+
+    // (party id, share, hiding randomness, binding randomness, hiding nonce, binding nonce,
+    //  hiding commitment, binding commitment, binding factor, signature share)
+    const SIGNERS: &[(usize, &str, &str, &str, &str, &str, &str, &str, &str, &str)] = &[
+        (
+            0,
+            "08f89ffe80ac94dcb920c26f3f46140bfc7f95b493f8310f5fc1ea2b01f4254c",
+            "7ea5ed09af19f6ff21040c07ec2d2adbd35b759da5a401d4c99dd26b82391cb2",
+            "47acab018f116020c10cb9b9abdc7ac10aae1b48ca6e36dc15acb6ec9be5cdc5",
+            "841d3a6450d7580b4da83c8e618414d0f024391f2aeb511d7579224420aa81f0",
+            "8d2624f532af631377f33cf44b5ac5f849067cae2eacb88680a31e77c79b5a80",
+            "03c699af97d26bb4d3f05232ec5e1938c12f1e6ae97643c8f8f11c9820303f1904",
+            "02fa2aaccd51b948c9dc1a325d77226e98a5a3fe65fe9ba213761a60123040a45e",
+            "3e08fe561e075c653cbfd46908a10e7637c70c74f0a77d5fd45d1a750c739ec6",
+            "c4fce1775a1e141fb579944166eab0d65eefe7b98d480a569bbbfcb14f91c197",
+        ),
+        (
+            2,
+            "00e95d59dd0d46b0e303e500b62b7ccb0e555d49f5b849f5e748c071da8c0dbc",
+            "e6cc56ccbd0502b3f6f831d91e2ebd01c4de0479e0191b66895a4ffd9b68d544",
+            "7203d55eb82a5ca0d7d83674541ab55f6e76f1b85391d2c13706a89a064fd5b9",
+            "2b19b13f193f4ce83a399362a90cdc1e0ddcd83e57089a7af0bdca71d47869b2",
+            "7a443bde83dc63ef52dda354005225ba0e553243402a4705ce28ffaafe0f5b98",
+            "03077507ba327fc074d2793955ef3410ee3f03b82b4cdc2370f71d865beb926ef6",
+            "02ad53031ddfbbacfc5fbda3d3b0c2445c8e3e99cbc4ca2db2aa283fa68525b135",
+            "93f79041bb3fd266105be251adaeb5fd7f8b104fb554a4ba9a0becea48ddbfd7",
+            "0160fd0d388932f4826d2ebcd6b9eaba734f7c71cf25b4279a4ca2581e47b18d",
+        ),
+    ];
+
+    fn scalar(s: &str) -> Scalar {
+        decode_scalar(&s.to_string())
+    }
+
+    fn randomness(s: &str) -> [u8; 32] {
+        hex::decode(s).unwrap().try_into().unwrap()
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn test_vectors() {
+        let group_secret_key = scalar(GROUP_SECRET_KEY);
+        let group_public_key = decode_point(&GROUP_PUBLIC_KEY.to_string());
+        let msg = hex::decode(MESSAGE).unwrap();
+        assert_eq!(group_secret_key * G, group_public_key);
+
+        let signers: Vec<usize> = SIGNERS.iter().map(|s| s.0).collect();
+        let mut nonces = Vec::new();
+        let mut public_nonces = Vec::new();
+        for (id, share, hiding_rand, binding_rand, hiding, binding, D, E, _, _) in SIGNERS {
+            let expected =
+                group_secret_key + scalar(SHARE_COEFFICIENT) * Scalar::from((id + 1) as u32);
+            assert_eq!(scalar(share), expected);
+
+            let nonce = Nonce {
+                d: rfc9591::nonce_generate_from(&scalar(share), &randomness(hiding_rand)),
+                e: rfc9591::nonce_generate_from(&scalar(share), &randomness(binding_rand)),
+            };
+            assert_eq!(nonce.d, scalar(hiding));
+            assert_eq!(nonce.e, scalar(binding));
+
+            let public_nonce = PublicNonce::from(&nonce);
+            assert_eq!(public_nonce.D, decode_point(&D.to_string()));
+            assert_eq!(public_nonce.E, decode_point(&E.to_string()));
+
+            nonces.push(nonce);
+            public_nonces.push(public_nonce);
+        }
+
+        let binding_factors =
+            rfc9591::compute_binding_factors(&group_public_key, &signers, &public_nonces, &msg);
+        let mut sig_shares = Vec::new();
+        for (i, (id, share, _, _, _, _, _, _, binding_factor, sig_share)) in
+            SIGNERS.iter().enumerate()
+        {
+            assert_eq!(binding_factors[i], scalar(binding_factor));
+
+            let z_i = rfc9591::sign(
+                *id,
+                &scalar(share),
+                &nonces[i],
+                &msg,
+                &signers,
+                &public_nonces,
+                &group_public_key,
+            )
+            .unwrap();
+            assert_eq!(z_i, scalar(sig_share));
+            sig_shares.push(z_i);
+        }
+
+        let public_keys: Vec<_> = SIGNERS.iter().map(|s| scalar(s.1) * G).collect();
+        assert!(rfc9591::verify_signature_shares(
+            &msg,
+            &signers,
+            &public_nonces,
+            &sig_shares,
+            &public_keys,
+            &group_public_key
+        )
+        .is_empty());
+
+        // the commitment list is sorted before encoding, so the order of the signers is irrelevant
+        let reversed: Vec<usize> = signers.iter().rev().copied().collect();
+        let reversed_nonces: Vec<PublicNonce> = public_nonces.iter().rev().cloned().collect();
+        assert_eq!(
+            rfc9591::encode_group_commitment_list(&signers, &public_nonces),
+            rfc9591::encode_group_commitment_list(&reversed, &reversed_nonces)
+        );
+
+        let sig = rfc9591::aggregate(
+            &msg,
+            &signers,
+            &public_nonces,
+            &sig_shares,
+            &group_public_key,
+        );
+        assert_eq!(hex::encode(rfc9591::signature_to_bytes(&sig)), SIG);
+        assert!(rfc9591::verify(&sig, &group_public_key, &msg));
+        assert!(!rfc9591::verify(&sig, &group_public_key, b"tset"));
+
+        // a signer which is not in the signer set has no binding factor, and can't sign
+        assert_eq!(
+            rfc9591::sign(
+                1,
+                &scalar(SIGNERS[0].1),
+                &nonces[0],
+                &msg,
+                &signers,
+                &public_nonces,
+                &group_public_key,
+            ),
+            Err(SignerError::MissingPublicNonces(vec![1]))
+        );
+    }
+}
@@ -4,8 +4,9 @@ use secp256k1_math::{
     scalar::Scalar,
 };
 use serde::{Deserialize, Serialize};
-use sha3::{Digest, Sha3_256};
+use sha3::Digest;
 
+use crate::compute;
 use crate::util::hash_to_scalar;
 
 #[allow(non_snake_case)]
@@ -18,9 +19,14 @@ pub struct ID {
 
 #[allow(non_snake_case)]
 impl ID {
-    pub fn new<RNG: RngCore + CryptoRng>(id: &Scalar, a: &Scalar, rng: &mut RNG) -> Self {
+    pub fn new<RNG: RngCore + CryptoRng>(
+        id: &Scalar,
+        a: &Scalar,
+        ctx: &[u8],
+        rng: &mut RNG,
+    ) -> Self {
         let k = Scalar::random(rng);
-        let c = Self::challenge(id, &(&k * &G), &(a * &G));
+        let c = Self::challenge(id, &(k * G), &(a * G), ctx);
 
         Self {
             id: *id,
@@ -29,8 +35,25 @@ impl ID {
         }
     }
 
-    pub fn challenge(id: &Scalar, K: &Point, A: &Point) -> Scalar {
-        let mut hasher = Sha3_256::new();
+    // A proof for a secret which is already public, such as a Dkg dealer's once its polynomial
+    // is rebuilt from its shares.  The nonce comes from the secret, so everyone makes the same
+    // proof
+    pub fn from_public(id: &Scalar, a: &Scalar, ctx: &[u8]) -> Self {
+        let mut hasher = compute::hasher(compute::POK_TAG, ctx);
+        hasher.update(id.as_bytes());
+        hasher.update(a.as_bytes());
+        let k = hash_to_scalar(&mut hasher);
+        let c = Self::challenge(id, &(k * G), &(a * G), ctx);
+
+        Self {
+            id: *id,
+            kG: k * G,
+            kca: k + c * a,
+        }
+    }
+
+    pub fn challenge(id: &Scalar, K: &Point, A: &Point, ctx: &[u8]) -> Scalar {
+        let mut hasher = compute::hasher(compute::POK_TAG, ctx);
 
         hasher.update(id.as_bytes());
         hasher.update(K.compress().as_bytes());
@@ -39,8 +62,8 @@ impl ID {
         hash_to_scalar(&mut hasher)
     }
 
-    pub fn verify(&self, A: &Point) -> bool {
-        let c = Self::challenge(&self.id, &self.kG, A);
+    pub fn verify(&self, A: &Point, ctx: &[u8]) -> bool {
+        let c = Self::challenge(&self.id, &self.kG, A, ctx);
         &self.kca * &G == &self.kG + c * A
     }
 }
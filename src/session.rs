@@ -0,0 +1,661 @@
+use core::iter::zip;
+use rand_core::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use crate::bip340::SchnorrProof;
+use crate::common::{PublicNonce, Signature, SignatureShare, SigningPackage};
+use crate::errors::{SessionError, SigningPackageError};
+use crate::traits::{Aggregator, Signer};
+
+// A random id carried by every message in a session, so a message from one session can never
+// be taken as part of another
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Deserialize, Serialize)]
+pub struct SessionId(pub [u8; 32]);
+
+impl SessionId {
+    pub fn random<RNG: RngCore + CryptoRng>(rng: &mut RNG) -> Self {
+        let mut id = [0u8; 32];
+        rng.fill_bytes(&mut id);
+        Self(id)
+    }
+}
+
+// The kind of signature a session makes, which the coordinator picks
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum SignatureMode {
+    Frost,
+    Bip340,
+    Taproot(Option<[u8; 32]>), // the merkle root of the script tree, if any
+}
+
+// Bip340 and Taproot sessions both make a SchnorrProof
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum SessionSignature {
+    Frost(Signature),
+    Schnorr(SchnorrProof),
+}
+
+// Round one, from each signer: a public nonce and the key_ids for each of its party_ids
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct Commitments {
+    pub session_id: SessionId,
+    pub nonces: Vec<(usize, PublicNonce)>,
+    pub key_ids: Vec<(usize, Vec<usize>)>,
+}
+
+// Round two, from the coordinator to every signer in the package
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct SigningRequest {
+    pub session_id: SessionId,
+    pub mode: SignatureMode,
+    pub package: SigningPackage,
+}
+
+// The answer to a SigningRequest, with a share for each of the signer's party_ids
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct SignatureShares {
+    pub session_id: SessionId,
+    pub shares: Vec<SignatureShare>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum SignerSessionState {
+    Committed(Commitments),
+    Signed(SignatureShares),
+}
+
+impl SignerSessionState {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Committed(_) => "committed",
+            Self::Signed(_) => "signed",
+        }
+    }
+}
+
+// The signer side of a session.  Only public values are kept here, and the secret nonces stay
+// in the Signer, so a session resumed after a restart can only sign if the Signer kept them.
+// A Signer holds one set of nonces, so it can only be in one session at a time: starting a
+// second session replaces the nonces of the first, which then fails with BadPublicNonce
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct SignerSession {
+    pub id: SessionId,
+    state: SignerSessionState,
+}
+
+impl SignerSession {
+    // generate fresh nonces, returning the commitments to send to the coordinator.  Any
+    // session the signer is already in can no longer sign
+    pub fn new<S: Signer, RNG: RngCore + CryptoRng>(
+        id: SessionId,
+        signer: &mut S,
+        rng: &mut RNG,
+    ) -> (Self, Commitments) {
+        let commitments = Commitments {
+            session_id: id,
+            nonces: zip(signer.get_ids(), signer.gen_nonces(rng)).collect(),
+            key_ids: signer.get_signer_key_ids(),
+        };
+        let session = Self {
+            id,
+            state: SignerSessionState::Committed(commitments.clone()),
+        };
+        (session, commitments)
+    }
+
+    pub fn state(&self) -> &SignerSessionState {
+        &self.state
+    }
+
+    // Sign the request once.  A failed request leaves the session committed
+    pub fn sign<S: Signer>(
+        &mut self,
+        signer: &mut S,
+        request: &SigningRequest,
+    ) -> Result<SignatureShares, SessionError> {
+        if request.session_id != self.id {
+            return Err(SessionError::WrongSession);
+        }
+        if let SignerSessionState::Signed(_) = self.state {
+            return Err(SessionError::OutOfOrder(
+                "signing request",
+                self.state.name(),
+            ));
+        }
+
+        let package = &request.package;
+        let shares = match request.mode {
+            SignatureMode::Frost => signer.sign(package)?,
+            SignatureMode::Bip340 => signer.sign_bip340(package)?,
+            SignatureMode::Taproot(merkle_root) => signer.sign_taproot(package, merkle_root)?,
+        };
+        let shares = SignatureShares {
+            session_id: self.id,
+            shares,
+        };
+        self.state = SignerSessionState::Signed(shares.clone());
+        Ok(shares)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum CoordinatorState {
+    CollectingCommitments {
+        nonces: Vec<(usize, PublicNonce)>,
+        key_ids: Vec<(usize, Vec<usize>)>,
+    },
+    PackageSent {
+        request: SigningRequest,
+        shares: Vec<SignatureShare>,
+    },
+    SharesCollected {
+        request: SigningRequest,
+        shares: Vec<SignatureShare>,
+    },
+    Signed(SessionSignature),
+}
+
+impl CoordinatorState {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::CollectingCommitments { .. } => "collecting commitments",
+            Self::PackageSent { .. } => "package sent",
+            Self::SharesCollected { .. } => "shares collected",
+            Self::Signed(_) => "signed",
+        }
+    }
+}
+
+// The coordinator side of a session: collect commitments, send the package to the signers,
+// collect their shares and aggregate them
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct CoordinatorSession {
+    pub id: SessionId,
+    pub msg: Vec<u8>,
+    pub threshold: usize,
+    pub mode: SignatureMode,
+    state: CoordinatorState,
+}
+
+impl CoordinatorSession {
+    pub fn new(id: SessionId, msg: &[u8], threshold: usize, mode: SignatureMode) -> Self {
+        Self {
+            id,
+            msg: msg.to_vec(),
+            threshold,
+            mode,
+            state: CoordinatorState::CollectingCommitments {
+                nonces: Vec::new(),
+                key_ids: Vec::new(),
+            },
+        }
+    }
+
+    pub fn state(&self) -> &CoordinatorState {
+        &self.state
+    }
+
+    pub fn receive_commitments(&mut self, commitments: &Commitments) -> Result<(), SessionError> {
+        if commitments.session_id != self.id {
+            return Err(SessionError::WrongSession);
+        }
+        let (nonces, key_ids) = match &mut self.state {
+            CoordinatorState::CollectingCommitments { nonces, key_ids } => (nonces, key_ids),
+            state => return Err(SessionError::OutOfOrder("commitments", state.name())),
+        };
+
+        let mut ids: Vec<usize> = commitments.nonces.iter().map(|(id, _)| *id).collect();
+        ids.sort();
+        let mismatched_ids: Vec<usize> = ids
+            .iter()
+            .filter(|id| !commitments.key_ids.iter().any(|(i, _)| i == *id))
+            .chain(
+                commitments
+                    .key_ids
+                    .iter()
+                    .map(|(id, _)| id)
+                    .filter(|id| !ids.contains(id)),
+            )
+            .cloned()
+            .collect();
+        if !mismatched_ids.is_empty() {
+            return Err(SigningPackageError::MismatchedKeyIds(mismatched_ids).into());
+        }
+        let duplicate_id = ids
+            .windows(2)
+            .find(|w| w[0] == w[1])
+            .map(|w| w[0])
+            .or_else(|| {
+                ids.iter()
+                    .cloned()
+                    .find(|id| nonces.iter().any(|(i, _)| i == id))
+            });
+        if let Some(id) = duplicate_id {
+            return Err(SessionError::DuplicateSigner(id));
+        }
+
+        nonces.extend(commitments.nonces.iter().cloned());
+        key_ids.extend(commitments.key_ids.iter().cloned());
+        Ok(())
+    }
+
+    // Make the package from the commitments so far, which must reach the threshold.  No more
+    // commitments are taken after this
+    pub fn signing_request(&mut self) -> Result<SigningRequest, SessionError> {
+        let package = match &self.state {
+            CoordinatorState::CollectingCommitments { nonces, key_ids } => {
+                SigningPackage::with_key_ids(&self.msg, nonces, key_ids, self.threshold)?
+            }
+            state => return Err(SessionError::OutOfOrder("signing request", state.name())),
+        };
+
+        let request = SigningRequest {
+            session_id: self.id,
+            mode: self.mode,
+            package,
+        };
+        self.state = CoordinatorState::PackageSent {
+            request: request.clone(),
+            shares: Vec::new(),
+        };
+        Ok(request)
+    }
+
+    // the signers in the package which have not sent their shares yet
+    pub fn missing_shares(&self) -> Vec<usize> {
+        match &self.state {
+            CoordinatorState::PackageSent { request, shares } => request
+                .package
+                .signers()
+                .into_iter()
+                .filter(|id| !shares.iter().any(|s| s.id == *id))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    // Shares are only checked against the package here, aggregate checks that they verify
+    pub fn receive_shares(&mut self, sig_shares: &SignatureShares) -> Result<(), SessionError> {
+        if sig_shares.session_id != self.id {
+            return Err(SessionError::WrongSession);
+        }
+        let (request, shares) = match &mut self.state {
+            CoordinatorState::PackageSent { request, shares } => (request, shares),
+            state => return Err(SessionError::OutOfOrder("signature shares", state.name())),
+        };
+
+        let signers = request.package.signers();
+        for (i, share) in sig_shares.shares.iter().enumerate() {
+            if !signers.contains(&share.id) {
+                return Err(SessionError::UnknownSigner(share.id));
+            }
+            let received = shares.iter().chain(&sig_shares.shares[..i]);
+            if received.into_iter().any(|s| s.id == share.id) {
+                return Err(SessionError::DuplicateSigner(share.id));
+            }
+        }
+        shares.extend(sig_shares.shares.iter().cloned());
+
+        if shares.len() == signers.len() {
+            let request = request.clone();
+            let shares = shares.clone();
+            self.state = CoordinatorState::SharesCollected { request, shares };
+        }
+        Ok(())
+    }
+
+    // Aggregate the shares once every signer has sent them.  If any share is bad the session
+    // stays in the shares collected state, and a new session is needed to sign
+    pub fn aggregate<A: Aggregator>(
+        &mut self,
+        aggregator: &mut A,
+    ) -> Result<SessionSignature, SessionError> {
+        let (request, shares) = match &self.state {
+            CoordinatorState::SharesCollected { request, shares } => (request, shares),
+            state => return Err(SessionError::OutOfOrder("aggregate", state.name())),
+        };
+
+        let package = &request.package;
+        let signature = match request.mode {
+            SignatureMode::Frost => SessionSignature::Frost(aggregator.sign(package, shares)?),
+            SignatureMode::Bip340 => {
+                SessionSignature::Schnorr(aggregator.sign_bip340(package, shares)?)
+            }
+            SignatureMode::Taproot(merkle_root) => {
+                SessionSignature::Schnorr(aggregator.sign_taproot(package, shares, merkle_root)?)
+            }
+        };
+        self.state = CoordinatorState::Signed(signature.clone());
+        Ok(signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hashbrown::HashMap;
+    use num_traits::{One, Zero};
+    use rand_core::{CryptoRng, OsRng, RngCore};
+    use secp256k1_math::{point::Point, scalar::Scalar};
+
+    use crate::common::PolyCommitment;
+    use crate::errors::{AggregatorError, SessionError, SignerError, SigningPackageError};
+    use crate::session::{
+        CoordinatorSession, CoordinatorState, SessionId, SessionSignature, SignatureMode,
+        SignerSession, SignerSessionState,
+    };
+    use crate::traits::{Aggregator, Signer};
+    use crate::{bip340, taproot, v1, v2};
+
+    #[allow(non_snake_case)]
+    fn dkg<S: Signer, SA: Aggregator, RNG: RngCore + CryptoRng>(
+        signers: &mut [S],
+        num_keys: usize,
+        threshold: usize,
+        rng: &mut RNG,
+    ) -> (Point, SA) {
+        let A: Vec<PolyCommitment> = signers
+            .iter()
+            .flat_map(|s| s.get_poly_commitments(rng))
+            .collect();
+        let shares: HashMap<usize, HashMap<usize, Scalar>> =
+            signers.iter().flat_map(|s| s.get_shares()).collect();
+        for signer in signers.iter_mut() {
+            signer.compute_secrets(&shares, &A).unwrap();
+        }
+
+        let group_key = A.iter().fold(Point::zero(), |key, comm| key + comm.A[0]);
+        (group_key, SA::new(num_keys, threshold, A).unwrap())
+    }
+
+    // run a whole session with the first signers which reach the threshold
+    fn run_session<S: Signer, SA: Aggregator, RNG: RngCore + CryptoRng>(
+        signers: &mut [S],
+        aggregator: &mut SA,
+        msg: &[u8],
+        threshold: usize,
+        mode: SignatureMode,
+        rng: &mut RNG,
+    ) -> SessionSignature {
+        let id = SessionId::random(rng);
+        let mut coordinator = CoordinatorSession::new(id, msg, threshold, mode);
+
+        let mut sessions = Vec::new();
+        let mut num_keys = 0;
+        for signer in signers.iter_mut() {
+            let (session, commitments) = SignerSession::new(id, signer, rng);
+            coordinator.receive_commitments(&commitments).unwrap();
+            sessions.push(session);
+
+            num_keys += signer.get_key_ids().len();
+            if num_keys >= threshold {
+                break;
+            }
+        }
+
+        let request = coordinator.signing_request().unwrap();
+        for (session, signer) in sessions.iter_mut().zip(signers.iter_mut()) {
+            let shares = session.sign(signer, &request).unwrap();
+            coordinator.receive_shares(&shares).unwrap();
+        }
+        assert!(coordinator.missing_shares().is_empty());
+
+        coordinator.aggregate(aggregator).unwrap()
+    }
+
+    fn sign_all_modes<S: Signer, SA: Aggregator>(
+        signers: &mut [S],
+        num_keys: usize,
+        threshold: usize,
+    ) {
+        let mut rng = OsRng::default();
+        let msg = "It was many and many a year ago".as_bytes();
+        let (group_key, mut aggregator) = dkg::<_, SA, _>(signers, num_keys, threshold, &mut rng);
+
+        let mode = SignatureMode::Frost;
+        match run_session(signers, &mut aggregator, msg, threshold, mode, &mut rng) {
+            SessionSignature::Frost(sig) => assert!(sig.verify(&group_key, msg, &[])),
+            _ => panic!("expected a FROST signature"),
+        }
+
+        let mode = SignatureMode::Bip340;
+        match run_session(signers, &mut aggregator, msg, threshold, mode, &mut rng) {
+            SessionSignature::Schnorr(proof) => {
+                assert!(proof.verify(&bip340::x_only(&group_key), msg))
+            }
+            _ => panic!("expected a BIP-340 signature"),
+        }
+
+        let merkle_root = Some([7u8; 32]);
+        let mode = SignatureMode::Taproot(merkle_root);
+        match run_session(signers, &mut aggregator, msg, threshold, mode, &mut rng) {
+            SessionSignature::Schnorr(proof) => {
+                let output_key = taproot::output_key(&group_key, merkle_root).unwrap();
+                assert!(proof.verify(&output_key, msg))
+            }
+            _ => panic!("expected a BIP-340 signature"),
+        }
+    }
+
+    #[test]
+    fn v1_session() {
+        let mut rng = OsRng::default();
+        let mut signers: Vec<v1::Signer> = [vec![0, 1], vec![2], vec![3, 4]]
+            .iter()
+            .map(|ids| v1::Signer::new(ids, 5, 3, &mut rng))
+            .collect();
+
+        sign_all_modes::<_, v1::SignatureAggregator>(&mut signers, 5, 3);
+    }
+
+    #[test]
+    fn v2_session() {
+        let mut rng = OsRng::default();
+        let mut parties: Vec<v2::Party> = [vec![0, 1, 2], vec![3, 4], vec![5, 6, 7], vec![8, 9]]
+            .iter()
+            .enumerate()
+            .map(|(pid, kids)| {
+                v2::Party::new(pid, kids.iter().copied().collect(), 10, 4, 7, &mut rng)
+            })
+            .collect();
+
+        sign_all_modes::<_, v2::SignatureAggregator>(&mut parties, 10, 7);
+    }
+
+    // the first session of a signer fails once it starts a second, and the second still signs
+    fn two_sessions<S: Signer, SA: Aggregator>(
+        signers: &mut [S],
+        num_keys: usize,
+        threshold: usize,
+    ) {
+        let mut rng = OsRng::default();
+        let msg = "It was many and many a year ago".as_bytes();
+        let (group_key, mut aggregator) = dkg::<_, SA, _>(signers, num_keys, threshold, &mut rng);
+        let (first, others) = signers.split_at_mut(1);
+        let signer = &mut first[0];
+
+        let ids = [SessionId::random(&mut rng), SessionId::random(&mut rng)];
+        let mut coordinators =
+            ids.map(|id| CoordinatorSession::new(id, msg, threshold, SignatureMode::Frost));
+        let mut sessions = Vec::new();
+        for (id, coordinator) in ids.iter().zip(coordinators.iter_mut()) {
+            let (session, commitments) = SignerSession::new(*id, signer, &mut rng);
+            coordinator.receive_commitments(&commitments).unwrap();
+            sessions.push(session);
+        }
+
+        for (i, coordinator) in coordinators.iter_mut().enumerate() {
+            let mut other_sessions = Vec::new();
+            for other in others.iter_mut() {
+                let (session, commitments) = SignerSession::new(ids[i], other, &mut rng);
+                coordinator.receive_commitments(&commitments).unwrap();
+                other_sessions.push(session);
+            }
+            let request = coordinator.signing_request().unwrap();
+            for (session, other) in other_sessions.iter_mut().zip(others.iter_mut()) {
+                let shares = session.sign(other, &request).unwrap();
+                coordinator.receive_shares(&shares).unwrap();
+            }
+
+            let party_id = signer.get_ids()[0];
+            if i == 0 {
+                assert_eq!(
+                    sessions[0].sign(signer, &request),
+                    Err(SessionError::Signer(SignerError::BadPublicNonce(party_id)))
+                );
+                assert!(matches!(
+                    sessions[0].state(),
+                    SignerSessionState::Committed(_)
+                ));
+            } else {
+                let shares = sessions[1].sign(signer, &request).unwrap();
+                coordinator.receive_shares(&shares).unwrap();
+                match coordinator.aggregate(&mut aggregator).unwrap() {
+                    SessionSignature::Frost(sig) => assert!(sig.verify(&group_key, msg, &[])),
+                    _ => panic!("expected a FROST signature"),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn v1_two_sessions() {
+        let mut rng = OsRng::default();
+        let mut signers: Vec<v1::Signer> = [vec![0, 1], vec![2], vec![3, 4]]
+            .iter()
+            .map(|ids| v1::Signer::new(ids, 5, 3, &mut rng))
+            .collect();
+
+        two_sessions::<_, v1::SignatureAggregator>(&mut signers, 5, 3);
+    }
+
+    #[test]
+    fn v2_two_sessions() {
+        let mut rng = OsRng::default();
+        let mut parties: Vec<v2::Party> = [vec![0, 1, 2], vec![3, 4], vec![5, 6, 7], vec![8, 9]]
+            .iter()
+            .enumerate()
+            .map(|(pid, kids)| {
+                v2::Party::new(pid, kids.iter().copied().collect(), 10, 4, 7, &mut rng)
+            })
+            .collect();
+
+        two_sessions::<_, v2::SignatureAggregator>(&mut parties, 10, 7);
+    }
+
+    #[test]
+    fn out_of_order() {
+        let mut rng = OsRng::default();
+        let msg = "It was many and many a year ago".as_bytes();
+        let mut signers: Vec<v1::Signer> = (0..3)
+            .map(|i| v1::Signer::new(&[i], 3, 2, &mut rng))
+            .collect();
+        let (_, mut aggregator) =
+            dkg::<_, v1::SignatureAggregator, _>(&mut signers, 3, 2, &mut rng);
+
+        let id = SessionId::random(&mut rng);
+        let other_id = SessionId::random(&mut rng);
+        let mut coordinator = CoordinatorSession::new(id, msg, 2, SignatureMode::Frost);
+
+        let (mut session0, commitments0) = SignerSession::new(id, &mut signers[0], &mut rng);
+        let (_, other_commitments) = SignerSession::new(other_id, &mut signers[1], &mut rng);
+        assert_eq!(
+            coordinator.receive_commitments(&other_commitments),
+            Err(SessionError::WrongSession)
+        );
+        coordinator.receive_commitments(&commitments0).unwrap();
+        assert_eq!(
+            coordinator.receive_commitments(&commitments0),
+            Err(SessionError::DuplicateSigner(0))
+        );
+
+        let mut bad_commitments = commitments0.clone();
+        bad_commitments.key_ids = Vec::new();
+        assert_eq!(
+            coordinator.receive_commitments(&bad_commitments),
+            Err(SessionError::BadSigningPackage(
+                SigningPackageError::MismatchedKeyIds(vec![0])
+            ))
+        );
+
+        // nothing to aggregate yet, and one signer is below the threshold
+        assert_eq!(
+            coordinator.aggregate(&mut aggregator),
+            Err(SessionError::OutOfOrder(
+                "aggregate",
+                "collecting commitments"
+            ))
+        );
+        assert_eq!(
+            coordinator.signing_request(),
+            Err(SessionError::BadSigningPackage(
+                SigningPackageError::BelowThreshold(2, 1)
+            ))
+        );
+
+        let (mut session1, commitments1) = SignerSession::new(id, &mut signers[1], &mut rng);
+        coordinator.receive_commitments(&commitments1).unwrap();
+        let request = coordinator.signing_request().unwrap();
+
+        // the package is fixed once it is sent
+        let (_, commitments2) = SignerSession::new(id, &mut signers[2], &mut rng);
+        assert_eq!(
+            coordinator.receive_commitments(&commitments2),
+            Err(SessionError::OutOfOrder("commitments", "package sent"))
+        );
+        assert_eq!(
+            coordinator.signing_request(),
+            Err(SessionError::OutOfOrder("signing request", "package sent"))
+        );
+
+        let mut wrong_request = request.clone();
+        wrong_request.session_id = other_id;
+        assert_eq!(
+            session0.sign(&mut signers[0], &wrong_request),
+            Err(SessionError::WrongSession)
+        );
+
+        // a coordinator restarting from its saved state picks up where it left off
+        let saved = coordinator.clone();
+        let shares0 = session0.sign(&mut signers[0], &request).unwrap();
+        assert_eq!(
+            session0.sign(&mut signers[0], &request),
+            Err(SessionError::OutOfOrder("signing request", "signed"))
+        );
+        assert_eq!(
+            session0.state(),
+            &SignerSessionState::Signed(shares0.clone())
+        );
+
+        let mut coordinator = saved;
+        coordinator.receive_shares(&shares0).unwrap();
+        assert_eq!(
+            coordinator.receive_shares(&shares0),
+            Err(SessionError::DuplicateSigner(0))
+        );
+        assert_eq!(coordinator.missing_shares(), vec![1]);
+        assert_eq!(
+            coordinator.aggregate(&mut aggregator),
+            Err(SessionError::OutOfOrder("aggregate", "package sent"))
+        );
+
+        let mut shares1 = session1.sign(&mut signers[1], &request).unwrap();
+        shares1.shares[0].z_i += Scalar::one();
+        let mut unknown_shares = shares1.clone();
+        unknown_shares.shares[0].id = 2;
+        assert_eq!(
+            coordinator.receive_shares(&unknown_shares),
+            Err(SessionError::UnknownSigner(2))
+        );
+        coordinator.receive_shares(&shares1).unwrap();
+        assert_eq!(
+            coordinator.aggregate(&mut aggregator),
+            Err(SessionError::Aggregator(AggregatorError::BadPartySigs(
+                vec![1]
+            )))
+        );
+        assert!(matches!(
+            coordinator.state(),
+            CoordinatorState::SharesCollected { .. }
+        ));
+    }
+}
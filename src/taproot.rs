@@ -0,0 +1,102 @@
+use secp256k1_math::{
+    point::{Point, G},
+    scalar::Scalar,
+};
+use sha2::Digest;
+
+use crate::bip340::{parity, tagged_hash, x_only};
+
+// BIP-341 tweak of an internal key, committing to the script tree if there is one.  Fails if
+// the hash is not less than the curve order
+pub fn tweak(internal_key: &Point, merkle_root: Option<[u8; 32]>) -> Option<Scalar> {
+    let mut hasher = tagged_hash("TapTweak");
+
+    hasher.update(x_only(internal_key));
+    if let Some(root) = merkle_root {
+        hasher.update(root);
+    }
+
+    let mut bytes = [0u8; 32];
+    bytes.copy_from_slice(hasher.finalize().as_slice());
+    to_scalar(bytes)
+}
+
+// None unless bytes is less than the curve order, rather than reducing it
+fn to_scalar(bytes: [u8; 32]) -> Option<Scalar> {
+    let t = Scalar::from(bytes);
+    if *t.as_bytes() != bytes {
+        return None;
+    }
+    Some(t)
+}
+
+// Q = lift_x(x(P)) + t * G, where lift_x(x(P)) is P with its Y made even
+pub fn tweaked_public_key(internal_key: &Point, tweak: &Scalar) -> Point {
+    parity(internal_key) * internal_key + tweak * G
+}
+
+// the x-only output key which goes in the taproot output
+pub fn output_key(internal_key: &Point, merkle_root: Option<[u8; 32]>) -> Option<[u8; 32]> {
+    let t = tweak(internal_key, merkle_root)?;
+    Some(x_only(&tweaked_public_key(internal_key, &t)))
+}
+
+#[cfg(test)]
+mod tests {
+    use secp256k1_math::point::{Compressed, Point};
+
+    use crate::taproot;
+
+    // (internal key, merkle root, tweak, output key) from the scriptPubKey vectors in
+    // https://github.com/bitcoin/bips/blob/master/bip-0341/wallet-test-vectors.json
+    const VECTORS: &[(&str, Option<&str>, &str, &str)] = &[
+        (
+            "d6889cb081036e0faefa3a35157ad71086b123b2b144b649798b494c300a961d",
+            None,
+            "b86e7be8f39bab32a6f2c0443abbc210f0edac0e2c53d501b36b64437d9c6c70",
+            "53a1f6e454df1aa2776a2814a721372d6258050de330b3c6d10ee8f4e0dda343",
+        ),
+        (
+            "187791b6f712a8ea41c8ecdd0ee77fab3e85263b37e1ec18a3651926b3a6cf27",
+            Some("5b75adecf53548f3ec6ad7d78383bf84cc57b55a3127c72b9a2481752dd88b21"),
+            "cbd8679ba636c1110ea247542cfbd964131a6be84f873f7f3b62a777528ed001",
+            "147c9c57132f6e7ecddba9800bb0c4449251c92a1e60371ee77557b6620f3ea3",
+        ),
+    ];
+
+    #[test]
+    fn test_vectors() {
+        for (internal_key, merkle_root, tweak, output_key) in VECTORS {
+            let mut compressed = hex::decode("02").unwrap();
+            compressed.extend(hex::decode(internal_key).unwrap());
+            let internal_key = Point::from(Compressed::from(compressed.as_slice()));
+            let merkle_root: Option<[u8; 32]> =
+                merkle_root.map(|r| hex::decode(r).unwrap().try_into().unwrap());
+
+            let t = taproot::tweak(&internal_key, merkle_root).unwrap();
+            assert_eq!(hex::encode(t.as_bytes()), *tweak);
+            assert_eq!(
+                hex::encode(taproot::output_key(&internal_key, merkle_root).unwrap()),
+                *output_key
+            );
+        }
+    }
+
+    #[test]
+    fn tweak_out_of_range() {
+        let order: [u8; 32] =
+            hex::decode("FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEBAAEDCE6AF48A03BBFD25E8CD0364141")
+                .unwrap()
+                .try_into()
+                .unwrap();
+        assert_eq!(taproot::to_scalar(order), None);
+        assert_eq!(taproot::to_scalar([0xff; 32]), None);
+
+        let mut below_order = order;
+        below_order[31] -= 1;
+        assert_eq!(
+            taproot::to_scalar(below_order).unwrap().as_bytes(),
+            &below_order
+        );
+    }
+}
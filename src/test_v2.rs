@@ -1,11 +1,27 @@
+use core::iter::zip;
 use rand_core::{CryptoRng, OsRng, RngCore};
+use std::fs;
 use std::time;
 
-use crate::v2::{
-    Party, PolyCommitment, PubKeyMap, PublicNonce, SelectedSigners, SignatureAggregator,
-    SignatureShare,
+use crate::bip340::{self, SchnorrProof};
+use crate::common::{
+    BatchSignatureShare, PolyCommitment, PublicNonce, SignatureShare, SigningPackage,
+};
+use crate::dkg::{
+    self, Complaint, DkgCommitment, DkgScheme, DkgShare, DkgState, EncryptedShare, Justification,
+    PedersenCommitment, RecoveryShare, RefreshCommitment, RepairShare, RepairSum,
+};
+#[cfg(feature = "reconstruct")]
+use crate::errors::ReconstructError;
+use crate::errors::{AggregatorError, DkgError, NoncePoolError, SignerError, SigningPackageError};
+use crate::nonce_pool::{FileNonceStore, MemoryNonceStore, NonceId, NoncePool};
+use crate::taproot;
+use crate::v2::{self, NonceRequest, Party, PubKeyMap, SelectedSigners, SignatureAggregator};
+use num_traits::{One, Zero};
+use secp256k1_math::{
+    point::{Point, G},
+    scalar::Scalar,
 };
-use secp256k1_math::scalar::Scalar;
 
 use hashbrown::{HashMap, HashSet};
 
@@ -54,7 +70,7 @@ fn distribute(
     for party_id in 0..parties.len() {
         let party_shares = filter_party_shares(party_id, &key_owners, &broadcast_shares);
         let compute_secret_start = time::Instant::now();
-        let pks = parties[party_id].compute_secret(party_shares, &A);
+        let pks = parties[party_id].compute_secret(party_shares, &A).unwrap();
         public_keys.extend(pks);
 
         let compute_secret_time = compute_secret_start.elapsed();
@@ -96,17 +112,14 @@ fn select_parties<RNG: RngCore + CryptoRng>(
 }
 
 // There might be a slick one-liner for this?
-fn collect_signatures(
-    parties: &[Party],
-    signers: &SelectedSigners,
-    nonce_ctr: usize,
-    msg: &[u8],
-) -> Vec<SignatureShare> {
-    signers
-        .keys()
+fn collect_signatures(parties: &mut [Party], package: &SigningPackage) -> Vec<SignatureShare> {
+    package
+        .signers()
+        .iter()
         .map(|party_id| SignatureShare {
-            party_id: *party_id,
-            z_i: parties[*party_id].sign(&msg, &signers, nonce_ctr),
+            id: *party_id,
+            z_i: parties[*party_id].sign(package).unwrap(),
+            key_ids: package.signer_key_ids(*party_id).to_vec(),
         })
         .collect()
 }
@@ -176,7 +189,8 @@ pub fn test_v2() {
         .collect();
     let (total_compute_secret_time, public_keys) = distribute(&mut parties, &key_owners, &A, &B);
 
-    let mut sig_agg = SignatureAggregator::new(num_keys, num_parties, threshold, A, B, public_keys);
+    let mut sig_agg =
+        SignatureAggregator::new(num_keys, num_parties, threshold, A, B, public_keys).unwrap();
 
     let mut total_sig_time = 0;
     let mut total_party_sig_time = 0;
@@ -184,19 +198,19 @@ pub fn test_v2() {
         let msg = "It was many and many a year ago".as_bytes();
 
         let signers = select_parties(&key_owners, threshold, &mut rng); // signers[party_id] = Set(key_ids)
-        let nonce_ctr = sig_agg.get_nonce_ctr();
+        let package = sig_agg.signing_package(&msg, &signers).unwrap();
         let party_sig_start = time::Instant::now();
-        let sig_shares = collect_signatures(&parties, &signers, nonce_ctr, &msg);
+        let sig_shares = collect_signatures(&mut parties, &package);
         let party_sig_time = party_sig_start.elapsed();
         let sig_start = time::Instant::now();
-        let sig = sig_agg.sign(&msg, &sig_shares, &signers);
+        let sig = sig_agg.sign(&package, &sig_shares).unwrap();
         let sig_time = sig_start.elapsed();
 
         total_party_sig_time += party_sig_time.as_micros();
         total_sig_time += sig_time.as_micros();
 
         println!("Signature (R,z) = \n({},{})", sig.R, sig.z);
-        assert!(sig.verify(&sig_agg.group_key, &msg));
+        assert!(sig.verify(&sig_agg.group_key, &msg, &sig_agg.context));
 
         // this resets one party's nonces assuming it went down and needed to regenerate
         if sig_ct == 3 {
@@ -211,18 +225,16 @@ pub fn test_v2() {
             );
         }
 
-        // this refills the nonces if they run out
-        // TODO: who should kick this off?
-        if sig_agg.get_nonce_ctr() == num_nonces as usize {
-            println!("Everyone's nonces were refilled.");
-            let B: Vec<Vec<PublicNonce>> = parties
-                .iter_mut()
-                .map(|p| p.gen_nonces(num_nonces, &mut rng))
-                .collect();
-            for p in &mut parties {
-                p.set_group_nonces(B.clone());
-            }
-            sig_agg.set_group_nonces(B.clone());
+        // parties which are running out of nonces are asked for more
+        for request in sig_agg.nonce_requests() {
+            println!("Refilling nonces for party {}", request.party_id);
+            reset_nonce(
+                &mut parties,
+                &mut sig_agg,
+                request.party_id,
+                request.num_nonces,
+                &mut rng,
+            );
         }
     }
     // Note: The scaling likely depends on the distribution of keys across parties
@@ -249,3 +261,1798 @@ pub fn test_v2() {
         total_sig_time / num_sigs as u128
     );
 }
+
+#[allow(non_snake_case)]
+#[test]
+pub fn test_v2_errors() {
+    let num_nonces = 4;
+    let num_keys = 4;
+    let threshold = 3;
+    let mut rng = OsRng::default();
+
+    let key_owners = vec![0, 0, 1, 2];
+    let num_parties = 3;
+    let party_keys: Vec<HashSet<usize>> = vec![
+        [0, 1].iter().cloned().collect(),
+        [2].iter().cloned().collect(),
+        [3].iter().cloned().collect(),
+    ];
+
+    let mut parties: Vec<Party> = (0..num_parties)
+        .map(|i| {
+            Party::new(
+                i,
+                party_keys[i].clone(),
+                num_keys,
+                num_parties,
+                threshold,
+                &mut rng,
+            )
+        })
+        .collect();
+    let A: Vec<PolyCommitment> = parties
+        .iter()
+        .map(|p| p.get_poly_commitment(&mut rng))
+        .collect();
+    let B: Vec<Vec<PublicNonce>> = parties
+        .iter_mut()
+        .map(|p| p.gen_nonces(num_nonces, &mut rng))
+        .collect();
+    let broadcast_shares: Vec<Vec<(usize, Scalar)>> =
+        parties.iter().map(|p| p.get_shares()).collect();
+    let shares = filter_party_shares(0, &key_owners, &broadcast_shares);
+
+    assert_eq!(
+        parties[0].compute_secret(shares.clone(), &A[..2]).err(),
+        Some(DkgError::BadPolyCommitmentLen(3, 2))
+    );
+
+    let mut bad_A = A.clone();
+    bad_A[1].A[0] += G;
+    assert_eq!(
+        parties[0].compute_secret(shares.clone(), &bad_A).err(),
+        Some(DkgError::BadPolyCommitments(vec![1]))
+    );
+
+    // an empty commitment, or one for a polynomial of the wrong degree
+    let mut bad_A = A.clone();
+    bad_A[1].A.clear();
+    assert!(!bad_A[1].verify(&[]));
+    assert_eq!(
+        parties[0].compute_secret(shares.clone(), &bad_A).err(),
+        Some(DkgError::BadPolyCommitments(vec![1]))
+    );
+    let mut bad_A = A.clone();
+    bad_A[2].A.push(G);
+    assert_eq!(
+        parties[0].compute_secret(shares.clone(), &bad_A).err(),
+        Some(DkgError::BadPolyCommitments(vec![2]))
+    );
+
+    let mut missing_shares = shares.clone();
+    missing_shares
+        .get_mut(&1)
+        .unwrap()
+        .retain(|(sender, _)| *sender != 2);
+    assert_eq!(
+        parties[0].compute_secret(missing_shares, &A).err(),
+        Some(DkgError::MissingShares(vec![2]))
+    );
+
+    let mut bad_shares = shares.clone();
+    bad_shares.get_mut(&0).unwrap()[1].1 += Scalar::one();
+    assert_eq!(
+        parties[0].compute_secret(bad_shares, &A).err(),
+        Some(DkgError::BadShares(vec![1]))
+    );
+
+    let (_, public_keys) = distribute(&mut parties, &key_owners, &A, &B);
+
+    assert!(matches!(
+        SignatureAggregator::new(
+            num_keys,
+            num_parties,
+            threshold,
+            A.clone(),
+            B[..2].to_vec(),
+            public_keys.clone()
+        ),
+        Err(AggregatorError::BadNonceLen(3, 2))
+    ));
+
+    let mut sig_agg =
+        SignatureAggregator::new(num_keys, num_parties, threshold, A, B, public_keys).unwrap();
+
+    let msg = "It was many and many a year ago".as_bytes();
+    let signers = select_parties(&key_owners, threshold, &mut rng);
+
+    // too few keys to reach the threshold
+    let one_key: SelectedSigners = [(1, party_keys[1].clone())].into_iter().collect();
+    assert_eq!(
+        sig_agg.signing_package(&msg, &one_key).err(),
+        Some(AggregatorError::BadSigningPackage(
+            SigningPackageError::BelowThreshold(3, 1)
+        ))
+    );
+
+    // a party refuses a package without its nonce, or with a nonce it did not generate
+    let other_nonces = sig_agg.B[1].clone();
+    let without_party = SigningPackage::with_key_ids(
+        &msg,
+        &[(1, other_nonces[0].clone())],
+        &[(1, vec![0, 1, 2])],
+        threshold,
+    )
+    .unwrap();
+    assert_eq!(
+        parties[0].sign(&without_party).err(),
+        Some(SignerError::MissingPublicNonces(vec![0]))
+    );
+    let wrong_nonce = SigningPackage::with_key_ids(
+        &msg,
+        &[(0, other_nonces[1].clone()), (1, other_nonces[0].clone())],
+        &[(0, vec![0, 1]), (1, vec![2])],
+        threshold,
+    )
+    .unwrap();
+    assert_eq!(
+        parties[0].sign(&wrong_nonce).err(),
+        Some(SignerError::BadPublicNonce(0))
+    );
+
+    // or one that signs with keys it does not hold, or with too few keys
+    let own_nonce = sig_agg.B[0][0].clone();
+    let other_keys = SigningPackage::with_key_ids(
+        &msg,
+        &[(0, own_nonce.clone()), (1, other_nonces[0].clone())],
+        &[(0, vec![0, 2]), (1, vec![1])],
+        threshold,
+    )
+    .unwrap();
+    assert_eq!(
+        parties[0].sign(&other_keys).err(),
+        Some(SignerError::BadKeyIds(0, vec![0, 2]))
+    );
+    let too_few =
+        SigningPackage::with_key_ids(&msg, &[(0, own_nonce)], &[(0, vec![0, 1])], 2).unwrap();
+    assert_eq!(
+        parties[0].sign(&too_few).err(),
+        Some(SignerError::BadSigningPackage(
+            SigningPackageError::BelowThreshold(3, 2)
+        ))
+    );
+
+    let package = sig_agg.signing_package(&msg, &signers).unwrap();
+    let mut sig_shares = collect_signatures(&mut parties, &package);
+    for sig_share in sig_shares.iter_mut() {
+        sig_share.z_i += Scalar::one();
+    }
+    assert_eq!(
+        sig_agg.sign(&package, &sig_shares).err(),
+        Some(AggregatorError::BadPartySigs(package.signers()))
+    );
+
+    // the failed round still consumed its nonces, so its package can't be signed again
+    let nonce_indices = sig_agg.get_nonce_indices(&signers);
+    assert!(nonce_indices.values().all(|i| *i == 1));
+    for party_id in package.signers() {
+        assert_eq!(
+            parties[party_id].sign(&package).err(),
+            Some(SignerError::BadPublicNonce(party_id))
+        );
+        assert_eq!(
+            parties[party_id].sign_bip340(&package).err(),
+            Some(SignerError::BadPublicNonce(party_id))
+        );
+    }
+    assert_eq!(
+        sig_agg.sign(&package, &sig_shares).err(),
+        Some(AggregatorError::BadPackageNonces(package.signers()))
+    );
+
+    // a second share from a signer, even in place of another signer's, or a share from outside
+    // the package is reported by id
+    let package = sig_agg.signing_package(&msg, &signers).unwrap();
+    let sig_shares = collect_signatures(&mut parties, &package);
+    let mut duplicate_shares = sig_shares.clone();
+    duplicate_shares[1] = sig_shares[0].clone();
+    assert_eq!(
+        sig_agg.sign(&package, &duplicate_shares).err(),
+        Some(AggregatorError::BadPartySigs(vec![sig_shares[0].id]))
+    );
+    let package = sig_agg.signing_package(&msg, &signers).unwrap();
+    let mut sig_shares = collect_signatures(&mut parties, &package);
+    sig_shares.push(SignatureShare {
+        id: num_parties,
+        ..sig_shares[0].clone()
+    });
+    assert_eq!(
+        sig_agg.sign(&package, &sig_shares).err(),
+        Some(AggregatorError::BadPartySigs(vec![num_parties]))
+    );
+
+    let next_package = sig_agg.signing_package(&msg, &signers).unwrap();
+    let sig_shares = collect_signatures(&mut parties, &next_package);
+    assert!(sig_agg.sign(&next_package, &sig_shares).is_ok());
+
+    assert_eq!(
+        sig_agg.signing_package(&msg, &signers).err(),
+        Some(AggregatorError::OutOfNonces(package.signers()))
+    );
+}
+
+#[allow(non_snake_case)]
+#[test]
+pub fn test_v2_complaints() {
+    let num_nonces = 1;
+    let num_keys = 4;
+    let threshold = 3;
+    let mut rng = OsRng::default();
+
+    let key_owners = vec![0, 0, 1, 2];
+    let num_parties = 3;
+    let party_keys: Vec<HashSet<usize>> = vec![
+        [0, 1].iter().cloned().collect(),
+        [2].iter().cloned().collect(),
+        [3].iter().cloned().collect(),
+    ];
+
+    let mut parties: Vec<Party> = (0..num_parties)
+        .map(|i| {
+            Party::new(
+                i,
+                party_keys[i].clone(),
+                num_keys,
+                num_parties,
+                threshold,
+                &mut rng,
+            )
+        })
+        .collect();
+    let A: Vec<PolyCommitment> = parties
+        .iter()
+        .map(|p| p.get_poly_commitment(&mut rng))
+        .collect();
+    let B: Vec<Vec<PublicNonce>> = parties
+        .iter_mut()
+        .map(|p| p.gen_nonces(num_nonces, &mut rng))
+        .collect();
+    let broadcast_shares: Vec<Vec<(usize, Scalar)>> =
+        parties.iter().map(|p| p.get_shares()).collect();
+    let mut party_shares: Vec<HashMap<usize, Vec<(usize, Scalar)>>> = (0..num_parties)
+        .map(|i| filter_party_shares(i, &key_owners, &broadcast_shares))
+        .collect();
+
+    // party 2 sends a bad share for key_id 1 to party 0
+    party_shares[0].get_mut(&1).unwrap()[2].1 += Scalar::one();
+    assert_eq!(
+        parties[0].compute_secret(party_shares[0].clone(), &A).err(),
+        Some(DkgError::BadShares(vec![2]))
+    );
+
+    let complaints: Vec<Complaint> = parties
+        .iter()
+        .flat_map(|p| p.get_complaints(&party_shares[p.party_id], &A))
+        .collect();
+    assert_eq!(
+        complaints,
+        vec![Complaint {
+            accuser: 0,
+            accused: 2,
+            key_id: 1
+        }]
+    );
+
+    // an honest justification means the complaint was false, so the accuser is disqualified
+    let justifications: Vec<Justification> = parties
+        .iter()
+        .flat_map(|p| p.justify(&complaints, &party_keys))
+        .collect();
+    let qualified = v2::resolve_complaints(&A, &complaints, &justifications, &party_keys, &[]);
+    assert_eq!(qualified, [1, 2].iter().cloned().collect());
+
+    // a missing justification disqualifies the accused
+    let qualified = v2::resolve_complaints(&A, &complaints, &[], &party_keys, &[]);
+    assert_eq!(qualified, [0, 1].iter().cloned().collect());
+
+    // complaints about other parties' shares are never answered, and disqualify the accuser
+    let forged: Vec<Complaint> = [0, 1, 3]
+        .iter()
+        .map(|key_id| Complaint {
+            accuser: 1,
+            accused: 0,
+            key_id: *key_id,
+        })
+        .collect();
+    assert!(parties[0].justify(&forged, &party_keys).is_empty());
+    assert_eq!(
+        v2::resolve_complaints(&A, &forged, &[], &party_keys, &[]),
+        [0, 2].iter().cloned().collect()
+    );
+
+    let mut public_keys = PubKeyMap::new();
+    for party in parties.iter_mut() {
+        let pks = party
+            .compute_qualified_secret(&qualified, party_shares[party.party_id].clone(), &A)
+            .unwrap();
+        public_keys.extend(pks);
+    }
+    for party in parties.iter_mut() {
+        party.set_group_nonces(B.clone());
+    }
+
+    let mut sig_agg = SignatureAggregator::new_qualified(
+        num_keys,
+        num_parties,
+        threshold,
+        A,
+        B,
+        public_keys,
+        &qualified,
+        &[],
+    )
+    .unwrap();
+
+    let msg = "It was many and many a year ago".as_bytes();
+    let signers = select_parties(&key_owners, threshold, &mut rng);
+    let package = sig_agg.signing_package(&msg, &signers).unwrap();
+    let sig_shares = collect_signatures(&mut parties, &package);
+    let sig = sig_agg.sign(&package, &sig_shares).unwrap();
+
+    assert!(sig.verify(&sig_agg.group_key, &msg, &sig_agg.context));
+}
+
+#[allow(non_snake_case)]
+#[test]
+pub fn test_v2_encrypted_shares() {
+    let num_keys = 4;
+    let threshold = 3;
+    let mut rng = OsRng::default();
+
+    let key_owners = vec![0, 0, 1, 2];
+    let num_parties = 3;
+    let party_keys: Vec<HashSet<usize>> = vec![
+        [0, 1].iter().cloned().collect(),
+        [2].iter().cloned().collect(),
+        [3].iter().cloned().collect(),
+    ];
+
+    let mut parties: Vec<Party> = (0..num_parties)
+        .map(|i| {
+            Party::new(
+                i,
+                party_keys[i].clone(),
+                num_keys,
+                num_parties,
+                threshold,
+                &mut rng,
+            )
+        })
+        .collect();
+    let A: Vec<PolyCommitment> = parties
+        .iter()
+        .map(|p| p.get_poly_commitment(&mut rng))
+        .collect();
+
+    // network keys are per party, but shares are encrypted to the owner of each key_id
+    let network_private_keys: Vec<Scalar> =
+        (0..num_parties).map(|_| Scalar::random(&mut rng)).collect();
+    let party_public_keys: PubKeyMap = network_private_keys
+        .iter()
+        .enumerate()
+        .map(|(i, key)| (i, key * G))
+        .collect();
+    let key_public_keys: PubKeyMap = key_owners
+        .iter()
+        .enumerate()
+        .map(|(key_id, party_id)| (key_id, party_public_keys[party_id]))
+        .collect();
+
+    let encrypted_shares: Vec<EncryptedShare> = parties
+        .iter()
+        .flat_map(|p| {
+            p.get_encrypted_shares(
+                &network_private_keys[p.party_id],
+                &key_public_keys,
+                &mut rng,
+            )
+            .unwrap()
+        })
+        .collect();
+    assert_eq!(encrypted_shares.len(), num_parties * num_keys);
+
+    let mut tampered_shares = encrypted_shares.clone();
+    for es in tampered_shares.iter_mut().filter(|es| es.sender == 1) {
+        es.ciphertext[20] ^= 1;
+    }
+    assert_eq!(
+        parties[0]
+            .decrypt_shares(
+                &network_private_keys[0],
+                &party_public_keys,
+                &tampered_shares
+            )
+            .err(),
+        Some(DkgError::BadEncryptedShares(vec![1]))
+    );
+
+    let broadcast_shares: Vec<Vec<(usize, Scalar)>> =
+        parties.iter().map(|p| p.get_shares()).collect();
+    for party_id in 0..num_parties {
+        let shares = parties[party_id]
+            .decrypt_shares(
+                &network_private_keys[party_id],
+                &party_public_keys,
+                &encrypted_shares,
+            )
+            .unwrap();
+        assert_eq!(
+            shares,
+            filter_party_shares(party_id, &key_owners, &broadcast_shares)
+        );
+        assert!(parties[party_id].compute_secret(shares, &A).is_ok());
+    }
+}
+
+#[allow(non_snake_case)]
+#[test]
+pub fn test_v2_bip340() {
+    let num_keys = 4;
+    let threshold = 3;
+    let num_nonces = 4;
+    let mut rng = OsRng::default();
+    // 32 bytes, so libsecp256k1 can check the signatures too
+    let msg = b"It was many and many a year ago.";
+
+    let key_owners = vec![0, 0, 1, 2];
+    let num_parties = 3;
+    let party_keys: Vec<HashSet<usize>> = vec![
+        [0, 1].iter().cloned().collect(),
+        [2].iter().cloned().collect(),
+        [3].iter().cloned().collect(),
+    ];
+
+    // repeat with new keys and nonces to cover both parities of the group key and R
+    for _ in 0..4 {
+        let mut parties: Vec<Party> = (0..num_parties)
+            .map(|i| {
+                Party::new(
+                    i,
+                    party_keys[i].clone(),
+                    num_keys,
+                    num_parties,
+                    threshold,
+                    &mut rng,
+                )
+            })
+            .collect();
+        let A: Vec<PolyCommitment> = parties
+            .iter()
+            .map(|p| p.get_poly_commitment(&mut rng))
+            .collect();
+        let B: Vec<Vec<PublicNonce>> = parties
+            .iter_mut()
+            .map(|p| p.gen_nonces(num_nonces, &mut rng))
+            .collect();
+        let (_, public_keys) = distribute(&mut parties, &key_owners, &A, &B);
+        let mut sig_agg =
+            SignatureAggregator::new(num_keys, num_parties, threshold, A, B, public_keys).unwrap();
+        let public_key = bip340::x_only(&sig_agg.group_key);
+
+        for _ in 0..2 {
+            let signers = select_parties(&key_owners, threshold, &mut rng);
+            let package = sig_agg.signing_package(msg, &signers).unwrap();
+            let sig_shares: Vec<SignatureShare> = package
+                .signers()
+                .iter()
+                .map(|party_id| SignatureShare {
+                    id: *party_id,
+                    z_i: parties[*party_id].sign_bip340(&package).unwrap(),
+                    key_ids: package.signer_key_ids(*party_id).to_vec(),
+                })
+                .collect();
+
+            let proof = sig_agg.sign_bip340(&package, &sig_shares).unwrap();
+            let bytes = proof.to_bytes();
+            let proof = SchnorrProof::from_bytes(&bytes).unwrap();
+            assert!(proof.verify(&public_key, msg));
+            assert!(bip340::libsecp256k1_verify(&proof, &public_key, msg));
+        }
+
+        // shares for the SHA3 challenge are rejected
+        let signers = select_parties(&key_owners, threshold, &mut rng);
+        let package = sig_agg.signing_package(msg, &signers).unwrap();
+        let sig_shares = collect_signatures(&mut parties, &package);
+        assert!(sig_agg.sign_bip340(&package, &sig_shares).is_err());
+    }
+}
+
+#[allow(non_snake_case)]
+#[test]
+pub fn test_v2_taproot() {
+    let num_keys = 4;
+    let threshold = 3;
+    let num_nonces = 2;
+    let mut rng = OsRng::default();
+    let msg = "It was many and many a year ago".as_bytes();
+
+    let key_owners = vec![0, 0, 1, 2];
+    let num_parties = 3;
+    let party_keys: Vec<HashSet<usize>> = vec![
+        [0, 1].iter().cloned().collect(),
+        [2].iter().cloned().collect(),
+        [3].iter().cloned().collect(),
+    ];
+
+    // repeat with new keys to cover both parities of the internal and output keys
+    for merkle_root in [None, Some([7u8; 32]), None, Some([7u8; 32])] {
+        let mut parties: Vec<Party> = (0..num_parties)
+            .map(|i| {
+                Party::new(
+                    i,
+                    party_keys[i].clone(),
+                    num_keys,
+                    num_parties,
+                    threshold,
+                    &mut rng,
+                )
+            })
+            .collect();
+        let A: Vec<PolyCommitment> = parties
+            .iter()
+            .map(|p| p.get_poly_commitment(&mut rng))
+            .collect();
+        let B: Vec<Vec<PublicNonce>> = parties
+            .iter_mut()
+            .map(|p| p.gen_nonces(num_nonces, &mut rng))
+            .collect();
+        let (_, public_keys) = distribute(&mut parties, &key_owners, &A, &B);
+        let mut sig_agg =
+            SignatureAggregator::new(num_keys, num_parties, threshold, A, B, public_keys).unwrap();
+        let output_key = taproot::output_key(&sig_agg.group_key, merkle_root).unwrap();
+
+        // the second signature uses shares for a different script tree, so it is rejected
+        for (i, sig_root) in [merkle_root, Some([8u8; 32])].iter().enumerate() {
+            let signers = select_parties(&key_owners, threshold, &mut rng);
+            let package = sig_agg.signing_package(&msg, &signers).unwrap();
+            let sig_shares: Vec<SignatureShare> = package
+                .signers()
+                .iter()
+                .map(|party_id| SignatureShare {
+                    id: *party_id,
+                    z_i: parties[*party_id]
+                        .sign_taproot(&package, merkle_root)
+                        .unwrap(),
+                    key_ids: package.signer_key_ids(*party_id).to_vec(),
+                })
+                .collect();
+
+            let result = sig_agg.sign_taproot(&package, &sig_shares, *sig_root);
+            if i == 0 {
+                assert!(result.unwrap().verify(&output_key, &msg));
+            } else {
+                assert!(result.is_err());
+            }
+        }
+    }
+}
+
+#[allow(non_snake_case)]
+#[test]
+pub fn test_v2_context() {
+    let num_keys = 4;
+    let threshold = 3;
+    let num_nonces = 1;
+    let mut rng = OsRng::default();
+    let msg = "It was many and many a year ago".as_bytes();
+    let ctx = "chain 1".as_bytes();
+
+    let key_owners = vec![0, 0, 1, 2];
+    let num_parties = 3;
+    let party_keys: Vec<HashSet<usize>> = vec![
+        [0, 1].iter().cloned().collect(),
+        [2].iter().cloned().collect(),
+        [3].iter().cloned().collect(),
+    ];
+
+    let mut parties: Vec<Party> = (0..num_parties)
+        .map(|i| {
+            let mut party = Party::new(
+                i,
+                party_keys[i].clone(),
+                num_keys,
+                num_parties,
+                threshold,
+                &mut rng,
+            );
+            party.set_context(ctx);
+            party
+        })
+        .collect();
+    let A: Vec<PolyCommitment> = parties
+        .iter()
+        .map(|p| p.get_poly_commitment(&mut rng))
+        .collect();
+    let B: Vec<Vec<PublicNonce>> = parties
+        .iter_mut()
+        .map(|p| p.gen_nonces(num_nonces, &mut rng))
+        .collect();
+    let (_, public_keys) = distribute(&mut parties, &key_owners, &A, &B);
+
+    // proofs of knowledge from one deployment are rejected in another
+    assert_eq!(
+        SignatureAggregator::new(
+            num_keys,
+            num_parties,
+            threshold,
+            A.clone(),
+            B.clone(),
+            public_keys.clone()
+        )
+        .err(),
+        Some(AggregatorError::BadPolyCommitments(vec![0, 1, 2]))
+    );
+    assert!(v2::resolve_complaints(&A, &[], &[], &[], &[]).is_empty());
+
+    let qualified = (0..num_parties).collect();
+    let mut sig_agg = SignatureAggregator::new_qualified(
+        num_keys,
+        num_parties,
+        threshold,
+        A,
+        B,
+        public_keys,
+        &qualified,
+        ctx,
+    )
+    .unwrap();
+
+    let signers = select_parties(&key_owners, threshold, &mut rng);
+    let package = sig_agg.signing_package(&msg, &signers).unwrap();
+    let sig_shares = collect_signatures(&mut parties, &package);
+    let sig = sig_agg.sign(&package, &sig_shares).unwrap();
+    assert!(sig.verify(&sig_agg.group_key, &msg, ctx));
+    assert!(!sig.verify(&sig_agg.group_key, &msg, &[]));
+}
+
+#[allow(non_snake_case)]
+#[test]
+pub fn test_v2_nonce_pool() {
+    let num_keys = 4;
+    let threshold = 3;
+    let num_nonces = 3;
+    let mut rng = OsRng::default();
+    let msg = "It was many and many a year ago".as_bytes();
+
+    let key_owners = vec![0, 0, 1, 2];
+    let num_parties = 3;
+    let party_keys: Vec<HashSet<usize>> = vec![
+        [0, 1].iter().cloned().collect(),
+        [2].iter().cloned().collect(),
+        [3].iter().cloned().collect(),
+    ];
+    let mut parties: Vec<Party> = (0..num_parties)
+        .map(|i| {
+            Party::new(
+                i,
+                party_keys[i].clone(),
+                num_keys,
+                num_parties,
+                threshold,
+                &mut rng,
+            )
+        })
+        .collect();
+    let A: Vec<PolyCommitment> = parties
+        .iter()
+        .map(|p| p.get_poly_commitment(&mut rng))
+        .collect();
+    let (_, public_keys) = distribute(&mut parties, &key_owners, &A, &Vec::new());
+
+    // party 0 keeps its nonces on disk, the others in memory
+    let dir = std::env::temp_dir().join(format!("frost-test-v2-nonce-pool-{}", rng.next_u64()));
+    let mut file_pool = NoncePool::new(FileNonceStore::open(&dir).unwrap());
+    let mut memory_pools: Vec<NoncePool<MemoryNonceStore>> = (1..num_parties)
+        .map(|_| NoncePool::new(MemoryNonceStore::new()))
+        .collect();
+
+    let mut batches = Vec::new();
+    let mut B = Vec::new();
+    let (batch, nonces) = parties[0]
+        .gen_pool_nonces(num_nonces, &mut file_pool, &mut rng)
+        .unwrap();
+    batches.push(batch);
+    B.push(nonces);
+    for (party, pool) in parties[1..].iter().zip(memory_pools.iter_mut()) {
+        let (batch, nonces) = party.gen_pool_nonces(num_nonces, pool, &mut rng).unwrap();
+        batches.push(batch);
+        B.push(nonces);
+    }
+    for party in parties.iter_mut() {
+        party.set_group_nonces(B.clone());
+    }
+    let mut sig_agg =
+        SignatureAggregator::new(num_keys, num_parties, threshold, A, B, public_keys).unwrap();
+
+    // a snapshot of party 0 taken before it signs
+    let snapshot = parties[0].clone();
+
+    let signers: SelectedSigners = (0..num_parties)
+        .map(|i| (i, party_keys[i].clone()))
+        .collect();
+    let package = sig_agg.signing_package(&msg, &signers).unwrap();
+    let index = sig_agg.get_nonce_indices(&signers)[&0];
+    let mut sig_shares = Vec::new();
+    for party_id in 0..num_parties {
+        let z_i = if party_id == 0 {
+            parties[0].sign_from_pool(&package, batches[0], &mut file_pool)
+        } else {
+            parties[party_id].sign_from_pool(
+                &package,
+                batches[party_id],
+                &mut memory_pools[party_id - 1],
+            )
+        };
+        sig_shares.push(SignatureShare {
+            id: party_id,
+            z_i: z_i.unwrap(),
+            key_ids: package.signer_key_ids(party_id).to_vec(),
+        });
+    }
+    let sig = sig_agg.sign(&package, &sig_shares).unwrap();
+    assert!(sig.verify(&sig_agg.group_key, &msg, &sig_agg.context));
+
+    // neither the restored snapshot nor a restarted pool will use the nonce again
+    let mut file_pool = NoncePool::new(FileNonceStore::open(&dir).unwrap());
+    assert_eq!(
+        snapshot.sign_from_pool(&package, batches[0], &mut file_pool),
+        Err(SignerError::NoncePool(NoncePoolError::UsedNonce(
+            batches[0], index
+        )))
+    );
+    assert_eq!(
+        snapshot.sign_from_pool(&package, batches[0] + 1, &mut file_pool),
+        Err(SignerError::NoncePool(NoncePoolError::UnknownNonce(
+            batches[0] + 1,
+            index
+        )))
+    );
+
+    // nor sign with a pool nonce which is not its public nonce, and that leaves it unused
+    let mut tampered = snapshot.clone();
+    let other_nonces = sig_agg.B[1].clone();
+    tampered.set_party_nonces(0, other_nonces.clone());
+    let id = NonceId {
+        batch: batches[0],
+        index: index + 1,
+    };
+    let tampered_package = SigningPackage::with_key_ids(
+        &msg,
+        &[
+            (0, other_nonces[id.index].clone()),
+            (1, other_nonces[0].clone()),
+        ],
+        &[(0, vec![0, 1]), (1, vec![2])],
+        threshold,
+    )
+    .unwrap();
+    assert_eq!(
+        tampered.sign_from_pool(&tampered_package, id.batch, &mut file_pool),
+        Err(SignerError::BadPublicNonce(0))
+    );
+    assert!(file_pool.public_nonce(&id).is_ok());
+
+    // BIP-340 and taproot shares use up pool nonces the same way
+    let merkle_root = Some([7u8; 32]);
+    let mut pool_shares = |package: &SigningPackage, taproot: bool| -> Vec<SignatureShare> {
+        (0..num_parties)
+            .map(|party_id| {
+                let batch = batches[party_id];
+                let z_i = match (party_id, taproot) {
+                    (0, false) => parties[0].sign_bip340_from_pool(package, batch, &mut file_pool),
+                    (0, true) => parties[0].sign_taproot_from_pool(
+                        package,
+                        merkle_root,
+                        batch,
+                        &mut file_pool,
+                    ),
+                    (_, false) => parties[party_id].sign_bip340_from_pool(
+                        package,
+                        batch,
+                        &mut memory_pools[party_id - 1],
+                    ),
+                    (_, true) => parties[party_id].sign_taproot_from_pool(
+                        package,
+                        merkle_root,
+                        batch,
+                        &mut memory_pools[party_id - 1],
+                    ),
+                };
+                SignatureShare {
+                    id: party_id,
+                    z_i: z_i.unwrap(),
+                    key_ids: package.signer_key_ids(party_id).to_vec(),
+                }
+            })
+            .collect()
+    };
+    let bip340_package = sig_agg.signing_package(&msg, &signers).unwrap();
+    let index = sig_agg.get_nonce_indices(&signers)[&0];
+    let sig_shares = pool_shares(&bip340_package, false);
+    let proof = sig_agg.sign_bip340(&bip340_package, &sig_shares).unwrap();
+    assert!(proof.verify(&bip340::x_only(&sig_agg.group_key), &msg));
+
+    let package = sig_agg.signing_package(&msg, &signers).unwrap();
+    let sig_shares = pool_shares(&package, true);
+    let proof = sig_agg
+        .sign_taproot(&package, &sig_shares, merkle_root)
+        .unwrap();
+    assert!(proof.verify(
+        &taproot::output_key(&sig_agg.group_key, merkle_root).unwrap(),
+        &msg
+    ));
+
+    assert_eq!(
+        parties[0].sign_taproot_from_pool(&bip340_package, merkle_root, batches[0], &mut file_pool),
+        Err(SignerError::NoncePool(NoncePoolError::UsedNonce(
+            batches[0], index
+        )))
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[allow(non_snake_case)]
+#[test]
+pub fn test_v2_nonce_cursors() {
+    let num_keys = 4;
+    let threshold = 3;
+    let num_nonces = 3;
+    let mut rng = OsRng::default();
+    let msg = "It was many and many a year ago".as_bytes();
+
+    let key_owners = vec![0, 0, 1, 2];
+    let num_parties = 3;
+    let party_keys: Vec<HashSet<usize>> = vec![
+        [0, 1].iter().cloned().collect(),
+        [2].iter().cloned().collect(),
+        [3].iter().cloned().collect(),
+    ];
+    let mut parties: Vec<Party> = (0..num_parties)
+        .map(|i| {
+            Party::new(
+                i,
+                party_keys[i].clone(),
+                num_keys,
+                num_parties,
+                threshold,
+                &mut rng,
+            )
+        })
+        .collect();
+    let A: Vec<PolyCommitment> = parties
+        .iter()
+        .map(|p| p.get_poly_commitment(&mut rng))
+        .collect();
+    let B: Vec<Vec<PublicNonce>> = parties
+        .iter_mut()
+        .map(|p| p.gen_nonces(num_nonces, &mut rng))
+        .collect();
+    let (_, public_keys) = distribute(&mut parties, &key_owners, &A, &B);
+    let mut sig_agg =
+        SignatureAggregator::new(num_keys, num_parties, threshold, A, B, public_keys).unwrap();
+
+    // party 0 signs every round, and parties 1 and 2 take turns
+    let with_party = |i: usize| -> SelectedSigners {
+        [(0, party_keys[0].clone()), (i, party_keys[i].clone())]
+            .into_iter()
+            .collect()
+    };
+    let sign_round = |parties: &mut [Party], sig_agg: &mut SignatureAggregator, i: usize| {
+        let signers = with_party(i);
+        let package = sig_agg.signing_package(&msg, &signers).unwrap();
+        let nonce_indices = sig_agg.get_nonce_indices(&signers);
+        let sig_shares = collect_signatures(parties, &package);
+        let sig = sig_agg.sign(&package, &sig_shares).unwrap();
+        assert!(sig.verify(&sig_agg.group_key, &msg, &sig_agg.context));
+        nonce_indices
+    };
+
+    assert_eq!(
+        sign_round(&mut parties, &mut sig_agg, 1),
+        [(0, 0), (1, 0)].into_iter().collect()
+    );
+    assert_eq!(
+        sign_round(&mut parties, &mut sig_agg, 2),
+        [(0, 1), (2, 0)].into_iter().collect()
+    );
+    assert_eq!(sig_agg.get_unused_nonces(0).len(), 1);
+    assert_eq!(sig_agg.get_unused_nonces(1).len(), 2);
+    assert_eq!(sig_agg.get_unused_nonces(2).len(), 2);
+
+    // only the party which is running low is asked for more
+    let requests = sig_agg.nonce_requests();
+    assert_eq!(
+        requests,
+        vec![NonceRequest {
+            party_id: 0,
+            num_nonces
+        }]
+    );
+    for request in requests {
+        reset_nonce(
+            &mut parties,
+            &mut sig_agg,
+            request.party_id,
+            request.num_nonces,
+            &mut rng,
+        );
+    }
+    assert!(sig_agg.nonce_requests().is_empty());
+    assert_eq!(
+        sign_round(&mut parties, &mut sig_agg, 1),
+        [(0, 0), (1, 1)].into_iter().collect()
+    );
+
+    // a new B resets every cursor, and its rows can be longer than the number of parties
+    let B: Vec<Vec<PublicNonce>> = parties
+        .iter_mut()
+        .map(|p| p.gen_nonces(num_parties as u32 + 1, &mut rng))
+        .collect();
+    for party in parties.iter_mut() {
+        party.set_group_nonces(B.clone());
+    }
+    sig_agg.set_group_nonces(B);
+    for _ in 0..num_parties + 1 {
+        sign_round(&mut parties, &mut sig_agg, 1);
+    }
+
+    assert_eq!(
+        sig_agg.signing_package(&msg, &with_party(1)).err(),
+        Some(AggregatorError::OutOfNonces(vec![0, 1]))
+    );
+}
+
+#[allow(non_snake_case)]
+#[test]
+pub fn test_v2_batch() {
+    let num_keys = 4;
+    let threshold = 3;
+    let num_nonces = 9;
+    let mut rng = OsRng::default();
+    let msgs: Vec<&[u8]> = vec![
+        "It was many and many a year ago".as_bytes(),
+        "In a kingdom by the sea".as_bytes(),
+        "That a maiden there lived whom you may know".as_bytes(),
+    ];
+
+    let key_owners = vec![0, 0, 1, 2];
+    let num_parties = 3;
+    let party_keys: Vec<HashSet<usize>> = vec![
+        [0, 1].iter().cloned().collect(),
+        [2].iter().cloned().collect(),
+        [3].iter().cloned().collect(),
+    ];
+
+    let mut parties: Vec<Party> = (0..num_parties)
+        .map(|i| {
+            Party::new(
+                i,
+                party_keys[i].clone(),
+                num_keys,
+                num_parties,
+                threshold,
+                &mut rng,
+            )
+        })
+        .collect();
+    let A: Vec<PolyCommitment> = parties
+        .iter()
+        .map(|p| p.get_poly_commitment(&mut rng))
+        .collect();
+    let B: Vec<Vec<PublicNonce>> = parties
+        .iter_mut()
+        .map(|p| p.gen_nonces(num_nonces, &mut rng))
+        .collect();
+    let (_, public_keys) = distribute(&mut parties, &key_owners, &A, &B);
+    let mut sig_agg =
+        SignatureAggregator::new(num_keys, num_parties, threshold, A, B, public_keys).unwrap();
+
+    let signers: SelectedSigners = [(0, party_keys[0].clone()), (1, party_keys[1].clone())]
+        .into_iter()
+        .collect();
+    let packages = sig_agg.signing_packages(&msgs, &signers).unwrap();
+    assert_eq!(packages.len(), msgs.len());
+
+    // a party refuses to sign two messages with one nonce, or a batch of different signers
+    let same_nonce = vec![packages[0].clone(), packages[0].clone()];
+    assert_eq!(
+        parties[0].sign_batch(&same_nonce).err(),
+        Some(SignerError::DuplicateNonce(0))
+    );
+    let all_signers: SelectedSigners = (0..num_parties)
+        .map(|i| (i, party_keys[i].clone()))
+        .collect();
+    let other_signers = sig_agg.signing_package(&msgs[0], &all_signers).unwrap();
+    let mismatched = vec![packages[0].clone(), other_signers];
+    assert_eq!(
+        parties[0].sign_batch(&mismatched).err(),
+        Some(SignerError::BadSigningPackage(
+            SigningPackageError::MismatchedBatch
+        ))
+    );
+
+    // a bad share for one message is caught, and the nonces are burned
+    let mut sig_shares: Vec<BatchSignatureShare> = [0, 1]
+        .iter()
+        .map(|i| parties[*i].sign_batch(&packages).unwrap())
+        .collect();
+    sig_shares[1].z_i[2] += Scalar::one();
+    assert_eq!(
+        sig_agg.sign_batch(&packages, &sig_shares, &mut rng).err(),
+        Some(AggregatorError::BadPartySigs(vec![1]))
+    );
+    assert_eq!(
+        sig_agg.sign_batch(&packages, &sig_shares, &mut rng).err(),
+        Some(AggregatorError::BadPackageNonces(vec![0, 1]))
+    );
+
+    let packages = sig_agg.signing_packages(&msgs, &signers).unwrap();
+    let sig_shares: Vec<BatchSignatureShare> = [0, 1]
+        .iter()
+        .map(|i| parties[*i].sign_batch(&packages).unwrap())
+        .collect();
+    let sigs = sig_agg
+        .sign_batch(&packages, &sig_shares, &mut rng)
+        .unwrap();
+    for (sig, msg) in sigs.iter().zip(&msgs) {
+        assert!(sig.verify(&sig_agg.group_key, msg, &[]));
+    }
+
+    // the nonces can't sign a second batch, even one for other messages
+    let replayed: Vec<SigningPackage> = zip(&packages, msgs.iter().rev())
+        .map(|(package, msg)| {
+            let nonces: Vec<(usize, PublicNonce)> =
+                zip(package.signers(), package.nonces()).collect();
+            let key_ids: Vec<(usize, Vec<usize>)> = package
+                .signers()
+                .iter()
+                .map(|i| (*i, package.signer_key_ids(*i).to_vec()))
+                .collect();
+            SigningPackage::with_key_ids(msg, &nonces, &key_ids, threshold).unwrap()
+        })
+        .collect();
+    assert_eq!(
+        parties[0].sign_batch(&replayed).err(),
+        Some(SignerError::BadPublicNonce(0))
+    );
+    let used_packages = packages;
+
+    let merkle_root = Some([7u8; 32]);
+    let output_key = taproot::output_key(&sig_agg.group_key, merkle_root).unwrap();
+    let packages = sig_agg.signing_packages(&msgs, &signers).unwrap();
+
+    // a batch with one used nonce is refused without using up the others
+    let mixed = vec![packages[0].clone(), used_packages[1].clone()];
+    assert_eq!(
+        parties[0].sign_batch_taproot(&mixed, merkle_root).err(),
+        Some(SignerError::BadPublicNonce(0))
+    );
+    let sig_shares: Vec<BatchSignatureShare> = [0, 1]
+        .iter()
+        .map(|i| {
+            parties[*i]
+                .sign_batch_taproot(&packages, merkle_root)
+                .unwrap()
+        })
+        .collect();
+    let proofs = sig_agg
+        .sign_batch_taproot(&packages, &sig_shares, merkle_root, &mut rng)
+        .unwrap();
+    for (proof, msg) in proofs.iter().zip(&msgs) {
+        assert!(proof.verify(&output_key, msg));
+    }
+
+    assert_eq!(
+        sig_agg.signing_packages(&msgs[..1], &signers).err(),
+        Some(AggregatorError::OutOfNonces(vec![0, 1]))
+    );
+}
+
+// the group most Dkg tests run: party 0 holds two of the 4 key_ids, and the threshold is 3
+fn party_keys() -> Vec<HashSet<usize>> {
+    vec![
+        [0, 1].iter().cloned().collect(),
+        [2].iter().cloned().collect(),
+        [3].iter().cloned().collect(),
+    ]
+}
+
+// the party which holds key_id
+fn owner(party_keys: &[HashSet<usize>], key_id: usize) -> usize {
+    party_keys.iter().position(|k| k.contains(&key_id)).unwrap()
+}
+
+fn new_dkgs(
+    party_keys: &[HashSet<usize>],
+    num_keys: usize,
+    threshold: usize,
+    scheme: DkgScheme,
+    ctx: &[u8],
+) -> Vec<v2::Dkg> {
+    let mut rng = OsRng::default();
+    let num_parties = party_keys.len();
+    (0..num_parties)
+        .map(|i| {
+            v2::Dkg::new(
+                i,
+                party_keys[i].clone(),
+                num_keys,
+                num_parties,
+                threshold,
+                scheme,
+                ctx,
+                &mut rng,
+            )
+        })
+        .collect()
+}
+
+// deliver every commitment and share, which leaves a Feldman Dkg ready to finish and a
+// Pedersen one ready to reveal its commitments
+fn run_rounds(
+    party_keys: &[HashSet<usize>],
+    num_keys: usize,
+    threshold: usize,
+    scheme: DkgScheme,
+    ctx: &[u8],
+) -> Vec<v2::Dkg> {
+    let mut dkgs = new_dkgs(party_keys, num_keys, threshold, scheme, ctx);
+    match scheme {
+        DkgScheme::Feldman => {
+            let commitments: Vec<DkgCommitment> =
+                dkgs.iter_mut().map(|d| d.commitment().unwrap()).collect();
+            for dkg in dkgs.iter_mut() {
+                for commitment in &commitments {
+                    dkg.receive_commitment(commitment).unwrap();
+                }
+            }
+        }
+        DkgScheme::Pedersen => {
+            let commitments: Vec<PedersenCommitment> = dkgs
+                .iter()
+                .map(|d| d.pedersen_commitment().unwrap())
+                .collect();
+            for dkg in dkgs.iter_mut() {
+                for commitment in &commitments {
+                    dkg.receive_pedersen_commitment(commitment).unwrap();
+                }
+            }
+        }
+    }
+    let shares: Vec<DkgShare> = dkgs.iter_mut().flat_map(|d| d.shares().unwrap()).collect();
+    for share in &shares {
+        dkgs[owner(party_keys, share.key_id)]
+            .receive_share(share)
+            .unwrap();
+    }
+    dkgs
+}
+
+// run a Feldman Dkg with every message delivered
+fn run_dkg(
+    party_keys: &[HashSet<usize>],
+    num_keys: usize,
+    threshold: usize,
+    ctx: &[u8],
+) -> Vec<v2::KeyPackage> {
+    run_rounds(party_keys, num_keys, threshold, DkgScheme::Feldman, ctx)
+        .iter_mut()
+        .map(|d| d.finish().unwrap())
+        .collect()
+}
+
+// the sums the helpers send to whoever repairs or enrolls key_id
+fn repair_sums(
+    key_packages: &[v2::KeyPackage],
+    key_id: usize,
+    helpers: &[(usize, Vec<usize>)],
+) -> Vec<RepairSum> {
+    let mut rng = OsRng::default();
+    let helper_ids: Vec<usize> = helpers.iter().map(|(i, _)| *i).collect();
+    let parts: Vec<RepairShare> = helper_ids
+        .iter()
+        .flat_map(|i| {
+            Party::from_key_package(&key_packages[*i])
+                .repair_shares(key_id, helpers, &mut rng)
+                .unwrap()
+        })
+        .collect();
+    helper_ids
+        .iter()
+        .map(|i| {
+            let received: Vec<RepairShare> = parts
+                .iter()
+                .filter(|p| p.recipient == *i)
+                .cloned()
+                .collect();
+            dkg::repair_sum(*i, &helper_ids, key_id, &received).unwrap()
+        })
+        .collect()
+}
+
+// move the group key of key_packages to the parties holding party_keys
+fn run_reshare(
+    key_packages: &[v2::KeyPackage],
+    dealers: &[(usize, Vec<usize>)],
+    party_keys: &[HashSet<usize>],
+    num_keys: usize,
+    threshold: usize,
+    ctx: &[u8],
+) -> Vec<v2::KeyPackage> {
+    let mut rng = OsRng::default();
+    let old = &key_packages[0];
+    let num_parties = party_keys.len();
+    let mut reshares: Vec<v2::Reshare> = (0..num_parties)
+        .map(|i| {
+            v2::Reshare::new(
+                i,
+                party_keys[i].clone(),
+                num_keys,
+                num_parties,
+                threshold,
+                old.threshold,
+                dealers,
+                &old.group_key,
+                &old.public_keys,
+                ctx,
+            )
+            .unwrap()
+        })
+        .collect();
+    let dealer_key_ids: Vec<usize> = dealers.iter().flat_map(|(_, k)| k.clone()).collect();
+    let dealt: Vec<(DkgCommitment, Vec<DkgShare>)> = dealers
+        .iter()
+        .map(|(i, _)| {
+            Party::from_key_package(&key_packages[*i])
+                .reshare(&dealer_key_ids, num_keys, threshold, &mut rng)
+                .unwrap()
+        })
+        .collect();
+    for reshare in reshares.iter_mut() {
+        for (commitment, _) in &dealt {
+            reshare.receive_commitment(commitment).unwrap();
+        }
+    }
+    for share in dealt.iter().flat_map(|(_, shares)| shares) {
+        reshares[owner(party_keys, share.key_id)]
+            .receive_share(share)
+            .unwrap();
+    }
+    reshares.iter_mut().map(|r| r.finish().unwrap()).collect()
+}
+
+#[test]
+pub fn test_v2_dkg() {
+    let (num_keys, num_parties, threshold) = (4, 3, 3);
+    let ctx = "chain 1".as_bytes();
+    let party_keys = party_keys();
+
+    let mut dkgs = new_dkgs(&party_keys, num_keys, threshold, DkgScheme::Feldman, ctx);
+    let commitments: Vec<DkgCommitment> =
+        dkgs.iter_mut().map(|d| d.commitment().unwrap()).collect();
+
+    assert_eq!(
+        dkgs[0].shares().err(),
+        Some(DkgError::MissingPolyCommitments(vec![1, 2]))
+    );
+    for dkg in dkgs.iter_mut() {
+        for commitment in &commitments {
+            dkg.receive_commitment(commitment).unwrap();
+        }
+    }
+
+    let shares: Vec<DkgShare> = dkgs.iter_mut().flat_map(|d| d.shares().unwrap()).collect();
+    assert_eq!(
+        dkgs[0].receive_share(shares.iter().find(|s| s.key_id == 2).unwrap()),
+        Err(DkgError::WrongKeyId(2))
+    );
+
+    // party 0 is still waiting on party 2 until it has shares for both its key_ids
+    for share in shares.iter().filter(|s| !(s.sender == 2 && s.key_id == 1)) {
+        dkgs[owner(&party_keys, share.key_id)]
+            .receive_share(share)
+            .unwrap();
+    }
+    assert_eq!(dkgs[0].missing(), vec![2]);
+    assert_eq!(
+        dkgs[0].finish().err(),
+        Some(DkgError::MissingShares(vec![2]))
+    );
+    for share in shares.iter().filter(|s| s.sender == 2 && s.key_id == 1) {
+        dkgs[0].receive_share(share).unwrap();
+    }
+
+    let key_packages: Vec<v2::KeyPackage> = dkgs.iter_mut().map(|d| d.finish().unwrap()).collect();
+    let qualified = (0..num_parties).collect();
+    let sig_agg = SignatureAggregator::new_qualified(
+        num_keys,
+        num_parties,
+        threshold,
+        key_packages[0].poly_commitments.clone(),
+        vec![Vec::new(); num_parties],
+        key_packages[0].public_keys.clone(),
+        &qualified,
+        ctx,
+    )
+    .unwrap();
+    assert_eq!(sig_agg.group_key, key_packages[0].group_key);
+
+    assert!(dkg_sign(&key_packages, ctx));
+}
+
+#[test]
+pub fn test_v2_pedersen_dkg() {
+    let mut rng = OsRng::default();
+    let ctx = "chain 1".as_bytes();
+    let party_keys = party_keys();
+
+    let mut dkgs = new_dkgs(&party_keys, 4, 3, DkgScheme::Pedersen, ctx);
+    let pedersen_commitments: Vec<PedersenCommitment> = dkgs
+        .iter()
+        .map(|d| d.pedersen_commitment().unwrap())
+        .collect();
+    assert_eq!(
+        dkgs[0].commitment().err(),
+        Some(DkgError::OutOfOrder("commitment", "commitments"))
+    );
+    for dkg in dkgs.iter_mut() {
+        for commitment in &pedersen_commitments {
+            dkg.receive_pedersen_commitment(commitment).unwrap();
+        }
+    }
+
+    let shares: Vec<DkgShare> = dkgs.iter_mut().flat_map(|d| d.shares().unwrap()).collect();
+    let mut bad_share = shares.iter().find(|s| s.key_id == 1).unwrap().clone();
+    bad_share.blinding = Some(Scalar::from(1));
+    assert_eq!(
+        dkgs[0].receive_share(&bad_share),
+        Err(DkgError::BadShares(vec![bad_share.sender]))
+    );
+    for share in &shares {
+        dkgs[owner(&party_keys, share.key_id)]
+            .receive_share(share)
+            .unwrap();
+    }
+
+    let commitments: Vec<DkgCommitment> =
+        dkgs.iter_mut().map(|d| d.commitment().unwrap()).collect();
+    assert_eq!(dkgs[0].state(), DkgState::Extraction);
+
+    // party 1 reveals a different polynomial than the one it dealt party 0's shares from
+    let mut impostor = v2::Dkg::new(
+        1,
+        party_keys[1].clone(),
+        4,
+        3,
+        3,
+        DkgScheme::Feldman,
+        ctx,
+        &mut rng,
+    );
+    assert_eq!(
+        dkgs[0].receive_commitment(&impostor.commitment().unwrap()),
+        Err(DkgError::BadShares(vec![1]))
+    );
+
+    for dkg in dkgs.iter_mut() {
+        for commitment in &commitments {
+            dkg.receive_commitment(commitment).unwrap();
+        }
+    }
+    let key_packages: Vec<v2::KeyPackage> = dkgs.iter_mut().map(|d| d.finish().unwrap()).collect();
+
+    assert!(dkg_sign(&key_packages, ctx));
+}
+
+#[test]
+pub fn test_v2_pedersen_dkg_recovery() {
+    let threshold = 3;
+    let ctx = "chain 1".as_bytes();
+    let mut dkgs = run_rounds(&party_keys(), 4, threshold, DkgScheme::Pedersen, ctx);
+
+    // party 2 sees every other commitment, then withholds its own
+    let commitments: Vec<DkgCommitment> =
+        dkgs.iter_mut().map(|d| d.commitment().unwrap()).collect();
+    for dkg in dkgs.iter_mut() {
+        for commitment in &commitments[..2] {
+            dkg.receive_commitment(commitment).unwrap();
+        }
+    }
+    assert_eq!(
+        dkgs[0].finish().err(),
+        Some(DkgError::MissingPolyCommitments(vec![2]))
+    );
+
+    // parties 0 and 1 hold three key_ids between them, which is enough to rebuild it
+    let recovery_shares: Vec<RecoveryShare> = dkgs[..2]
+        .iter()
+        .flat_map(|d| d.recovery_shares(&[2]).unwrap())
+        .collect();
+    assert_eq!(recovery_shares.len(), threshold);
+    for dkg in dkgs.iter_mut() {
+        for share in &recovery_shares {
+            dkg.receive_recovery_share(share).unwrap();
+        }
+    }
+    let key_packages: Vec<v2::KeyPackage> = dkgs.iter_mut().map(|d| d.finish().unwrap()).collect();
+
+    let group_key = commitments
+        .iter()
+        .fold(Point::zero(), |key, c| key + c.commitment.A[0]);
+    assert!(key_packages.iter().all(|k| k.group_key == group_key));
+    assert!(dkg_sign(&key_packages, ctx));
+}
+
+#[test]
+#[allow(non_snake_case)]
+pub fn test_v2_refresh() {
+    let mut rng = OsRng::default();
+    let ctx = "chain 1".as_bytes();
+    let party_keys = party_keys();
+    let old_packages = run_dkg(&party_keys, 4, 3, ctx);
+
+    let mut refreshes: Vec<v2::Refresh> = old_packages
+        .iter()
+        .map(|k| v2::Refresh::new(k.clone(), &mut rng))
+        .collect();
+    let commitments: Vec<RefreshCommitment> = refreshes.iter().map(|r| r.commitment()).collect();
+    for refresh in refreshes.iter_mut() {
+        for commitment in &commitments {
+            refresh.receive_commitment(commitment).unwrap();
+        }
+    }
+    let shares: Vec<DkgShare> = refreshes
+        .iter_mut()
+        .flat_map(|r| r.shares().unwrap())
+        .collect();
+    for share in &shares {
+        refreshes[owner(&party_keys, share.key_id)]
+            .receive_share(share)
+            .unwrap();
+    }
+    let key_packages: Vec<v2::KeyPackage> =
+        refreshes.iter_mut().map(|r| r.finish().unwrap()).collect();
+
+    for (old, new) in zip(&old_packages, &key_packages) {
+        assert_eq!(new.group_key, old.group_key);
+        for key_id in &new.state.key_ids {
+            let private_key = new.state.private_keys[key_id];
+            assert!(private_key != old.state.private_keys[key_id]);
+            assert_eq!(private_key * G, new.public_keys[key_id]);
+            assert!(old.state.private_keys[key_id] * G != new.public_keys[key_id]);
+        }
+        assert_eq!(new.state.polynomial.data(), old.state.polynomial.data());
+    }
+    assert!(dkg_sign(&key_packages, ctx));
+
+    // the refreshed keys can still repair a lost share: parties 0 and 1 help party 2
+    let helpers = vec![(0, vec![0, 1]), (1, vec![2])];
+    let sums = repair_sums(&key_packages, 3, &helpers);
+    let A = &key_packages[0].poly_commitments;
+    let state = v2::PartyState::repair(party_keys[2].clone(), &[0, 1], &sums, A, ctx).unwrap();
+    assert_eq!(
+        state.private_keys[&3],
+        key_packages[2].state.private_keys[&3]
+    );
+    let mut repaired = key_packages.clone();
+    repaired[2].state = state;
+    assert!(dkg_sign(&repaired, ctx));
+
+    // and reshare the same group key, here to the same key_ids
+    let reshared = run_reshare(&key_packages, &helpers, &party_keys, 4, 3, ctx);
+    assert_eq!(reshared[0].group_key, key_packages[0].group_key);
+    assert!(dkg_sign(&reshared, ctx));
+}
+
+#[test]
+pub fn test_v2_reshare() {
+    let mut rng = OsRng::default();
+    let ctx = "chain 1".as_bytes();
+    let old_packages = run_dkg(&party_keys(), 4, 3, ctx);
+    let old_parties: Vec<Party> = old_packages.iter().map(Party::from_key_package).collect();
+    let group_key = old_packages[0].group_key;
+
+    // parties 0 and 2 hold 3 of the 4 key_ids, and move the group to 4 of 5 key_ids
+    let dealers = vec![(0, vec![0, 1]), (2, vec![3])];
+    let (num_keys, threshold) = (5, 4);
+    let new_party_keys: Vec<HashSet<usize>> = vec![
+        [0, 1, 2].iter().cloned().collect(),
+        [3, 4].iter().cloned().collect(),
+    ];
+    let key_packages = run_reshare(
+        &old_packages,
+        &dealers,
+        &new_party_keys,
+        num_keys,
+        threshold,
+        ctx,
+    );
+    for key_package in &key_packages {
+        assert_eq!(key_package.group_key, group_key);
+    }
+    assert!(dkg_sign(&key_packages, ctx));
+
+    // dealers holding fewer than the old threshold of key_ids, or a dealer or key_id given twice
+    let new_reshare = |dealers: &[(usize, Vec<usize>)]| {
+        v2::Reshare::new(
+            0,
+            new_party_keys[0].clone(),
+            num_keys,
+            new_party_keys.len(),
+            threshold,
+            3,
+            dealers,
+            &group_key,
+            &old_packages[0].public_keys,
+            ctx,
+        )
+    };
+    assert_eq!(
+        new_reshare(&[(0, vec![0, 1])]).err(),
+        Some(DkgError::BelowThreshold(3, 2))
+    );
+    assert_eq!(
+        new_reshare(&[(0, vec![0, 1]), (0, vec![0, 1])]).err(),
+        Some(DkgError::DuplicateMessage(0))
+    );
+    assert_eq!(
+        new_reshare(&[(0, vec![0, 1]), (2, vec![1, 3])]).err(),
+        Some(DkgError::DuplicateMessage(2))
+    );
+
+    // a dealer which deals with the wrong lambdas is caught
+    let (bad_commitment, _) = old_parties[0]
+        .reshare(&[0, 1, 2], num_keys, threshold, &mut rng)
+        .unwrap();
+    assert_eq!(
+        new_reshare(&dealers)
+            .unwrap()
+            .receive_commitment(&bad_commitment),
+        Err(DkgError::BadPolyCommitments(vec![0]))
+    );
+
+    // or one which is asked to deal without its own key_ids
+    assert_eq!(
+        old_parties[2]
+            .reshare(&[0, 1, 2], num_keys, threshold, &mut rng)
+            .err(),
+        Some(DkgError::WrongKeyId(3))
+    );
+}
+
+#[test]
+#[allow(non_snake_case)]
+pub fn test_v2_repair() {
+    let mut rng = OsRng::default();
+    let ctx = "chain 1".as_bytes();
+    let party_keys: Vec<HashSet<usize>> = vec![
+        [0, 1].iter().cloned().collect(),
+        [2].iter().cloned().collect(),
+        [3, 4].iter().cloned().collect(),
+    ];
+    let mut key_packages = run_dkg(&party_keys, 5, 3, ctx);
+    let parties: Vec<Party> = key_packages.iter().map(Party::from_key_package).collect();
+    let A = &key_packages[0].poly_commitments;
+
+    // party 0 lost the state for both its key_ids, and parties 1 and 2 help it
+    let victim = 0;
+    let helpers = vec![(1, vec![2]), (2, vec![3, 4])];
+    let helper_ids = [1, 2];
+
+    // a party which is not a helper, or is listed with key_ids it does not hold, can't help
+    assert_eq!(
+        parties[0].repair_shares(0, &helpers, &mut rng).err(),
+        Some(DkgError::UnknownParty(0))
+    );
+    assert_eq!(
+        parties[1]
+            .repair_shares(0, &[(1, vec![2, 3]), (2, vec![4])], &mut rng)
+            .err(),
+        Some(DkgError::WrongKeyId(3))
+    );
+
+    let sums: Vec<RepairSum> = party_keys[victim]
+        .iter()
+        .flat_map(|key_id| repair_sums(&key_packages, *key_id, &helpers))
+        .collect();
+
+    assert_eq!(
+        v2::PartyState::repair(party_keys[victim].clone(), &helper_ids, &sums[1..], A, ctx).err(),
+        Some(DkgError::MissingShares(vec![1]))
+    );
+    assert_eq!(
+        v2::PartyState::repair(HashSet::new(), &[], &[], A, ctx).err(),
+        Some(DkgError::NoHelpers)
+    );
+    assert_eq!(
+        v2::PartyState::repair(party_keys[victim].clone(), &helper_ids, &sums, &[], ctx).err(),
+        Some(DkgError::NoPolyCommitments)
+    );
+    let state =
+        v2::PartyState::repair(party_keys[victim].clone(), &helper_ids, &sums, A, ctx).unwrap();
+    for key_id in &party_keys[victim] {
+        assert_eq!(
+            state.private_keys[key_id],
+            key_packages[victim].state.private_keys[key_id]
+        );
+    }
+
+    key_packages[victim].state = state;
+    assert!(dkg_sign(&key_packages, ctx));
+}
+
+#[test]
+pub fn test_v2_enroll() {
+    let ctx = "chain 1".as_bytes();
+    let mut key_packages = run_dkg(&party_keys(), 4, 3, ctx);
+    let group_key = key_packages[0].group_key;
+    let mut sig_agg =
+        SignatureAggregator::from_key_package(&key_packages[0], vec![Vec::new(); 3]).unwrap();
+
+    // parties 0 and 1 enroll key_id 4 for party 2
+    let new_key_id = 4;
+    let helpers = vec![(0, vec![0, 1]), (1, vec![2])];
+    let sums = repair_sums(&key_packages, new_key_id, &helpers);
+
+    assert_eq!(
+        key_packages[0].add_key_ids(&[5]),
+        Err(DkgError::WrongKeyId(5))
+    );
+    assert_eq!(sig_agg.add_key_ids(&[5]), Err(AggregatorError::BadKeyId(5)));
+    for (i, key_package) in key_packages.iter_mut().enumerate() {
+        if i == 2 {
+            key_package.enroll(&[new_key_id], &[0, 1], &sums).unwrap();
+        } else {
+            key_package.add_key_ids(&[new_key_id]).unwrap();
+        }
+    }
+    sig_agg.add_key_ids(&[new_key_id]).unwrap();
+
+    assert_eq!(key_packages[2].num_keys, 5);
+    assert_eq!(
+        key_packages[2].state.private_keys[&new_key_id] * G,
+        key_packages[0].public_keys[&new_key_id]
+    );
+    assert_eq!(sig_agg.public_keys, key_packages[0].public_keys);
+    for key_package in &key_packages {
+        assert_eq!(key_package.group_key, group_key);
+    }
+    assert!(dkg_sign(&key_packages, ctx));
+}
+
+#[test]
+pub fn test_v2_trusted_dealer() {
+    let mut rng = OsRng::default();
+    let ctx = "chain 1".as_bytes();
+    let party_keys = party_keys();
+    let (num_keys, num_parties, threshold) = (4, 3, 3);
+
+    let secret = Scalar::random(&mut rng);
+    let (commitment, shares) = dkg::trusted_deal(Some(secret), num_keys, threshold, ctx, &mut rng);
+    let party_shares = |key_ids: &HashSet<usize>| -> Vec<DkgShare> {
+        shares
+            .iter()
+            .filter(|share| key_ids.contains(&share.key_id))
+            .cloned()
+            .collect()
+    };
+
+    let key_packages: Vec<v2::KeyPackage> = party_keys
+        .iter()
+        .enumerate()
+        .map(|(i, key_ids)| {
+            v2::KeyPackage::from_dealer(
+                i,
+                key_ids.clone(),
+                num_keys,
+                num_parties,
+                &commitment,
+                &party_shares(key_ids),
+                ctx,
+            )
+            .unwrap()
+        })
+        .collect();
+    assert_eq!(key_packages[0].group_key, secret * G);
+    assert!(dkg_sign(&key_packages, ctx));
+
+    // each of the party's key ids needs its own share, and no others
+    let from_dealer = |shares: &[DkgShare]| {
+        v2::KeyPackage::from_dealer(
+            0,
+            party_keys[0].clone(),
+            num_keys,
+            num_parties,
+            &commitment,
+            shares,
+            ctx,
+        )
+        .err()
+    };
+    assert_eq!(
+        from_dealer(&shares[..1]),
+        Some(DkgError::MissingShares(vec![0]))
+    );
+    assert_eq!(from_dealer(&shares), Some(DkgError::WrongKeyId(2)));
+    let mut bad_shares = party_shares(&party_keys[0]);
+    bad_shares[1].share += Scalar::one();
+    assert_eq!(from_dealer(&bad_shares), Some(DkgError::BadShares(vec![0])));
+}
+
+#[cfg(feature = "reconstruct")]
+#[test]
+pub fn test_v2_reconstruct() {
+    let ctx = "chain 1".as_bytes();
+    let key_packages = run_dkg(&party_keys(), 4, 3, ctx);
+    let public_keys = &key_packages[0].public_keys;
+    let group_key = &key_packages[0].group_key;
+    let states = |ids: &[usize]| -> Vec<v2::PartyState> {
+        ids.iter().map(|i| key_packages[*i].state.clone()).collect()
+    };
+
+    let secret = v2::PartyState::reconstruct(&states(&[0, 2]), public_keys, group_key).unwrap();
+    assert_eq!(secret * G, *group_key);
+    assert_eq!(
+        v2::PartyState::reconstruct(&states(&[1, 2]), public_keys, group_key),
+        Err(ReconstructError::BadGroupKey)
+    );
+
+    let mut bad_states = states(&[0, 1]);
+    *bad_states[1].private_keys.get_mut(&2).unwrap() += Scalar::one();
+    assert_eq!(
+        v2::PartyState::reconstruct(&bad_states, public_keys, group_key),
+        Err(ReconstructError::BadShares(vec![2]))
+    );
+}
+
+// sign with a random selection of the parties made by a Dkg, Refresh or Reshare
+#[allow(non_snake_case)]
+fn dkg_sign(key_packages: &[v2::KeyPackage], ctx: &[u8]) -> bool {
+    let num_nonces = 1;
+    let mut rng = OsRng::default();
+    let msg = "It was many and many a year ago".as_bytes();
+    let threshold = key_packages[0].threshold;
+    let party_keys: Vec<HashSet<usize>> = key_packages
+        .iter()
+        .map(|k| k.state.key_ids.clone())
+        .collect();
+    let key_owners: Vec<usize> = (0..key_packages[0].num_keys)
+        .map(|key_id| owner(&party_keys, key_id))
+        .collect();
+
+    let mut parties: Vec<Party> = key_packages.iter().map(Party::from_key_package).collect();
+    for party in &parties {
+        for key_id in &party.key_ids {
+            assert_eq!(
+                party.public_keys[key_id],
+                key_packages[0].public_keys[key_id]
+            );
+        }
+    }
+
+    let B: Vec<Vec<PublicNonce>> = parties
+        .iter_mut()
+        .map(|p| p.gen_nonces(num_nonces, &mut rng))
+        .collect();
+    for party in parties.iter_mut() {
+        party.set_group_nonces(B.clone());
+    }
+    let mut sig_agg = SignatureAggregator::from_key_package(&key_packages[0], B).unwrap();
+
+    let signers = select_parties(&key_owners, threshold, &mut rng);
+    let package = sig_agg.signing_package(msg, &signers).unwrap();
+    let sig_shares = collect_signatures(&mut parties, &package);
+    let sig = sig_agg.sign(&package, &sig_shares).unwrap();
+    sig.verify(&sig_agg.group_key, msg, ctx)
+}
@@ -1,9 +1,278 @@
+use hashbrown::HashMap;
 use rand_core::{CryptoRng, RngCore};
+use secp256k1_math::scalar::Scalar;
 
-use crate::common::{PublicNonce, SignatureShare};
+use crate::bip340::SchnorrProof;
+use crate::common::{PolyCommitment, PublicNonce, Signature, SignatureShare, SigningPackage};
+use crate::errors::{AggregatorError, DkgError, SignerError};
 
-pub trait Signer {
+// The DKG side of a signer, which deals shares of its polynomials and computes its secrets
+pub trait DkgParticipant {
+    fn get_poly_commitments<RNG: RngCore + CryptoRng>(&self, rng: &mut RNG) -> Vec<PolyCommitment>;
+
+    // outer key is the dealing party_id, inner key is the receiving key_id
+    fn get_shares(&self) -> HashMap<usize, HashMap<usize, Scalar>>;
+
+    // shares are keyed the same way as get_shares, and may include shares for other key_ids
+    #[allow(non_snake_case)]
+    fn compute_secrets(
+        &mut self,
+        shares: &HashMap<usize, HashMap<usize, Scalar>>,
+        A: &[PolyCommitment],
+    ) -> Result<(), DkgError>;
+}
+
+pub trait Signer: DkgParticipant {
+    // the party_ids which generate nonces and signature shares
+    fn get_ids(&self) -> Vec<usize>;
+
+    // the key_ids whose private keys are held
+    fn get_key_ids(&self) -> Vec<usize>;
+
+    // the key_ids held under each party_id, as SigningPackage::with_key_ids takes them
+    fn get_signer_key_ids(&self) -> Vec<(usize, Vec<usize>)>;
+
+    // one public nonce per party_id, in get_ids order
     fn gen_nonces<RNG: RngCore + CryptoRng>(&mut self, rng: &mut RNG) -> Vec<PublicNonce>;
 
-    fn sign(&self, msg: &[u8], signers: &[usize], nonces: &[PublicNonce]) -> Vec<SignatureShare>;
+    // the package must hold the nonces from gen_nonces for every id in get_ids
+    fn sign(&mut self, package: &SigningPackage) -> Result<Vec<SignatureShare>, SignerError>;
+
+    // same as sign, but the shares aggregate to a BIP-340 signature
+    fn sign_bip340(&mut self, package: &SigningPackage)
+        -> Result<Vec<SignatureShare>, SignerError>;
+
+    // same as sign_bip340, but for the taproot output key of the group key
+    fn sign_taproot(
+        &mut self,
+        package: &SigningPackage,
+        merkle_root: Option<[u8; 32]>,
+    ) -> Result<Vec<SignatureShare>, SignerError>;
+}
+
+pub trait Aggregator: Sized {
+    #[allow(non_snake_case)]
+    fn new(
+        num_keys: usize,
+        threshold: usize,
+        A: Vec<PolyCommitment>,
+    ) -> Result<Self, AggregatorError>;
+
+    // there must be exactly one share from each signer in the package
+    fn sign(
+        &mut self,
+        package: &SigningPackage,
+        sig_shares: &[SignatureShare],
+    ) -> Result<Signature, AggregatorError>;
+
+    // aggregate shares from Signer::sign_bip340
+    fn sign_bip340(
+        &mut self,
+        package: &SigningPackage,
+        sig_shares: &[SignatureShare],
+    ) -> Result<SchnorrProof, AggregatorError>;
+
+    // aggregate shares from Signer::sign_taproot
+    fn sign_taproot(
+        &mut self,
+        package: &SigningPackage,
+        sig_shares: &[SignatureShare],
+        merkle_root: Option<[u8; 32]>,
+    ) -> Result<SchnorrProof, AggregatorError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use core::iter::zip;
+    use hashbrown::HashMap;
+    use num_traits::Zero;
+    use rand_core::{CryptoRng, OsRng, RngCore};
+    use secp256k1_math::{point::Point, scalar::Scalar};
+
+    use crate::bip340;
+    use crate::common::{PolyCommitment, PublicNonce, SignatureShare, SigningPackage};
+    use crate::errors::AggregatorError;
+    use crate::taproot;
+    use crate::traits::{Aggregator, Signer};
+    use crate::{v1, v2};
+
+    // run the dkg, returning the aggregator and the group key
+    #[allow(non_snake_case)]
+    fn dkg<S: Signer, SA: Aggregator, RNG: RngCore + CryptoRng>(
+        signers: &mut [S],
+        num_keys: usize,
+        threshold: usize,
+        rng: &mut RNG,
+    ) -> (SA, Point) {
+        let A: Vec<PolyCommitment> = signers
+            .iter()
+            .flat_map(|s| s.get_poly_commitments(rng))
+            .collect();
+        let shares: HashMap<usize, HashMap<usize, Scalar>> =
+            signers.iter().flat_map(|s| s.get_shares()).collect();
+        for signer in signers.iter_mut() {
+            signer.compute_secrets(&shares, &A).unwrap();
+        }
+
+        let group_key = A.iter().fold(Point::zero(), |key, comm| key + comm.A[0]);
+        (SA::new(num_keys, threshold, A).unwrap(), group_key)
+    }
+
+    // run the whole protocol without knowing which scheme is being used
+    fn dkg_sign<S: Signer, SA: Aggregator, RNG: RngCore + CryptoRng>(
+        signers: &mut [S],
+        num_keys: usize,
+        threshold: usize,
+        rng: &mut RNG,
+    ) -> bool {
+        let msg = "It was many and many a year ago".as_bytes();
+        let (mut sig_agg, group_key) = dkg::<S, SA, RNG>(signers, num_keys, threshold, rng);
+
+        // take signers in order until there are enough keys
+        let mut num_signers = 0;
+        let mut num_signing_keys = 0;
+        while num_signing_keys < threshold {
+            num_signing_keys += signers[num_signers].get_key_ids().len();
+            num_signers += 1;
+        }
+        let signers = &mut signers[..num_signers];
+
+        let ids: Vec<usize> = signers.iter().flat_map(|s| s.get_ids()).collect();
+        let key_ids: Vec<(usize, Vec<usize>)> = signers
+            .iter()
+            .flat_map(|s| s.get_signer_key_ids())
+            .collect();
+        let mut package = |signers: &mut [S]| {
+            let nonces: Vec<PublicNonce> =
+                signers.iter_mut().flat_map(|s| s.gen_nonces(rng)).collect();
+            let nonces: Vec<(usize, PublicNonce)> = zip(ids.clone(), nonces).collect();
+            SigningPackage::with_key_ids(msg, &nonces, &key_ids, threshold).unwrap()
+        };
+
+        let signing_package = package(signers);
+        let sig_shares: Vec<SignatureShare> = signers
+            .iter_mut()
+            .flat_map(|s| s.sign(&signing_package).unwrap())
+            .collect();
+        let sig = sig_agg.sign(&signing_package, &sig_shares).unwrap();
+
+        // each nonce signs once, whichever scheme is used
+        for signer in signers.iter_mut() {
+            assert!(signer.sign(&signing_package).is_err());
+            assert!(signer.sign_bip340(&signing_package).is_err());
+        }
+
+        let signing_package = package(signers);
+        let sig_shares: Vec<SignatureShare> = signers
+            .iter_mut()
+            .flat_map(|s| s.sign_bip340(&signing_package).unwrap())
+            .collect();
+        let proof = sig_agg.sign_bip340(&signing_package, &sig_shares).unwrap();
+
+        let merkle_root = Some([7u8; 32]);
+        let signing_package = package(signers);
+        let sig_shares: Vec<SignatureShare> = signers
+            .iter_mut()
+            .flat_map(|s| s.sign_taproot(&signing_package, merkle_root).unwrap())
+            .collect();
+        let taproot_proof = sig_agg
+            .sign_taproot(&signing_package, &sig_shares, merkle_root)
+            .unwrap();
+
+        sig.verify(&group_key, msg, &[])
+            && proof.verify(&bip340::x_only(&group_key), msg)
+            && taproot_proof.verify(&taproot::output_key(&group_key, merkle_root).unwrap(), msg)
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn v1_traits() {
+        let mut rng = OsRng::default();
+        let N: usize = 5;
+        let T: usize = 3;
+        let mut signers: Vec<v1::Signer> = (0..N)
+            .map(|i| v1::Signer::new(&[i], N, T, &mut rng))
+            .collect();
+
+        assert!(dkg_sign::<_, v1::SignatureAggregator, _>(
+            &mut signers,
+            N,
+            T,
+            &mut rng
+        ));
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn v2_traits() {
+        let mut rng = OsRng::default();
+        let Nk: usize = 10;
+        let T: usize = 7;
+        let party_key_ids: Vec<Vec<usize>> =
+            vec![vec![0, 1, 2], vec![3, 4], vec![5, 6, 7], vec![8, 9]];
+        let Np = party_key_ids.len();
+        let mut parties: Vec<v2::Party> = party_key_ids
+            .iter()
+            .enumerate()
+            .map(|(pid, pkids)| {
+                v2::Party::new(pid, pkids.iter().copied().collect(), Nk, Np, T, &mut rng)
+            })
+            .collect();
+
+        assert!(dkg_sign::<_, v2::SignatureAggregator, _>(
+            &mut parties,
+            Nk,
+            T,
+            &mut rng
+        ));
+    }
+
+    // the v2 aggregator keeps the nonces it is given through the trait, and refuses them again
+    #[test]
+    #[allow(non_snake_case)]
+    fn v2_trait_aggregator_nonces() {
+        let mut rng = OsRng::default();
+        let msg = "It was many and many a year ago".as_bytes();
+        let Nk: usize = 4;
+        let T: usize = 3;
+        let party_key_ids: Vec<Vec<usize>> = vec![vec![0, 1], vec![2], vec![3]];
+        let Np = party_key_ids.len();
+        let mut parties: Vec<v2::Party> = party_key_ids
+            .iter()
+            .enumerate()
+            .map(|(pid, pkids)| {
+                v2::Party::new(pid, pkids.iter().copied().collect(), Nk, Np, T, &mut rng)
+            })
+            .collect();
+        let (mut sig_agg, _) = dkg::<_, v2::SignatureAggregator, _>(&mut parties, Nk, T, &mut rng);
+
+        let signers = &mut parties[..2];
+        let nonces: Vec<(usize, PublicNonce)> = signers
+            .iter_mut()
+            .flat_map(|s| zip(s.get_ids(), Signer::gen_nonces(s, &mut rng)))
+            .collect();
+        let key_ids: Vec<(usize, Vec<usize>)> = signers
+            .iter()
+            .flat_map(|s| s.get_signer_key_ids())
+            .collect();
+        let package = SigningPackage::with_key_ids(msg, &nonces, &key_ids, T).unwrap();
+        let sig_shares: Vec<SignatureShare> = signers
+            .iter_mut()
+            .flat_map(|s| Signer::sign(s, &package).unwrap())
+            .collect();
+
+        assert!(Aggregator::sign(&mut sig_agg, &package, &sig_shares).is_ok());
+        let selected = key_ids
+            .iter()
+            .map(|(i, k)| (*i, k.iter().cloned().collect()))
+            .collect();
+        assert!(sig_agg
+            .get_nonce_indices(&selected)
+            .values()
+            .all(|i| *i == 1));
+        assert_eq!(
+            Aggregator::sign(&mut sig_agg, &package, &sig_shares).err(),
+            Some(AggregatorError::BadPackageNonces(vec![0, 1]))
+        );
+    }
 }
@@ -1,8 +1,15 @@
+use aes_gcm::{
+    aead::{Aead, KeyInit, Payload},
+    Aes256Gcm, Nonce,
+};
+use rand_core::{CryptoRng, RngCore};
 use secp256k1_math::{point::Compressed, point::Point, scalar::Scalar};
 use sha3::{Digest, Sha3_256};
 
+pub const AES_GCM_NONCE_SIZE: usize = 12;
+
 #[allow(dead_code)]
-pub fn hash_to_scalar(hasher: &mut Sha3_256) -> Scalar {
+pub fn hash_to_scalar<D: Digest + Clone>(hasher: &mut D) -> Scalar {
     let h = hasher.clone();
     let hash = h.finalize();
     let mut hash_bytes: [u8; 32] = [0; 32];
@@ -36,3 +43,49 @@ pub fn encode_scalar(s: &Scalar) -> String {
 pub fn encode_point(p: &Point) -> String {
     hex::encode(p.compress().as_bytes())
 }
+
+// ECDH between our private key and their public key, hashed down to a symmetric key
+pub fn make_shared_secret(private_key: &Scalar, public_key: &Point) -> [u8; 32] {
+    let shared_key = private_key * public_key;
+    let mut hasher = Sha3_256::new();
+
+    hasher.update(shared_key.compress().as_bytes());
+
+    let mut bytes: [u8; 32] = [0; 32];
+    bytes.clone_from_slice(hasher.finalize().as_slice());
+    bytes
+}
+
+// AES-256-GCM with a random nonce, which is prepended to the returned ciphertext
+pub fn encrypt<RNG: RngCore + CryptoRng>(
+    key: &[u8; 32],
+    data: &[u8],
+    aad: &[u8],
+    rng: &mut RNG,
+) -> Result<Vec<u8>, aes_gcm::Error> {
+    let mut nonce_bytes = [0u8; AES_GCM_NONCE_SIZE];
+    rng.fill_bytes(&mut nonce_bytes);
+
+    let cipher = Aes256Gcm::new(key.into());
+    let ciphertext = cipher.encrypt(Nonce::from_slice(&nonce_bytes), Payload { msg: data, aad })?;
+
+    let mut bytes = nonce_bytes.to_vec();
+    bytes.extend(ciphertext);
+    Ok(bytes)
+}
+
+pub fn decrypt(key: &[u8; 32], data: &[u8], aad: &[u8]) -> Result<Vec<u8>, aes_gcm::Error> {
+    if data.len() < AES_GCM_NONCE_SIZE {
+        return Err(aes_gcm::Error);
+    }
+    let (nonce_bytes, ciphertext) = data.split_at(AES_GCM_NONCE_SIZE);
+
+    let cipher = Aes256Gcm::new(key.into());
+    cipher.decrypt(
+        Nonce::from_slice(nonce_bytes),
+        Payload {
+            msg: ciphertext,
+            aad,
+        },
+    )
+}
@@ -1,3 +1,5 @@
+use core::iter::zip;
+use core::mem;
 use num_traits::Zero;
 use polynomial::Polynomial;
 use rand_core::{CryptoRng, RngCore};
@@ -7,17 +9,74 @@ use secp256k1_math::{
 };
 use serde::{Deserialize, Serialize};
 
-use crate::common::{Nonce, PolyCommitment, PublicNonce, Signature, SignatureShare};
+use crate::bip340::{self, SchnorrProof};
+use crate::common::{
+    Nonce, PolyCommitment, PublicNonce, Signature, SignatureShare, SigningPackage,
+};
 use crate::compute;
+use crate::dkg::{
+    self, Complaint, DkgCommitment, DkgRounds, DkgScheme, DkgShare, DkgState, EncryptedShare,
+    Justification, PedersenCommitment, RecoveryShare, RefreshCommitment, RepairShare, RepairSum,
+    Rounds,
+};
+#[cfg(feature = "reconstruct")]
+use crate::errors::ReconstructError;
+use crate::errors::{AggregatorError, DkgError, SignerError, SigningPackageError};
+#[cfg(feature = "reconstruct")]
+use crate::reconstruct;
+use crate::rfc9591;
 use crate::schnorr::ID;
+use crate::taproot;
+use crate::traits;
 use crate::vss::VSS;
 
-use hashbrown::HashMap;
+use hashbrown::{HashMap, HashSet};
+use std::collections::BTreeMap;
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct PartyState {
     pub private_key: Scalar,
     pub polynomial: Polynomial<Scalar>,
+    pub context: Vec<u8>,
+}
+
+impl PartyState {
+    // Rebuild the state of party id after it was lost, from the RepairSums of the helpers,
+    // see Party::repair_shares.  The party's Dkg polynomial can't be recovered, so a repaired
+    // party can sign but has nothing to deal in another Dkg
+    #[allow(non_snake_case)]
+    pub fn repair(
+        id: usize,
+        helpers: &[usize],
+        sums: &[RepairSum],
+        A: &[PolyCommitment],
+        context: &[u8],
+    ) -> Result<Self, DkgError> {
+        let private_key = dkg::repair_key(id, helpers, sums, A)?;
+
+        Ok(Self {
+            private_key,
+            polynomial: Polynomial::new(vec![Scalar::zero(); A[0].A.len()]),
+            context: context.to_vec(),
+        })
+    }
+
+    // Reconstruct the group secret from the states of at least t parties, keyed by party id.
+    // This is for offline disaster recovery only, since it puts the whole key in one place
+    #[cfg(feature = "reconstruct")]
+    pub fn reconstruct(
+        states: &[(usize, PartyState)],
+        public_keys: &[Point],
+        group_key: &Point,
+    ) -> Result<Scalar, ReconstructError> {
+        let shares: Vec<(usize, Scalar)> = states
+            .iter()
+            .map(|(id, state)| (*id, state.private_key))
+            .collect();
+        let public_keys = public_keys.iter().cloned().enumerate().collect();
+
+        reconstruct::secret(&shares, &public_keys, group_key)
+    }
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -30,6 +89,7 @@ pub struct Party {
     private_key: Scalar,
     group_key: Point,
     nonce: Nonce,
+    context: Vec<u8>,
 }
 
 impl Party {
@@ -43,6 +103,7 @@ impl Party {
             public_key: Point::zero(),
             group_key: Point::zero(),
             nonce: Nonce::zero(),
+            context: Vec::new(),
         }
     }
 
@@ -55,6 +116,7 @@ impl Party {
             public_key: &state.private_key * G,
             group_key: *group_key,
             nonce: Nonce::zero(),
+            context: state.context.clone(),
         }
     }
 
@@ -62,11 +124,34 @@ impl Party {
         PartyState {
             private_key: self.private_key,
             polynomial: self.f.clone(),
+            context: self.context.clone(),
         }
     }
 
+    pub fn from_key_package(key_package: &KeyPackage) -> Self {
+        Self::load(
+            key_package.id,
+            key_package.n,
+            &key_package.group_key,
+            &key_package.state,
+        )
+    }
+
+    // the application context (e.g. a chain id or group id) which is bound into every hash.
+    // All parties and the aggregator must use the same one
+    pub fn set_context(&mut self, context: &[u8]) {
+        self.context = context.to_vec();
+    }
+
     pub fn gen_nonce<RNG: RngCore + CryptoRng>(&mut self, rng: &mut RNG) -> PublicNonce {
-        self.nonce = Nonce::random(rng);
+        self.nonce = Nonce::random(&self.private_key, &self.context, rng);
+
+        PublicNonce::from(&self.nonce)
+    }
+
+    // RFC 9591 round one, which derives the nonces from the private key as well as the rng
+    pub fn gen_nonce_rfc9591<RNG: RngCore + CryptoRng>(&mut self, rng: &mut RNG) -> PublicNonce {
+        self.nonce = rfc9591::commit(&self.private_key, rng);
 
         PublicNonce::from(&self.nonce)
     }
@@ -74,13 +159,41 @@ impl Party {
     #[allow(non_snake_case)]
     pub fn get_poly_commitment<RNG: RngCore + CryptoRng>(&self, rng: &mut RNG) -> PolyCommitment {
         PolyCommitment {
-            id: ID::new(&self.id(), &self.f.data()[0], rng),
+            id: ID::new(&self.id(), &self.f.data()[0], &self.context, rng),
             A: (0..self.f.data().len())
                 .map(|i| &self.f.data()[i] * G)
                 .collect(),
         }
     }
 
+    // Deal this party's part of the group secret to a new committee of n parties with threshold
+    // t, see Reshare.  dealers are the old party ids which deal, and there must be at least the
+    // old threshold of them
+    pub fn reshare<RNG: RngCore + CryptoRng>(
+        &self,
+        dealers: &[usize],
+        n: usize,
+        t: usize,
+        rng: &mut RNG,
+    ) -> (DkgCommitment, Vec<DkgShare>) {
+        let secret = compute::lambda(&self.id, dealers) * self.private_key;
+        dkg::deal(self.id, &secret, n, t, &self.context, rng)
+    }
+
+    // Round one of repairing the lost share of party victim.  helpers are the party ids which
+    // help, and there must be at least the threshold of them.  Each helper gets one part, and
+    // turns the parts it gets into a RepairSum with dkg::repair_sum
+    pub fn repair_shares<RNG: RngCore + CryptoRng>(
+        &self,
+        victim: usize,
+        helpers: &[usize],
+        rng: &mut RNG,
+    ) -> Vec<RepairShare> {
+        let x = Scalar::from((victim + 1) as u32);
+        let contribution = compute::lambda_at(&self.id, helpers, &x) * self.private_key;
+        dkg::split_repair(self.id, victim, &contribution, helpers, rng)
+    }
+
     pub fn get_shares(&self) -> HashMap<usize, Scalar> {
         let mut shares = HashMap::new();
         for i in 0..self.n {
@@ -89,322 +202,2575 @@ impl Party {
         shares
     }
 
-    // TODO: Maybe this should be private? If receive_share is keeping track
-    // of which it receives, then this could be called when it has N shares from unique ids
-    #[allow(non_snake_case)]
-    pub fn compute_secret(&mut self, shares: HashMap<usize, Scalar>, A: &[PolyCommitment]) {
-        // TODO: return error with a list of missing shares
-        assert!(shares.len() == self.n);
-        self.private_key = Scalar::zero();
-        for (i, s) in shares.iter() {
-            let Ai = &A[*i];
-            assert!(Ai.verify()); // checks a0 proof
-            assert!(
-                s * G
-                    == (0..Ai.A.len()).fold(Point::zero(), |s, j| s
-                        + (Scalar::from((self.id + 1) as u32) ^ j) * Ai.A[j])
-            );
-            self.private_key += s;
-            self.group_key += Ai.A[0];
+    // encrypt each share to its recipient's network public key so all of them can be broadcast
+    pub fn get_encrypted_shares<RNG: RngCore + CryptoRng>(
+        &self,
+        network_private_key: &Scalar,
+        network_public_keys: &HashMap<usize, Point>,
+        rng: &mut RNG,
+    ) -> Result<Vec<EncryptedShare>, DkgError> {
+        let missing_keys: Vec<usize> = (0..self.n)
+            .filter(|i| !network_public_keys.contains_key(i))
+            .collect();
+        if !missing_keys.is_empty() {
+            return Err(DkgError::MissingPublicKeys(missing_keys));
         }
-        self.public_key = self.private_key * G;
-        println!("Party {} secret {}", self.id, self.private_key);
-    }
-
-    fn id(&self) -> Scalar {
-        Scalar::from((self.id + 1) as u32)
-    }
 
-    #[allow(non_snake_case)]
-    pub fn sign(&self, msg: &[u8], signers: &[usize], nonces: &[PublicNonce]) -> Scalar {
-        let (_R_vec, R) = compute::intermediate(msg, signers, nonces);
-        let mut z = &self.nonce.d + &self.nonce.e * compute::binding(&self.id(), nonces, msg);
-        z += compute::challenge(&self.group_key, &R, msg)
-            * &self.private_key
-            * compute::lambda(&self.id, signers);
-        z
+        let mut encrypted_shares = Vec::new();
+        for (i, share) in self.get_shares() {
+            let encrypted_share = EncryptedShare::new(
+                self.id,
+                i,
+                &share,
+                network_private_key,
+                &network_public_keys[&i],
+                rng,
+            )
+            .ok_or(DkgError::EncryptionFailure(i))?;
+            encrypted_shares.push(encrypted_share);
+        }
+        Ok(encrypted_shares)
     }
-}
-
-#[allow(non_snake_case)]
-pub struct SignatureAggregator {
-    pub N: usize,
-    pub T: usize,
-    pub key: Point,
-}
 
-impl SignatureAggregator {
-    #[allow(non_snake_case)]
-    pub fn new(N: usize, T: usize, A: Vec<PolyCommitment>) -> Self {
-        // TODO: How should we handle bad As?
-        assert!(A.len() == N);
-        for A_i in &A {
-            assert!(A_i.verify());
+    // decrypt the shares sent to this party, ready to be passed to compute_secret
+    pub fn decrypt_shares(
+        &self,
+        network_private_key: &Scalar,
+        network_public_keys: &HashMap<usize, Point>,
+        encrypted_shares: &[EncryptedShare],
+    ) -> Result<HashMap<usize, Scalar>, DkgError> {
+        let mut shares = HashMap::new();
+        let mut missing_keys = Vec::new();
+        let mut bad_shares = Vec::new();
+        for encrypted_share in encrypted_shares.iter().filter(|es| es.key_id == self.id) {
+            let sender = encrypted_share.sender;
+            match network_public_keys.get(&sender) {
+                Some(public_key) => {
+                    match encrypted_share.decrypt(network_private_key, public_key) {
+                        Some(share) => {
+                            shares.insert(sender, share);
+                        }
+                        None => bad_shares.push(sender),
+                    }
+                }
+                None => missing_keys.push(sender),
+            }
         }
-
-        let mut key = Point::new(); // TODO: Compute pub key from A
-        for A_i in &A {
-            key += &A_i.A[0];
+        if !missing_keys.is_empty() {
+            missing_keys.sort();
+            return Err(DkgError::MissingPublicKeys(missing_keys));
         }
-        println!("SA groupKey {}", key);
-
-        Self { N, T, key }
+        if !bad_shares.is_empty() {
+            bad_shares.sort();
+            return Err(DkgError::BadEncryptedShares(bad_shares));
+        }
+        Ok(shares)
     }
 
+    // TODO: Maybe this should be private? If receive_share is keeping track
+    // of which it receives, then this could be called when it has N shares from unique ids
     #[allow(non_snake_case)]
-    pub fn sign(
+    pub fn compute_secret(
         &mut self,
-        msg: &[u8],
-        nonces: &[PublicNonce],
-        sig_shares: &[SignatureShare],
-    ) -> Signature {
-        let signers: Vec<usize> = sig_shares.iter().map(|ss| ss.id).collect();
-        let (R_vec, R) = compute::intermediate(msg, &signers, nonces);
-        let mut z = Scalar::zero();
-        let c = compute::challenge(&self.key, &R, msg); // only needed for checking z_i
-
-        for i in 0..sig_shares.len() {
-            let z_i = sig_shares[i].z_i;
-            assert!(
-                z_i * G
-                    == R_vec[i]
-                        + (compute::lambda(&sig_shares[i].id, &signers)
-                            * c
-                            * sig_shares[i].public_key)
-            ); // TODO: This should return a list of bad parties.
-            z += z_i;
-        }
-
-        Signature { R, z }
+        shares: HashMap<usize, Scalar>,
+        A: &[PolyCommitment],
+    ) -> Result<(), DkgError> {
+        let qualified = (0..self.n).collect();
+        self.compute_qualified_secret(&qualified, shares, A)
     }
-}
 
-#[derive(Debug, Deserialize, Serialize)]
-pub struct SignerState {
-    n: usize,
-    group_key: Point,
-    parties: HashMap<usize, PartyState>,
-}
+    // compute the secret using only the shares and commitments from the qualified parties,
+    // which is the set left over after resolve_complaints
+    #[allow(non_snake_case)]
+    pub fn compute_qualified_secret(
+        &mut self,
+        qualified: &HashSet<usize>,
+        shares: HashMap<usize, Scalar>,
+        A: &[PolyCommitment],
+    ) -> Result<(), DkgError> {
+        if A.len() != self.n {
+            return Err(DkgError::BadPolyCommitmentLen(self.n, A.len()));
+        }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
-pub struct Signer {
-    pub n: usize,
-    pub group_key: Point,
-    pub parties: Vec<Party>,
-}
+        let mut qualified: Vec<usize> = qualified.iter().cloned().collect();
+        qualified.sort();
 
-impl Signer {
-    pub fn new<RNG: RngCore + CryptoRng>(ids: &[usize], n: usize, t: usize, rng: &mut RNG) -> Self {
-        let parties = ids.iter().map(|id| Party::new(*id, n, t, rng)).collect();
-        Signer {
-            n,
-            group_key: Point::zero(),
-            parties,
+        let bad_ids: Vec<usize> = qualified
+            .iter()
+            .cloned()
+            .filter(|i| {
+                *i >= self.n || A[*i].A.len() != self.f.data().len() || !A[*i].verify(&self.context)
+            })
+            .collect();
+        if !bad_ids.is_empty() {
+            return Err(DkgError::BadPolyCommitments(bad_ids));
         }
-    }
 
-    pub fn load(state: &SignerState) -> Self {
-        let parties = state
-            .parties
+        let missing_shares: Vec<usize> = qualified
             .iter()
-            .map(|(id, ps)| Party::load(*id, state.n, &state.group_key, ps))
+            .cloned()
+            .filter(|i| !shares.contains_key(i))
             .collect();
-
-        Self {
-            n: state.n,
-            group_key: state.group_key,
-            parties,
+        if !missing_shares.is_empty() {
+            return Err(DkgError::MissingShares(missing_shares));
         }
-    }
-
-    pub fn save(&self) -> SignerState {
-        let mut parties = HashMap::new();
 
-        for party in &self.parties {
-            parties.insert(party.id, party.save());
+        let bad_shares: Vec<usize> = qualified
+            .iter()
+            .cloned()
+            .filter(|i| !compute::check_share(&self.id(), &shares[i], &A[*i].A))
+            .collect();
+        if !bad_shares.is_empty() {
+            return Err(DkgError::BadShares(bad_shares));
         }
 
-        SignerState {
-            n: self.n,
-            group_key: self.group_key,
-            parties,
+        self.private_key = Scalar::zero();
+        self.group_key = Point::zero();
+        for i in &qualified {
+            self.private_key += shares[i];
+            self.group_key += A[*i].A[0];
         }
+        self.public_key = self.private_key * G;
+
+        Ok(())
     }
 
-    pub fn get_poly_commitments<RNG: RngCore + CryptoRng>(
+    // complain about every sender whose share is missing or fails to verify
+    #[allow(non_snake_case)]
+    pub fn get_complaints(
         &self,
-        rng: &mut RNG,
-    ) -> Vec<PolyCommitment> {
-        self.parties
-            .iter()
-            .map(|p| p.get_poly_commitment(rng))
+        shares: &HashMap<usize, Scalar>,
+        A: &[PolyCommitment],
+    ) -> Vec<Complaint> {
+        (0..A.len())
+            .filter(|i| match shares.get(i) {
+                Some(s) => !compute::check_share(&self.id(), s, &A[*i].A),
+                None => true,
+            })
+            .map(|i| Complaint {
+                accuser: self.id,
+                accused: i,
+                key_id: self.id,
+            })
             .collect()
     }
 
-    pub fn get_ids(&self) -> Vec<usize> {
-        self.parties.iter().map(|p| p.id).collect()
-    }
-}
-
-impl crate::traits::Signer for Signer {
-    fn gen_nonces<RNG: RngCore + CryptoRng>(&mut self, rng: &mut RNG) -> Vec<PublicNonce> {
-        self.parties.iter_mut().map(|p| p.gen_nonce(rng)).collect()
-    }
-
-    fn sign(&self, msg: &[u8], signers: &[usize], nonces: &[PublicNonce]) -> Vec<SignatureShare> {
-        self.parties
+    // reveal the disputed shares for every complaint against this party.  Only the accuser's
+    // own share is ever revealed, or t forged complaints would reveal the whole polynomial
+    pub fn justify(&self, complaints: &[Complaint]) -> Vec<Justification> {
+        complaints
             .iter()
-            .map(|p| SignatureShare {
-                id: p.id,
-                z_i: p.sign(msg, signers, nonces),
-                public_key: p.public_key,
+            .filter(|c| c.accused == self.id && c.key_id == c.accuser)
+            .map(|c| Justification {
+                accused: self.id,
+                key_id: c.key_id,
+                share: self.f.eval(Scalar::from((c.key_id + 1) as u32)),
             })
             .collect()
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::common::{PolyCommitment, PublicNonce, SignatureShare};
-    use crate::traits::Signer;
-    use crate::v1;
+    fn id(&self) -> Scalar {
+        Scalar::from((self.id + 1) as u32)
+    }
 
-    use hashbrown::HashMap;
-    use num_traits::Zero;
-    use rand_core::{CryptoRng, OsRng, RngCore};
+    // f has degree t - 1
+    fn threshold(&self) -> usize {
+        self.f.data().len()
+    }
 
-    #[test]
-    fn signer_new() {
-        let mut rng = OsRng::default();
-        let ids = [1, 2, 3];
-        let n: usize = 10;
-        let t: usize = 7;
+    pub fn sign(&mut self, package: &SigningPackage) -> Result<Scalar, SignerError> {
+        self.sign_with_tweak(package, None)
+    }
 
-        let signer = v1::Signer::new(&ids, n, t, &mut rng);
+    // BIP-340 uses the even Y versions of R and the group key, so negate the shares to match
+    pub fn sign_bip340(&mut self, package: &SigningPackage) -> Result<Scalar, SignerError> {
+        self.sign_with_tweak(package, Some(Scalar::zero()))
+    }
 
-        assert_eq!(signer.parties.len(), ids.len());
+    // sign for the taproot output key of the group key, see taproot::output_key
+    pub fn sign_taproot(
+        &mut self,
+        package: &SigningPackage,
+        merkle_root: Option<[u8; 32]>,
+    ) -> Result<Scalar, SignerError> {
+        let tweak = taproot::tweak(&self.group_key, merkle_root).ok_or(SignerError::BadTweak)?;
+        self.sign_with_tweak(package, Some(tweak))
     }
 
-    #[test]
-    fn signer_gen_nonces() {
-        let mut rng = OsRng::default();
-        let ids = [1, 2, 3];
-        let n: usize = 10;
-        let t: usize = 7;
+    // a share of an RFC 9591 FROST(secp256k1, SHA-256) signature.  The ciphersuite fixes its
+    // hashes, so the context is not used
+    pub fn sign_rfc9591(&mut self, package: &SigningPackage) -> Result<Scalar, SignerError> {
+        self.check_package(package)?;
+        let nonce = self.take_nonce(package)?;
+        rfc9591::sign(
+            self.id,
+            &self.private_key,
+            &nonce,
+            package.msg(),
+            &package.signers(),
+            &package.nonces(),
+            &self.group_key,
+        )
+    }
 
-        let mut signer = v1::Signer::new(&ids, n, t, &mut rng);
+    // Refuse a package which leaves this party out, gives it any key but its own, or has too
+    // few signers to make a signature.  The nonce is checked as it is taken
+    fn check_package(&self, package: &SigningPackage) -> Result<(), SignerError> {
+        if package.nonce(self.id).is_none() {
+            return Err(SignerError::MissingPublicNonces(vec![self.id]));
+        }
 
-        for party in &signer.parties {
-            assert!(party.nonce.is_zero());
+        let key_ids = package.signer_key_ids(self.id);
+        if key_ids != [self.id] {
+            return Err(SignerError::BadKeyIds(self.id, key_ids.to_vec()));
         }
 
-        let nonces = signer.gen_nonces(&mut rng);
+        let num_signers = package.signers().len();
+        if num_signers < self.threshold() {
+            return Err(SigningPackageError::BelowThreshold(self.threshold(), num_signers).into());
+        }
 
-        assert_eq!(nonces.len(), ids.len());
+        Ok(())
+    }
 
-        for party in &signer.parties {
-            assert!(!party.nonce.is_zero());
+    // the public nonce we are asked to sign with must be the unused one we generated
+    fn check_nonce(&self, package: &SigningPackage) -> Result<(), SignerError> {
+        if self.nonce.is_zero() {
+            return Err(SignerError::MissingNonce(self.id));
+        }
+
+        let public_nonce = package
+            .nonce(self.id)
+            .ok_or_else(|| SignerError::MissingPublicNonces(vec![self.id]))?;
+        if *public_nonce != PublicNonce::from(&self.nonce) {
+            return Err(SignerError::BadPublicNonce(self.id));
         }
+
+        Ok(())
     }
 
-    #[test]
-    fn signer_save_load() {
-        let mut rng = OsRng::default();
-        let ids = [1, 2, 3];
-        let n: usize = 10;
-        let t: usize = 7;
+    // Signing twice with one nonce leaks the private key, so the nonce is zeroed as it is
+    // taken and another sign fails until gen_nonce is called again.  A nonce which fails
+    // check_nonce is left alone
+    fn take_nonce(&mut self, package: &SigningPackage) -> Result<Nonce, SignerError> {
+        self.check_nonce(package)?;
+        Ok(mem::replace(&mut self.nonce, Nonce::zero()))
+    }
 
-        let signer = v1::Signer::new(&ids, n, t, &mut rng);
+    #[allow(non_snake_case)]
+    fn sign_with_tweak(
+        &mut self,
+        package: &SigningPackage,
+        tweak: Option<Scalar>,
+    ) -> Result<Scalar, SignerError> {
+        self.check_package(package)?;
+        let nonce = self.take_nonce(package)?;
+        let ctx = &self.context;
+        let msg = package.msg();
+        let (_R_vec, R) = compute::intermediate(package, ctx);
+        let (c, r_sign, key_sign) =
+            compute::tweaked_challenge(&self.group_key, &R, msg, tweak, ctx);
 
-        let state = signer.save();
-        let loaded = v1::Signer::load(&state);
+        let rho = compute::binding(&self.id(), &package.nonces(), msg, ctx);
+        let mut z = r_sign * (nonce.d + nonce.e * rho);
+        z += key_sign * c * self.private_key * compute::lambda(&self.id, &package.signers());
+        Ok(z)
+    }
+}
 
-        assert_eq!(signer, loaded);
+#[allow(non_snake_case)]
+pub struct SignatureAggregator {
+    pub N: usize,
+    pub T: usize,
+    pub key: Point,
+    pub public_keys: Vec<Point>, // the public key of each party, computed from A
+    pub context: Vec<u8>,
+}
+
+impl SignatureAggregator {
+    #[allow(non_snake_case)]
+    pub fn new(N: usize, T: usize, A: Vec<PolyCommitment>) -> Result<Self, AggregatorError> {
+        let qualified = (0..N).collect();
+        Self::new_qualified(N, T, A, &qualified, &[])
     }
 
+    // the group key is only computed over the parties which survived the complaint round.
+    // context is the application context the parties were given with Party::set_context
     #[allow(non_snake_case)]
-    fn dkg<RNG: RngCore + CryptoRng>(
-        signers: &mut Vec<v1::Signer>,
-        rng: &mut RNG,
-    ) -> Vec<PolyCommitment> {
-        let A: Vec<PolyCommitment> = signers
+    pub fn new_qualified(
+        N: usize,
+        T: usize,
+        A: Vec<PolyCommitment>,
+        qualified: &HashSet<usize>,
+        context: &[u8],
+    ) -> Result<Self, AggregatorError> {
+        if A.len() != N {
+            return Err(AggregatorError::BadPolyCommitmentLen(N, A.len()));
+        }
+
+        let mut qualified: Vec<usize> = qualified.iter().cloned().collect();
+        qualified.sort();
+
+        let bad_ids: Vec<usize> = qualified
             .iter()
-            .flat_map(|s| s.get_poly_commitments(rng))
+            .cloned()
+            .filter(|i| *i >= N || A[*i].A.len() != T || !A[*i].verify(context))
             .collect();
+        if !bad_ids.is_empty() {
+            return Err(AggregatorError::BadPolyCommitments(bad_ids));
+        }
 
-        // each party broadcasts their commitments
-        // these hashmaps will need to be serialized in tuples w/ the value encrypted
-        let mut broadcast_shares = Vec::new();
-        for signer in signers.iter() {
-            for party in &signer.parties {
-                broadcast_shares.push((party.id, party.get_shares()));
-            }
+        let mut key = Point::new();
+        for i in &qualified {
+            key += &A[*i].A[0];
         }
 
-        // each party collects its shares from the broadcasts
-        // maybe this should collect into a hashmap first?
-        for signer in signers.iter_mut() {
-            for party in signer.parties.iter_mut() {
-                let mut h = HashMap::new();
+        let public_keys = (0..N)
+            .map(|i| {
+                let id = Scalar::from((i + 1) as u32);
+                qualified
+                    .iter()
+                    .fold(Point::zero(), |p, j| p + compute::poly(&id, &A[*j].A))
+            })
+            .collect();
 
-                for (id, share) in &broadcast_shares {
-                    h.insert(*id, share[&party.id]);
-                }
+        Ok(Self {
+            N,
+            T,
+            key,
+            public_keys,
+            context: context.to_vec(),
+        })
+    }
 
-                party.compute_secret(h, &A);
-            }
+    // the aggregator for the group in a KeyPackage, which works for a Reshare as well as a Dkg
+    pub fn from_key_package(key_package: &KeyPackage) -> Self {
+        Self {
+            N: key_package.n,
+            T: key_package.threshold,
+            key: key_package.group_key,
+            public_keys: key_package.public_keys.clone(),
+            context: key_package.state.context.clone(),
         }
+    }
 
-        A
+    pub fn sign(
+        &mut self,
+        package: &SigningPackage,
+        sig_shares: &[SignatureShare],
+    ) -> Result<Signature, AggregatorError> {
+        self.aggregate(package, sig_shares, None)
     }
 
-    // There might be a slick one-liner for this?
-    fn sign<RNG: RngCore + CryptoRng>(
-        msg: &[u8],
-        signers: &mut [v1::Signer],
-        rng: &mut RNG,
-    ) -> (Vec<PublicNonce>, Vec<SignatureShare>) {
-        let ids: Vec<usize> = signers.iter().flat_map(|s| s.get_ids()).collect();
-        let nonces: Vec<PublicNonce> = signers.iter_mut().flat_map(|s| s.gen_nonces(rng)).collect();
-        let shares = signers
-            .iter()
-            .flat_map(|s| s.sign(msg, &ids, &nonces))
-            .collect();
+    // the returned proof has an even R and verifies against the x-only group key
+    pub fn sign_bip340(
+        &mut self,
+        package: &SigningPackage,
+        sig_shares: &[SignatureShare],
+    ) -> Result<SchnorrProof, AggregatorError> {
+        let sig = self.aggregate(package, sig_shares, Some(Scalar::zero()))?;
+        Ok(SchnorrProof::new(&sig))
+    }
 
-        (nonces, shares)
+    // the returned proof verifies against taproot::output_key(&self.key, merkle_root)
+    pub fn sign_taproot(
+        &mut self,
+        package: &SigningPackage,
+        sig_shares: &[SignatureShare],
+        merkle_root: Option<[u8; 32]>,
+    ) -> Result<SchnorrProof, AggregatorError> {
+        let tweak = taproot::tweak(&self.key, merkle_root).ok_or(AggregatorError::BadTweak)?;
+        let sig = self.aggregate(package, sig_shares, Some(tweak))?;
+        Ok(SchnorrProof::new(&sig))
     }
 
-    #[allow(non_snake_case)]
-    #[test]
+    // aggregate shares from Party::sign_rfc9591, the result verifies with rfc9591::verify
+    pub fn sign_rfc9591(
+        &mut self,
+        package: &SigningPackage,
+        sig_shares: &[SignatureShare],
+    ) -> Result<Signature, AggregatorError> {
+        let signers = package.signers();
+        let nonces = package.nonces();
+        let z = self.party_sigs(&signers, sig_shares)?;
+        let public_keys: Vec<Point> = signers.iter().map(|id| self.public_keys[*id]).collect();
+        let msg = package.msg();
+
+        let bad_party_sigs: Vec<usize> =
+            rfc9591::verify_signature_shares(msg, &signers, &nonces, &z, &public_keys, &self.key)
+                .into_iter()
+                .map(|i| signers[i])
+                .collect();
+        if !bad_party_sigs.is_empty() {
+            return Err(AggregatorError::BadPartySigs(bad_party_sigs));
+        }
+
+        Ok(rfc9591::aggregate(msg, &signers, &nonces, &z, &self.key))
+    }
+
+    // the share of each signer in signers order.  Shares from parties which are not signing,
+    // or which were sent twice, are bad
+    fn party_sigs(
+        &self,
+        signers: &[usize],
+        sig_shares: &[SignatureShare],
+    ) -> Result<Vec<Scalar>, AggregatorError> {
+        let mut z: Vec<Option<Scalar>> = vec![None; signers.len()];
+        let mut bad_ids = Vec::new();
+        for ss in sig_shares {
+            match signers.iter().position(|id| *id == ss.id && *id < self.N) {
+                Some(i) if z[i].is_none() => z[i] = Some(ss.z_i),
+                _ => bad_ids.push(ss.id),
+            }
+        }
+        if !bad_ids.is_empty() {
+            bad_ids.sort();
+            return Err(AggregatorError::BadPartySigs(bad_ids));
+        }
+
+        let missing_ids: Vec<usize> = zip(signers, &z)
+            .filter(|(_, z_i)| z_i.is_none())
+            .map(|(id, _)| *id)
+            .collect();
+        if !missing_ids.is_empty() {
+            return Err(AggregatorError::MissingPartySigs(missing_ids));
+        }
+
+        Ok(z.into_iter().flatten().collect())
+    }
+
+    #[allow(non_snake_case)]
+    fn aggregate(
+        &self,
+        package: &SigningPackage,
+        sig_shares: &[SignatureShare],
+        tweak: Option<Scalar>,
+    ) -> Result<Signature, AggregatorError> {
+        let signers = package.signers();
+        let z_vec = self.party_sigs(&signers, sig_shares)?;
+        let msg = package.msg();
+        let (R_vec, R) = compute::intermediate(package, &self.context);
+
+        let (c, r_sign, key_sign) =
+            compute::tweaked_challenge(&self.key, &R, msg, tweak, &self.context);
+
+        // check every share so all the bad parties can be reported at once
+        let bad_party_sigs: Vec<usize> = signers
+            .iter()
+            .enumerate()
+            .filter(|(i, id)| {
+                z_vec[*i] * G
+                    != r_sign * R_vec[*i]
+                        + (key_sign * compute::lambda(id, &signers) * c * self.public_keys[**id])
+            })
+            .map(|(_, id)| *id)
+            .collect();
+        if !bad_party_sigs.is_empty() {
+            return Err(AggregatorError::BadPartySigs(bad_party_sigs));
+        }
+
+        let mut z = z_vec.iter().fold(Scalar::zero(), |z, z_i| z + z_i);
+
+        // the tweak is public, so its part of the signature is added here
+        if let Some(t) = tweak {
+            let tweaked_key = taproot::tweaked_public_key(&self.key, &t);
+            z += c * t * bip340::parity(&tweaked_key);
+        }
+
+        Ok(Signature { R: r_sign * R, z })
+    }
+}
+
+// Resolve the broadcast complaints and justifications into the set of qualified parties.
+// Parties with a bad proof of knowledge are never qualified.
+#[allow(non_snake_case)]
+pub fn resolve_complaints(
+    A: &[PolyCommitment],
+    complaints: &[Complaint],
+    justifications: &[Justification],
+    ctx: &[u8],
+) -> HashSet<usize> {
+    let owns_key = |c: &Complaint| c.key_id == c.accuser;
+    let check_share = |j: &Justification| {
+        j.accused < A.len()
+            && compute::check_share(
+                &Scalar::from((j.key_id + 1) as u32),
+                &j.share,
+                &A[j.accused].A,
+            )
+    };
+    let mut qualified =
+        dkg::resolve_complaints(A.len(), complaints, justifications, owns_key, check_share);
+    qualified.retain(|i| A[*i].verify(ctx));
+    qualified
+}
+
+// Everything a party has at the end of a Dkg, Refresh or Reshare.  The public keys and poly
+// commitments are the same for every party, and are what a SignatureAggregator is made from
+#[derive(Clone, Deserialize, Serialize)]
+pub struct KeyPackage {
+    pub id: usize,
+    pub n: usize,
+    pub threshold: usize,
+    pub group_key: Point,
+    pub public_keys: Vec<Point>, // index is party id
+    pub poly_commitments: Vec<PolyCommitment>,
+    pub state: PartyState,
+}
+
+impl KeyPackage {
+    // the KeyPackage of party id from a trusted dealer, see dkg::trusted_deal
+    #[allow(non_snake_case)]
+    pub fn from_dealer(
+        id: usize,
+        n: usize,
+        commitment: &DkgCommitment,
+        share: &DkgShare,
+        context: &[u8],
+    ) -> Result<Self, DkgError> {
+        if id >= n {
+            return Err(DkgError::WrongKeyId(id));
+        }
+        let private_key = dkg::check_dealt_share(commitment, share, id, context)?;
+        let A = vec![commitment.commitment.clone()];
+        let threshold = A[0].A.len();
+
+        Ok(Self {
+            id,
+            n,
+            threshold,
+            group_key: A[0].A[0],
+            public_keys: (0..n).map(|i| dkg::public_key(i, &A)).collect(),
+            poly_commitments: A,
+            state: PartyState {
+                private_key,
+                // the dealer's polynomial is the only one
+                polynomial: Polynomial::new(vec![Scalar::zero(); threshold]),
+                context: context.to_vec(),
+            },
+        })
+    }
+}
+
+// One party's side of a DKG ceremony.  Every party broadcasts its commitment, sends each other
+// party its share once it has all the commitments, and finishes once it has every share.
+// In a Pedersen Dkg the first broadcast is a PedersenCommitment, and the commitment is only
+// broadcast in the extraction round, once every share is in.  A Dkg can be saved and resumed
+// in any state
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Dkg {
+    pub id: usize,
+    n: usize,
+    party: PartyState,
+    blinding: Option<Polynomial<Scalar>>,
+    rounds: DkgRounds,
+}
+
+impl Dkg {
+    // context is the application context, see Party::set_context
+    pub fn new<RNG: RngCore + CryptoRng>(
+        id: usize,
+        n: usize,
+        t: usize,
+        scheme: DkgScheme,
+        context: &[u8],
+        rng: &mut RNG,
+    ) -> Self {
+        let mut party = Party::new(id, n, t, rng);
+        party.set_context(context);
+        let commitment = party.get_poly_commitment(rng);
+
+        let blinding = match scheme {
+            DkgScheme::Feldman => None,
+            DkgScheme::Pedersen => Some(VSS::random_poly(t - 1, rng)),
+        };
+        let pedersen_commitment = blinding
+            .as_ref()
+            .map(|b| PedersenCommitment::new(id, &party.f, b));
+
+        Self {
+            id,
+            n,
+            party: party.save(),
+            blinding,
+            rounds: DkgRounds::new(id, n, [id], commitment, pedersen_commitment, context),
+        }
+    }
+
+    pub fn scheme(&self) -> DkgScheme {
+        self.rounds.scheme()
+    }
+
+    pub fn state(&self) -> DkgState {
+        self.rounds.state()
+    }
+
+    fn party(&self) -> Party {
+        Party::load(self.id, self.n, &Point::zero(), &self.party)
+    }
+
+    // the parties which have not sent their message for the current round
+    pub fn missing(&self) -> Vec<usize> {
+        self.rounds.missing()
+    }
+
+    // the round one broadcast of a Pedersen Dkg
+    pub fn pedersen_commitment(&self) -> Option<PedersenCommitment> {
+        self.rounds.pedersen_commitment()
+    }
+
+    // In a Pedersen Dkg the commitment is only revealed once every share is in, and asking
+    // for it then starts the extraction round
+    pub fn commitment(&mut self) -> Result<DkgCommitment, DkgError> {
+        self.rounds.commitment()
+    }
+
+    // a commitment which was already received can be received again
+    pub fn receive_pedersen_commitment(
+        &mut self,
+        msg: &PedersenCommitment,
+    ) -> Result<(), DkgError> {
+        self.rounds.receive_pedersen_commitment(msg)
+    }
+
+    // a commitment which was already received can be received again
+    pub fn receive_commitment(&mut self, msg: &DkgCommitment) -> Result<(), DkgError> {
+        self.rounds.receive_commitment(msg)
+    }
+
+    // Once every commitment is in, the shares to send to the other parties.  This party's own
+    // share is kept, and the shares can be sent again until the Dkg is done
+    pub fn shares(&mut self) -> Result<Vec<DkgShare>, DkgError> {
+        self.rounds
+            .shares(&self.party.polynomial, self.blinding.as_ref(), self.n)
+    }
+
+    // a share which was already received can be received again
+    pub fn receive_share(&mut self, msg: &DkgShare) -> Result<(), DkgError> {
+        self.rounds.receive_share(msg)
+    }
+
+    // In the extraction round of a Pedersen Dkg, the shares from dealers whose commitments are
+    // missing or were refused, to broadcast so every party can rebuild them
+    pub fn recovery_shares(&self, dealers: &[usize]) -> Result<Vec<RecoveryShare>, DkgError> {
+        self.rounds.recovery_shares(dealers)
+    }
+
+    // a recovery share which was already received can be received again
+    pub fn receive_recovery_share(&mut self, msg: &RecoveryShare) -> Result<(), DkgError> {
+        self.rounds.receive_recovery_share(msg)
+    }
+
+    #[allow(non_snake_case)]
+    pub fn finish(&mut self) -> Result<KeyPackage, DkgError> {
+        let A = self.rounds.check_finish()?;
+        let shares = self.rounds.received_shares()[&self.id]
+            .iter()
+            .map(|(i, s)| (*i, *s))
+            .collect();
+        let mut party = self.party();
+        party.compute_secret(shares, &A)?;
+
+        self.rounds.done();
+        Ok(KeyPackage {
+            id: self.id,
+            n: self.n,
+            threshold: self.rounds.threshold(),
+            group_key: party.group_key,
+            public_keys: (0..self.n).map(|i| dkg::public_key(i, &A)).collect(),
+            poly_commitments: A,
+            state: party.save(),
+        })
+    }
+}
+
+// One party's side of a proactive refresh of a KeyPackage.  It runs like a Dkg, but every
+// party deals a polynomial with a zero constant term and adds the shares it receives to its
+// private key.  The group key is unchanged, but shares from before the refresh no longer
+// combine with the new ones.  Every party must take part
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Refresh {
+    key_package: KeyPackage,
+    polynomial: Polynomial<Scalar>,
+    rounds: Rounds<Vec<Point>>,
+}
+
+impl Refresh {
+    #[allow(non_snake_case)]
+    pub fn new<RNG: RngCore + CryptoRng>(key_package: KeyPackage, rng: &mut RNG) -> Self {
+        let polynomial = VSS::random_zero_poly(key_package.threshold - 1, rng);
+        let A = polynomial.data().iter().map(|a| a * G).collect();
+
+        let (id, n) = (key_package.id, key_package.n);
+        Self {
+            polynomial,
+            rounds: Rounds::new((0..n).collect(), [id]).with_commitment(id, A),
+            key_package,
+        }
+    }
+
+    pub fn state(&self) -> DkgState {
+        self.rounds.state()
+    }
+
+    // the parties which have not sent their message for the current round
+    pub fn missing(&self) -> Vec<usize> {
+        self.rounds.missing()
+    }
+
+    pub fn commitment(&self) -> RefreshCommitment {
+        RefreshCommitment {
+            sender: self.key_package.id,
+            A: self.rounds.commitments()[&self.key_package.id].clone(),
+        }
+    }
+
+    // a commitment which was already received can be received again
+    pub fn receive_commitment(&mut self, msg: &RefreshCommitment) -> Result<(), DkgError> {
+        let threshold = self.key_package.threshold;
+        self.rounds
+            .receive_commitment(DkgState::Commitments, msg.sender, &msg.A, |_| {
+                dkg::check_refresh_commitment(msg, threshold)
+            })
+    }
+
+    // Once every commitment is in, the shares to send to the other parties.  This party's own
+    // share is kept, and the shares can be sent again until the Refresh is done
+    pub fn shares(&mut self) -> Result<Vec<DkgShare>, DkgError> {
+        self.rounds.start_shares()?;
+        let (id, n) = (self.key_package.id, self.key_package.n);
+        Ok(self.rounds.deal(id, &self.polynomial, None, n))
+    }
+
+    // a share which was already received can be received again
+    pub fn receive_share(&mut self, msg: &DkgShare) -> Result<(), DkgError> {
+        self.rounds.receive_feldman_share(msg)
+    }
+
+    // the refreshed KeyPackage, with the same group key
+    #[allow(non_snake_case)]
+    pub fn finish(&mut self) -> Result<KeyPackage, DkgError> {
+        self.rounds.check_finish()?;
+
+        let mut key_package = self.key_package.clone();
+        let state = &mut key_package.state;
+        state.private_key += self.rounds.share_sum(key_package.id);
+        // state.polynomial is left as the Dkg polynomial, whose shares were already sent.  The
+        // refresh is only in the private key, public keys and poly commitments
+        let commitments = self.rounds.commitments();
+        for (i, public_key) in key_package.public_keys.iter_mut().enumerate() {
+            let id = Scalar::from((i + 1) as u32);
+            *public_key = commitments
+                .values()
+                .fold(*public_key, |p, R| p + compute::poly(&id, R));
+        }
+        key_package.poly_commitments =
+            dkg::refresh_poly_commitments(&key_package.poly_commitments, commitments);
+
+        self.rounds.done();
+        Ok(key_package)
+    }
+}
+
+// One new party's side of a Reshare, which moves the group secret to a new committee with its
+// own n and t.  At least the old threshold of the old parties deal their part of the secret
+// with Party::reshare, and each dealer's commitment is checked against its old public key, so
+// the new committee has the same group key.  Shares are refused until every commitment is in
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Reshare {
+    pub id: usize,
+    n: usize,
+    threshold: usize,
+    group_key: Point,
+    dealer_keys: BTreeMap<usize, Point>, // each dealer's old public key times its lambda
+    context: Vec<u8>,
+    rounds: Rounds<PolyCommitment>,
+}
+
+impl Reshare {
+    // dealers are the old party ids which deal, at least old_t of them, and old_public_keys
+    // are indexed by old party id
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        id: usize,
+        n: usize,
+        t: usize,
+        old_t: usize,
+        dealers: &[usize],
+        group_key: &Point,
+        old_public_keys: &[Point],
+        context: &[u8],
+    ) -> Result<Self, DkgError> {
+        if let Some(i) = dealers.iter().find(|i| **i >= old_public_keys.len()) {
+            return Err(DkgError::UnknownParty(*i));
+        }
+        let dealer_ids: Vec<(usize, Vec<usize>)> = dealers.iter().map(|i| (*i, vec![*i])).collect();
+        dkg::check_dealers(&dealer_ids, old_t)?;
+        let dealer_keys: BTreeMap<usize, Point> = dealers
+            .iter()
+            .map(|i| (*i, compute::lambda(i, dealers) * old_public_keys[*i]))
+            .collect();
+
+        Ok(Self {
+            id,
+            n,
+            threshold: t,
+            group_key: *group_key,
+            rounds: Rounds::new(dealer_keys.keys().cloned().collect(), [id]),
+            dealer_keys,
+            context: context.to_vec(),
+        })
+    }
+
+    pub fn state(&self) -> DkgState {
+        self.rounds.state()
+    }
+
+    // the dealers which have not sent their message for the current round
+    pub fn missing(&self) -> Vec<usize> {
+        self.rounds.missing()
+    }
+
+    // a commitment which was already received can be received again
+    pub fn receive_commitment(&mut self, msg: &DkgCommitment) -> Result<(), DkgError> {
+        let (threshold, context) = (self.threshold, &self.context);
+        let dealer_key = self.dealer_keys.get(&msg.sender);
+        self.rounds.receive_commitment(
+            DkgState::Commitments,
+            msg.sender,
+            &msg.commitment,
+            |c| dkg::check_commitment(msg, threshold, context) && dealer_key == Some(&c.A[0]),
+        )?;
+        if self.rounds.missing().is_empty() {
+            self.rounds.start_shares()?;
+        }
+        Ok(())
+    }
+
+    // a share which was already received can be received again
+    pub fn receive_share(&mut self, msg: &DkgShare) -> Result<(), DkgError> {
+        self.rounds.receive_feldman_share(msg)
+    }
+
+    // The KeyPackage for the new committee.  Its poly commitments are the dealers', so a
+    // SignatureAggregator for it must be made with SignatureAggregator::from_key_package
+    #[allow(non_snake_case)]
+    pub fn finish(&mut self) -> Result<KeyPackage, DkgError> {
+        self.rounds.check_finish()?;
+
+        // with fewer dealers than the old threshold the dealer keys don't interpolate
+        let A: Vec<PolyCommitment> = self.rounds.commitments().values().cloned().collect();
+        let group_key = A.iter().fold(Point::zero(), |key, Ai| key + Ai.A[0]);
+        if group_key != self.group_key {
+            return Err(DkgError::BadGroupKey);
+        }
+
+        self.rounds.done();
+        Ok(KeyPackage {
+            id: self.id,
+            n: self.n,
+            threshold: self.threshold,
+            group_key,
+            public_keys: (0..self.n).map(|i| dkg::public_key(i, &A)).collect(),
+            poly_commitments: A,
+            state: PartyState {
+                private_key: self.rounds.share_sum(self.id),
+                // a new party dealt no polynomial of its own
+                polynomial: Polynomial::new(vec![Scalar::zero(); self.threshold]),
+                context: self.context.clone(),
+            },
+        })
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SignerState {
+    n: usize,
+    group_key: Point,
+    parties: HashMap<usize, PartyState>,
+}
+
+impl SignerState {
+    // the state of a Signer holding the parties the shares are for, from a trusted dealer
+    pub fn from_dealer(
+        n: usize,
+        commitment: &DkgCommitment,
+        shares: &[DkgShare],
+        context: &[u8],
+    ) -> Result<Self, DkgError> {
+        let mut parties = HashMap::new();
+        for share in shares {
+            let key_package = KeyPackage::from_dealer(share.key_id, n, commitment, share, context)?;
+            parties.insert(share.key_id, key_package.state);
+        }
+
+        Ok(Self {
+            n,
+            group_key: commitment.commitment.A[0],
+            parties,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Signer {
+    pub n: usize,
+    pub group_key: Point,
+    pub parties: Vec<Party>,
+}
+
+impl Signer {
+    pub fn new<RNG: RngCore + CryptoRng>(ids: &[usize], n: usize, t: usize, rng: &mut RNG) -> Self {
+        let parties = ids.iter().map(|id| Party::new(*id, n, t, rng)).collect();
+        Signer {
+            n,
+            group_key: Point::zero(),
+            parties,
+        }
+    }
+
+    pub fn load(state: &SignerState) -> Self {
+        let mut parties: Vec<Party> = state
+            .parties
+            .iter()
+            .map(|(id, ps)| Party::load(*id, state.n, &state.group_key, ps))
+            .collect();
+        parties.sort_by_key(|p| p.id);
+
+        Self {
+            n: state.n,
+            group_key: state.group_key,
+            parties,
+        }
+    }
+
+    pub fn save(&self) -> SignerState {
+        let mut parties = HashMap::new();
+
+        for party in &self.parties {
+            parties.insert(party.id, party.save());
+        }
+
+        SignerState {
+            n: self.n,
+            group_key: self.group_key,
+            parties,
+        }
+    }
+
+    pub fn set_context(&mut self, context: &[u8]) {
+        for party in self.parties.iter_mut() {
+            party.set_context(context);
+        }
+    }
+
+    pub fn get_poly_commitments<RNG: RngCore + CryptoRng>(
+        &self,
+        rng: &mut RNG,
+    ) -> Vec<PolyCommitment> {
+        self.parties
+            .iter()
+            .map(|p| p.get_poly_commitment(rng))
+            .collect()
+    }
+
+    pub fn get_ids(&self) -> Vec<usize> {
+        self.parties.iter().map(|p| p.id).collect()
+    }
+
+    // Every party is checked before any of them takes its nonce, so a package which one party
+    // refuses doesn't use up the nonces of the others
+    fn sign_parties<F>(
+        &mut self,
+        package: &SigningPackage,
+        sign: F,
+    ) -> Result<Vec<SignatureShare>, SignerError>
+    where
+        F: Fn(&mut Party) -> Result<Scalar, SignerError>,
+    {
+        for party in &self.parties {
+            party.check_package(package)?;
+            party.check_nonce(package)?;
+        }
+
+        self.parties
+            .iter_mut()
+            .map(|p| {
+                Ok(SignatureShare {
+                    id: p.id,
+                    z_i: sign(p)?,
+                    key_ids: vec![p.id],
+                })
+            })
+            .collect()
+    }
+}
+
+impl traits::DkgParticipant for Signer {
+    fn get_poly_commitments<RNG: RngCore + CryptoRng>(&self, rng: &mut RNG) -> Vec<PolyCommitment> {
+        Signer::get_poly_commitments(self, rng)
+    }
+
+    fn get_shares(&self) -> HashMap<usize, HashMap<usize, Scalar>> {
+        self.parties
+            .iter()
+            .map(|p| (p.id, p.get_shares()))
+            .collect()
+    }
+
+    #[allow(non_snake_case)]
+    fn compute_secrets(
+        &mut self,
+        shares: &HashMap<usize, HashMap<usize, Scalar>>,
+        A: &[PolyCommitment],
+    ) -> Result<(), DkgError> {
+        for party in self.parties.iter_mut() {
+            let party_shares = shares
+                .iter()
+                .filter_map(|(sender, s)| s.get(&party.id).map(|share| (*sender, *share)))
+                .collect();
+            party.compute_secret(party_shares, A)?;
+            self.group_key = party.group_key;
+        }
+        Ok(())
+    }
+}
+
+impl traits::Signer for Signer {
+    fn get_ids(&self) -> Vec<usize> {
+        Signer::get_ids(self)
+    }
+
+    // each v1 party holds a single key whose id is the party id
+    fn get_key_ids(&self) -> Vec<usize> {
+        Signer::get_ids(self)
+    }
+
+    fn get_signer_key_ids(&self) -> Vec<(usize, Vec<usize>)> {
+        self.parties.iter().map(|p| (p.id, vec![p.id])).collect()
+    }
+
+    fn gen_nonces<RNG: RngCore + CryptoRng>(&mut self, rng: &mut RNG) -> Vec<PublicNonce> {
+        self.parties.iter_mut().map(|p| p.gen_nonce(rng)).collect()
+    }
+
+    fn sign(&mut self, package: &SigningPackage) -> Result<Vec<SignatureShare>, SignerError> {
+        self.sign_parties(package, |p| p.sign(package))
+    }
+
+    fn sign_bip340(
+        &mut self,
+        package: &SigningPackage,
+    ) -> Result<Vec<SignatureShare>, SignerError> {
+        self.sign_parties(package, |p| p.sign_bip340(package))
+    }
+
+    fn sign_taproot(
+        &mut self,
+        package: &SigningPackage,
+        merkle_root: Option<[u8; 32]>,
+    ) -> Result<Vec<SignatureShare>, SignerError> {
+        self.sign_parties(package, |p| p.sign_taproot(package, merkle_root))
+    }
+}
+
+impl traits::Aggregator for SignatureAggregator {
+    #[allow(non_snake_case)]
+    fn new(
+        num_keys: usize,
+        threshold: usize,
+        A: Vec<PolyCommitment>,
+    ) -> Result<Self, AggregatorError> {
+        SignatureAggregator::new(num_keys, threshold, A)
+    }
+
+    fn sign(
+        &mut self,
+        package: &SigningPackage,
+        sig_shares: &[SignatureShare],
+    ) -> Result<Signature, AggregatorError> {
+        SignatureAggregator::sign(self, package, sig_shares)
+    }
+
+    fn sign_bip340(
+        &mut self,
+        package: &SigningPackage,
+        sig_shares: &[SignatureShare],
+    ) -> Result<SchnorrProof, AggregatorError> {
+        SignatureAggregator::sign_bip340(self, package, sig_shares)
+    }
+
+    fn sign_taproot(
+        &mut self,
+        package: &SigningPackage,
+        sig_shares: &[SignatureShare],
+        merkle_root: Option<[u8; 32]>,
+    ) -> Result<SchnorrProof, AggregatorError> {
+        SignatureAggregator::sign_taproot(self, package, sig_shares, merkle_root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::iter::zip;
+
+    use crate::bip340::{self, SchnorrProof};
+    use crate::common::{PolyCommitment, PublicNonce, SignatureShare, SigningPackage};
+    use crate::compute;
+    use crate::dkg::{
+        self, Complaint, DkgCommitment, DkgScheme, DkgShare, DkgState, EncryptedShare,
+        Justification, PedersenCommitment, RecoveryShare, RefreshCommitment, RepairShare,
+        RepairSum,
+    };
+    #[cfg(feature = "reconstruct")]
+    use crate::errors::ReconstructError;
+    use crate::errors::{AggregatorError, DkgError, SignerError, SigningPackageError};
+    use crate::rfc9591;
+    use crate::taproot;
+    use crate::traits::Signer;
+    use crate::v1;
+
+    use hashbrown::HashMap;
+    use num_traits::{One, Zero};
+    use rand_core::{CryptoRng, OsRng, RngCore};
+    use secp256k1_math::{
+        point::{Point, G},
+        scalar::Scalar,
+    };
+
+    #[test]
+    fn signer_new() {
+        let mut rng = OsRng::default();
+        let ids = [1, 2, 3];
+        let n: usize = 10;
+        let t: usize = 7;
+
+        let signer = v1::Signer::new(&ids, n, t, &mut rng);
+
+        assert_eq!(signer.parties.len(), ids.len());
+    }
+
+    #[test]
+    fn signer_gen_nonces() {
+        let mut rng = OsRng::default();
+        let ids = [1, 2, 3];
+        let n: usize = 10;
+        let t: usize = 7;
+
+        let mut signer = v1::Signer::new(&ids, n, t, &mut rng);
+
+        for party in &signer.parties {
+            assert!(party.nonce.is_zero());
+        }
+
+        let nonces = signer.gen_nonces(&mut rng);
+
+        assert_eq!(nonces.len(), ids.len());
+
+        for party in &signer.parties {
+            assert!(!party.nonce.is_zero());
+        }
+    }
+
+    #[test]
+    fn signer_save_load() {
+        let mut rng = OsRng::default();
+        let ids = [1, 2, 3];
+        let n: usize = 10;
+        let t: usize = 7;
+
+        let signer = v1::Signer::new(&ids, n, t, &mut rng);
+
+        let state = signer.save();
+        let loaded = v1::Signer::load(&state);
+
+        assert_eq!(signer, loaded);
+    }
+
+    #[allow(non_snake_case)]
+    fn dkg<RNG: RngCore + CryptoRng>(
+        signers: &mut Vec<v1::Signer>,
+        rng: &mut RNG,
+    ) -> Vec<PolyCommitment> {
+        let A: Vec<PolyCommitment> = signers
+            .iter()
+            .flat_map(|s| s.get_poly_commitments(rng))
+            .collect();
+
+        // each party broadcasts their commitments
+        // these hashmaps will need to be serialized in tuples w/ the value encrypted
+        let mut broadcast_shares = Vec::new();
+        for signer in signers.iter() {
+            for party in &signer.parties {
+                broadcast_shares.push((party.id, party.get_shares()));
+            }
+        }
+
+        // each party collects its shares from the broadcasts
+        // maybe this should collect into a hashmap first?
+        for signer in signers.iter_mut() {
+            for party in signer.parties.iter_mut() {
+                let mut h = HashMap::new();
+
+                for (id, share) in &broadcast_shares {
+                    h.insert(*id, share[&party.id]);
+                }
+
+                party.compute_secret(h, &A).unwrap();
+            }
+        }
+
+        A
+    }
+
+    // a package with fresh nonces from every party of the signers
+    fn signing_package<RNG: RngCore + CryptoRng>(
+        msg: &[u8],
+        signers: &mut [v1::Signer],
+        t: usize,
+        rng: &mut RNG,
+    ) -> SigningPackage {
+        let ids: Vec<usize> = signers.iter().flat_map(|s| s.get_ids()).collect();
+        let nonces: Vec<PublicNonce> = signers.iter_mut().flat_map(|s| s.gen_nonces(rng)).collect();
+        let nonces: Vec<(usize, PublicNonce)> = ids.into_iter().zip(nonces).collect();
+
+        SigningPackage::new(msg, &nonces, t).unwrap()
+    }
+
+    // There might be a slick one-liner for this?
+    fn sign<RNG: RngCore + CryptoRng>(
+        msg: &[u8],
+        signers: &mut [v1::Signer],
+        t: usize,
+        rng: &mut RNG,
+    ) -> (SigningPackage, Vec<SignatureShare>) {
+        let package = signing_package(msg, signers, t, rng);
+        let shares = signers
+            .iter_mut()
+            .flat_map(|s| s.sign(&package).unwrap())
+            .collect();
+
+        (package, shares)
+    }
+
+    #[allow(non_snake_case)]
+    #[test]
     fn signer_sign() {
         let mut rng = OsRng::default();
         let msg = "It was many and many a year ago".as_bytes();
-        let N: usize = 10;
-        let T: usize = 7;
-        let signer_ids: Vec<Vec<usize>> = [
-            [0, 1, 2].to_vec(),
-            [3, 4].to_vec(),
-            [5, 6, 7].to_vec(),
-            [8, 9].to_vec(),
-        ]
-        .to_vec();
-        let mut signers = signer_ids
+        let N: usize = 10;
+        let T: usize = 7;
+        let signer_ids: Vec<Vec<usize>> = [
+            [0, 1, 2].to_vec(),
+            [3, 4].to_vec(),
+            [5, 6, 7].to_vec(),
+            [8, 9].to_vec(),
+        ]
+        .to_vec();
+        let mut signers = signer_ids
+            .iter()
+            .map(|ids| v1::Signer::new(ids, N, T, &mut rng))
+            .collect();
+
+        let A = dkg(&mut signers, &mut rng);
+
+        // signers [0,1,3] who have T keys
+        {
+            let mut signers = [signers[0].clone(), signers[1].clone(), signers[3].clone()].to_vec();
+            let mut sig_agg = v1::SignatureAggregator::new(N, T, A.clone()).unwrap();
+
+            let (package, sig_shares) = sign(&msg, &mut signers, T, &mut rng);
+            let sig = sig_agg.sign(&package, &sig_shares).unwrap();
+            assert!(sig.verify(&sig_agg.key, &msg, &sig_agg.context));
+
+            // a package one party refuses leaves the nonces of the signer's other parties
+            let package = signing_package(&msg, &mut signers, T, &mut rng);
+            let mut nonces: Vec<(usize, PublicNonce)> = package
+                .signers()
+                .into_iter()
+                .zip(package.nonces())
+                .collect();
+            nonces[2].1 = nonces[0].1.clone();
+            let bad_package = SigningPackage::new(&msg, &nonces, T).unwrap();
+            assert_eq!(
+                signers[0].sign(&bad_package).err(),
+                Some(SignerError::BadPublicNonce(2))
+            );
+            let sig_shares: Vec<SignatureShare> = signers
+                .iter_mut()
+                .flat_map(|s| s.sign(&package).unwrap())
+                .collect();
+            let sig = sig_agg.sign(&package, &sig_shares).unwrap();
+            assert!(sig.verify(&sig_agg.key, &msg, &sig_agg.context));
+        }
+    }
+
+    #[allow(non_snake_case)]
+    #[test]
+    fn signer_sign_context() {
+        let mut rng = OsRng::default();
+        let msg = "It was many and many a year ago".as_bytes();
+        let N: usize = 5;
+        let T: usize = 3;
+        let ctx = "chain 1".as_bytes();
+        let other_ctx = "chain 2".as_bytes();
+
+        let mut signers: Vec<v1::Signer> = [[0, 1].to_vec(), [2].to_vec(), [3, 4].to_vec()]
+            .iter()
+            .map(|ids| v1::Signer::new(ids, N, T, &mut rng))
+            .collect();
+        for signer in signers.iter_mut() {
+            signer.set_context(ctx);
+        }
+        let A = dkg(&mut signers, &mut rng);
+        let qualified = (0..N).collect();
+
+        // proofs of knowledge from one deployment are rejected in another
+        assert_eq!(
+            v1::SignatureAggregator::new(N, T, A.clone()).err(),
+            Some(AggregatorError::BadPolyCommitments((0..N).collect()))
+        );
+        assert_eq!(
+            v1::SignatureAggregator::new_qualified(N, T, A.clone(), &qualified, other_ctx).err(),
+            Some(AggregatorError::BadPolyCommitments((0..N).collect()))
+        );
+        assert!(v1::resolve_complaints(&A, &[], &[], other_ctx).is_empty());
+
+        let mut sig_agg = v1::SignatureAggregator::new_qualified(N, T, A, &qualified, ctx).unwrap();
+        let mut signers = [signers[0].clone(), signers[2].clone()].to_vec();
+        let (package, sig_shares) = sign(msg, &mut signers, T, &mut rng);
+        let sig = sig_agg.sign(&package, &sig_shares).unwrap();
+
+        assert!(sig.verify(&sig_agg.key, msg, ctx));
+        assert!(!sig.verify(&sig_agg.key, msg, other_ctx));
+        assert!(!sig.verify(&sig_agg.key, msg, &[]));
+    }
+
+    #[allow(non_snake_case)]
+    #[test]
+    fn signer_sign_bip340() {
+        let mut rng = OsRng::default();
+        // 32 bytes, so libsecp256k1 can check the signatures too
+        let msg = b"It was many and many a year ago.";
+        let N: usize = 5;
+        let T: usize = 3;
+
+        // repeat with new keys and nonces to cover both parities of the group key and R
+        for _ in 0..4 {
+            let mut signers: Vec<v1::Signer> = [[0, 1].to_vec(), [2].to_vec(), [3, 4].to_vec()]
+                .iter()
+                .map(|ids| v1::Signer::new(ids, N, T, &mut rng))
+                .collect();
+            let A = dkg(&mut signers, &mut rng);
+            let mut sig_agg = v1::SignatureAggregator::new(N, T, A).unwrap();
+            let public_key = bip340::x_only(&sig_agg.key);
+
+            for _ in 0..2 {
+                let mut signers = [signers[0].clone(), signers[2].clone()].to_vec();
+                let package = signing_package(msg, &mut signers, T, &mut rng);
+                let sig_shares: Vec<SignatureShare> = signers
+                    .iter_mut()
+                    .flat_map(|s| s.sign_bip340(&package).unwrap())
+                    .collect();
+
+                let proof = sig_agg.sign_bip340(&package, &sig_shares).unwrap();
+                let bytes = proof.to_bytes();
+                let proof = SchnorrProof::from_bytes(&bytes).unwrap();
+                assert!(proof.verify(&public_key, msg));
+                assert!(bip340::libsecp256k1_verify(&proof, &public_key, msg));
+
+                // shares for the SHA3 challenge are rejected
+                let (package, sig_shares) = sign(msg, &mut signers, T, &mut rng);
+                assert!(sig_agg.sign_bip340(&package, &sig_shares).is_err());
+            }
+        }
+    }
+
+    #[allow(non_snake_case)]
+    #[test]
+    fn signer_sign_taproot() {
+        let mut rng = OsRng::default();
+        let msg = "It was many and many a year ago".as_bytes();
+        let N: usize = 5;
+        let T: usize = 3;
+
+        // repeat with new keys to cover both parities of the internal and output keys
+        for merkle_root in [None, Some([7u8; 32]), None, Some([7u8; 32])] {
+            let mut signers: Vec<v1::Signer> = [[0, 1].to_vec(), [2].to_vec(), [3, 4].to_vec()]
+                .iter()
+                .map(|ids| v1::Signer::new(ids, N, T, &mut rng))
+                .collect();
+            let A = dkg(&mut signers, &mut rng);
+            let mut sig_agg = v1::SignatureAggregator::new(N, T, A).unwrap();
+            let output_key = taproot::output_key(&sig_agg.key, merkle_root).unwrap();
+
+            let mut signers = [signers[0].clone(), signers[2].clone()].to_vec();
+            let package = signing_package(msg, &mut signers, T, &mut rng);
+            let sig_shares: Vec<SignatureShare> = signers
+                .iter_mut()
+                .flat_map(|s| s.sign_taproot(&package, merkle_root).unwrap())
+                .collect();
+
+            let proof = sig_agg
+                .sign_taproot(&package, &sig_shares, merkle_root)
+                .unwrap();
+            assert!(proof.verify(&output_key, msg));
+            assert!(!proof.verify(&bip340::x_only(&sig_agg.key), msg));
+
+            // shares for a different script tree are rejected
+            assert!(sig_agg
+                .sign_taproot(&package, &sig_shares, Some([8u8; 32]))
+                .is_err());
+        }
+    }
+
+    #[allow(non_snake_case)]
+    #[test]
+    fn signer_sign_rfc9591() {
+        let mut rng = OsRng::default();
+        let msg = "It was many and many a year ago".as_bytes();
+        let N: usize = 5;
+        let T: usize = 3;
+
+        let mut signers: Vec<v1::Signer> = [[0, 1].to_vec(), [2].to_vec(), [3, 4].to_vec()]
+            .iter()
+            .map(|ids| v1::Signer::new(ids, N, T, &mut rng))
+            .collect();
+        let A = dkg(&mut signers, &mut rng);
+        let mut sig_agg = v1::SignatureAggregator::new(N, T, A).unwrap();
+
+        // the commitment list is in no particular order
+        let mut parties = [
+            signers[2].parties[0].clone(),
+            signers[0].parties[1].clone(),
+            signers[1].parties[0].clone(),
+        ];
+        let nonces: Vec<(usize, PublicNonce)> = parties
+            .iter_mut()
+            .map(|p| (p.id, p.gen_nonce_rfc9591(&mut rng)))
+            .collect();
+        let package = SigningPackage::new(msg, &nonces, T).unwrap();
+        let sig_shares: Vec<SignatureShare> = parties
+            .iter_mut()
+            .map(|p| SignatureShare {
+                id: p.id,
+                z_i: p.sign_rfc9591(&package).unwrap(),
+                key_ids: vec![p.id],
+            })
+            .collect();
+
+        let sig = sig_agg.sign_rfc9591(&package, &sig_shares).unwrap();
+        assert!(rfc9591::verify(&sig, &sig_agg.key, msg));
+        assert!(!sig.verify(&sig_agg.key, msg, &[]));
+
+        // shares for the SHA3 challenge are rejected
+        let nonces: Vec<(usize, PublicNonce)> = parties
+            .iter_mut()
+            .map(|p| (p.id, p.gen_nonce_rfc9591(&mut rng)))
+            .collect();
+        let package = SigningPackage::new(msg, &nonces, T).unwrap();
+        let sig_shares: Vec<SignatureShare> = parties
+            .iter_mut()
+            .map(|p| SignatureShare {
+                id: p.id,
+                z_i: p.sign(&package).unwrap(),
+                key_ids: vec![p.id],
+            })
+            .collect();
+        assert_eq!(
+            sig_agg.sign_rfc9591(&package, &sig_shares).err(),
+            Some(AggregatorError::BadPartySigs(package.signers()))
+        );
+    }
+
+    #[test]
+    fn party_nonce_single_use() {
+        let mut rng = OsRng::default();
+        let msg = "It was many and many a year ago".as_bytes();
+        let mut parties: Vec<v1::Party> =
+            (0..3).map(|i| v1::Party::new(i, 3, 2, &mut rng)).collect();
+        let signers = [0, 1];
+
+        let other_nonce = parties[2].gen_nonce(&mut rng);
+        let without_nonces =
+            SigningPackage::new(msg, &[(0, other_nonce.clone()), (2, other_nonce)], 2).unwrap();
+        assert_eq!(
+            parties[0].sign(&without_nonces),
+            Err(SignerError::MissingNonce(0))
+        );
+
+        let nonces: Vec<(usize, PublicNonce)> = signers
+            .iter()
+            .map(|i| (*i, parties[*i].gen_nonce(&mut rng)))
+            .collect();
+        let package = SigningPackage::new(msg, &nonces, 2).unwrap();
+
+        // a request with some other public nonce is refused, and leaves the nonce usable
+        let swapped = [(0, nonces[1].1.clone()), (1, nonces[0].1.clone())];
+        let swapped = SigningPackage::new(msg, &swapped, 2).unwrap();
+        assert_eq!(
+            parties[0].sign(&swapped),
+            Err(SignerError::BadPublicNonce(0))
+        );
+        let others = SigningPackage::new(msg, &nonces[1..], 1).unwrap();
+        assert_eq!(
+            parties[0].sign(&others),
+            Err(SignerError::MissingPublicNonces(vec![0]))
+        );
+
+        assert!(parties[0].sign(&package).is_ok());
+        assert!(parties[0].nonce.is_zero());
+        assert_eq!(parties[0].sign(&package), Err(SignerError::MissingNonce(0)));
+        let other_msg = SigningPackage::new("another message".as_bytes(), &nonces, 2).unwrap();
+        assert_eq!(
+            parties[0].sign_bip340(&other_msg),
+            Err(SignerError::MissingNonce(0))
+        );
+
+        parties[0].gen_nonce(&mut rng);
+        assert_eq!(
+            parties[0].sign(&package),
+            Err(SignerError::BadPublicNonce(0))
+        );
+    }
+
+    fn new_dkgs(n: usize, t: usize, scheme: DkgScheme, ctx: &[u8]) -> Vec<v1::Dkg> {
+        let mut rng = OsRng::default();
+        (0..n)
+            .map(|i| v1::Dkg::new(i, n, t, scheme, ctx, &mut rng))
+            .collect()
+    }
+
+    // deliver every commitment and share, which leaves a Feldman Dkg ready to finish and a
+    // Pedersen one ready to reveal its commitments
+    fn run_rounds(n: usize, t: usize, scheme: DkgScheme, ctx: &[u8]) -> Vec<v1::Dkg> {
+        let mut dkgs = new_dkgs(n, t, scheme, ctx);
+        match scheme {
+            DkgScheme::Feldman => {
+                let commitments: Vec<DkgCommitment> =
+                    dkgs.iter_mut().map(|d| d.commitment().unwrap()).collect();
+                for dkg in dkgs.iter_mut() {
+                    for commitment in &commitments {
+                        dkg.receive_commitment(commitment).unwrap();
+                    }
+                }
+            }
+            DkgScheme::Pedersen => {
+                let commitments: Vec<PedersenCommitment> = dkgs
+                    .iter()
+                    .map(|d| d.pedersen_commitment().unwrap())
+                    .collect();
+                for dkg in dkgs.iter_mut() {
+                    for commitment in &commitments {
+                        dkg.receive_pedersen_commitment(commitment).unwrap();
+                    }
+                }
+            }
+        }
+        let shares: Vec<DkgShare> = dkgs.iter_mut().flat_map(|d| d.shares().unwrap()).collect();
+        for share in &shares {
+            dkgs[share.key_id].receive_share(share).unwrap();
+        }
+        dkgs
+    }
+
+    // run a Feldman Dkg with every message delivered
+    fn run_dkg(n: usize, t: usize, ctx: &[u8]) -> Vec<v1::KeyPackage> {
+        run_rounds(n, t, DkgScheme::Feldman, ctx)
+            .iter_mut()
+            .map(|d| d.finish().unwrap())
+            .collect()
+    }
+
+    // move the group key of key_packages to a t of n group
+    fn run_reshare(
+        key_packages: &[v1::KeyPackage],
+        dealers: &[usize],
+        n: usize,
+        t: usize,
+        ctx: &[u8],
+    ) -> Vec<v1::KeyPackage> {
+        let mut rng = OsRng::default();
+        let old = &key_packages[0];
+        let mut reshares: Vec<v1::Reshare> = (0..n)
+            .map(|i| {
+                v1::Reshare::new(
+                    i,
+                    n,
+                    t,
+                    old.threshold,
+                    dealers,
+                    &old.group_key,
+                    &old.public_keys,
+                    ctx,
+                )
+                .unwrap()
+            })
+            .collect();
+        let dealt: Vec<(DkgCommitment, Vec<DkgShare>)> = dealers
+            .iter()
+            .map(|i| {
+                v1::Party::from_key_package(&key_packages[*i]).reshare(dealers, n, t, &mut rng)
+            })
+            .collect();
+        for reshare in reshares.iter_mut() {
+            for (commitment, _) in &dealt {
+                reshare.receive_commitment(commitment).unwrap();
+            }
+        }
+        for share in dealt.iter().flat_map(|(_, shares)| shares) {
+            reshares[share.key_id].receive_share(share).unwrap();
+        }
+        reshares.iter_mut().map(|r| r.finish().unwrap()).collect()
+    }
+
+    // split secret with a trusted dealer, and give each party its key package
+    fn run_trusted_deal(
+        secret: Scalar,
+        n: usize,
+        t: usize,
+        ctx: &[u8],
+    ) -> (DkgCommitment, Vec<DkgShare>, Vec<v1::KeyPackage>) {
+        let mut rng = OsRng::default();
+        let (commitment, shares) = dkg::trusted_deal(Some(secret), n, t, ctx, &mut rng);
+        let key_packages: Vec<v1::KeyPackage> = shares
+            .iter()
+            .map(|share| v1::KeyPackage::from_dealer(share.key_id, n, &commitment, share, ctx))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        (commitment, shares, key_packages)
+    }
+
+    #[test]
+    fn dkg_state_machine() {
+        let n = 4;
+        let t = 3;
+        let ctx = "chain 1".as_bytes();
+        let mut dkgs = new_dkgs(n, t, DkgScheme::Feldman, ctx);
+        let commitments: Vec<DkgCommitment> =
+            dkgs.iter_mut().map(|d| d.commitment().unwrap()).collect();
+
+        assert_eq!(dkgs[0].missing(), vec![1, 2, 3]);
+        assert_eq!(
+            dkgs[0].shares().err(),
+            Some(DkgError::MissingPolyCommitments(vec![1, 2, 3]))
+        );
+
+        // a commitment must be from a party in the ceremony, and be for its sender
+        let mut forged = commitments[1].clone();
+        forged.sender = 2;
+        assert_eq!(
+            dkgs[0].receive_commitment(&forged).err(),
+            Some(DkgError::BadPolyCommitments(vec![2]))
+        );
+        forged.sender = n;
+        assert_eq!(
+            dkgs[0].receive_commitment(&forged).err(),
+            Some(DkgError::UnknownParty(n))
+        );
+
+        for dkg in dkgs.iter_mut() {
+            for commitment in &commitments {
+                dkg.receive_commitment(commitment).unwrap();
+            }
+        }
+        assert!(dkgs[0].missing().is_empty());
+        assert_eq!(
+            dkgs[0].receive_share(&DkgShare {
+                sender: 1,
+                key_id: 0,
+                share: Scalar::one(),
+                blinding: None,
+            }),
+            Err(DkgError::OutOfOrder("share", "commitments"))
+        );
+
+        let shares: Vec<DkgShare> = dkgs.iter_mut().flat_map(|d| d.shares().unwrap()).collect();
+        assert_eq!(
+            dkgs[0].receive_commitment(&commitments[1]),
+            Err(DkgError::OutOfOrder("commitment", "shares"))
+        );
+
+        let mut bad_share = shares
+            .iter()
+            .find(|s| s.sender == 1 && s.key_id == 0)
+            .unwrap()
+            .clone();
+        bad_share.share += Scalar::one();
+        assert_eq!(
+            dkgs[0].receive_share(&bad_share),
+            Err(DkgError::BadShares(vec![1]))
+        );
+        let other_share = shares.iter().find(|s| s.key_id == 1).unwrap();
+        assert_eq!(
+            dkgs[0].receive_share(other_share),
+            Err(DkgError::WrongKeyId(1))
+        );
+
+        for share in shares.iter().filter(|s| s.sender != 3) {
+            dkgs[share.key_id].receive_share(share).unwrap();
+        }
+        assert_eq!(dkgs[0].missing(), vec![3]);
+        assert_eq!(
+            dkgs[0].finish().err(),
+            Some(DkgError::MissingShares(vec![3]))
+        );
+
+        // repeated messages are harmless
+        for share in &shares {
+            dkgs[share.key_id].receive_share(share).unwrap();
+        }
+
+        let key_packages: Vec<v1::KeyPackage> =
+            dkgs.iter_mut().map(|d| d.finish().unwrap()).collect();
+        assert!(matches!(
+            dkgs[0].finish(),
+            Err(DkgError::OutOfOrder("finish", "done"))
+        ));
+
+        let qualified = (0..n).collect();
+        let sig_agg = v1::SignatureAggregator::new_qualified(
+            n,
+            t,
+            key_packages[0].poly_commitments.clone(),
+            &qualified,
+            ctx,
+        )
+        .unwrap();
+        assert_eq!(sig_agg.key, key_packages[0].group_key);
+        assert_eq!(sig_agg.public_keys, key_packages[0].public_keys);
+
+        assert!(dkg_sign(&key_packages, ctx));
+    }
+
+    #[test]
+    fn pedersen_dkg() {
+        let mut rng = OsRng::default();
+        let n = 4;
+        let t = 3;
+        let ctx = "chain 1".as_bytes();
+        let mut dkgs = new_dkgs(n, t, DkgScheme::Pedersen, ctx);
+
+        // the poly commitments stay hidden until every share is in
+        assert_eq!(
+            dkgs[0].commitment().err(),
+            Some(DkgError::OutOfOrder("commitment", "commitments"))
+        );
+        let pedersen_commitments: Vec<PedersenCommitment> = dkgs
+            .iter()
+            .map(|d| d.pedersen_commitment().unwrap())
+            .collect();
+
+        // a party which deals from another polynomial than the one it committed to
+        let mut impostor = v1::Dkg::new(1, n, t, DkgScheme::Feldman, ctx, &mut rng);
+        assert_eq!(
+            impostor.receive_pedersen_commitment(&pedersen_commitments[0]),
+            Err(DkgError::WrongScheme(0))
+        );
+
+        for dkg in dkgs.iter_mut() {
+            for commitment in &pedersen_commitments {
+                dkg.receive_pedersen_commitment(commitment).unwrap();
+            }
+        }
+        let shares: Vec<DkgShare> = dkgs.iter_mut().flat_map(|d| d.shares().unwrap()).collect();
+
+        let mut bad_share = shares
+            .iter()
+            .find(|s| s.sender == 1 && s.key_id == 0)
+            .unwrap()
+            .clone();
+        bad_share.blinding = Some(bad_share.blinding.unwrap() + Scalar::one());
+        assert_eq!(
+            dkgs[0].receive_share(&bad_share),
+            Err(DkgError::BadShares(vec![1]))
+        );
+        bad_share.blinding = None;
+        assert_eq!(
+            dkgs[0].receive_share(&bad_share),
+            Err(DkgError::WrongScheme(1))
+        );
+        assert_eq!(
+            dkgs[0].commitment().err(),
+            Some(DkgError::MissingShares(vec![1, 2, 3]))
+        );
+
+        for share in &shares {
+            dkgs[share.key_id].receive_share(share).unwrap();
+        }
+        let commitments: Vec<DkgCommitment> =
+            dkgs.iter_mut().map(|d| d.commitment().unwrap()).collect();
+        assert_eq!(dkgs[0].state(), DkgState::Extraction);
+        assert_eq!(
+            dkgs[0].receive_commitment(&impostor.commitment().unwrap()),
+            Err(DkgError::BadShares(vec![1]))
+        );
+        assert_eq!(
+            dkgs[0].finish().err(),
+            Some(DkgError::MissingPolyCommitments(vec![1, 2, 3]))
+        );
+
+        for dkg in dkgs.iter_mut() {
+            for commitment in &commitments {
+                dkg.receive_commitment(commitment).unwrap();
+            }
+        }
+        let key_packages: Vec<v1::KeyPackage> =
+            dkgs.iter_mut().map(|d| d.finish().unwrap()).collect();
+
+        assert!(dkg_sign(&key_packages, ctx));
+    }
+
+    #[test]
+    fn pedersen_dkg_recovery() {
+        let mut rng = OsRng::default();
+        let n = 4;
+        let t = 3;
+        let ctx = "chain 1".as_bytes();
+        let mut dkgs = run_rounds(n, t, DkgScheme::Pedersen, ctx);
+
+        // party 3 sees every other commitment, then withholds its own, except from party 0
+        // which gets one it didn't deal from
+        let commitments: Vec<DkgCommitment> =
+            dkgs.iter_mut().map(|d| d.commitment().unwrap()).collect();
+        for dkg in dkgs.iter_mut() {
+            for commitment in &commitments[..3] {
+                dkg.receive_commitment(commitment).unwrap();
+            }
+        }
+        let mut impostor = v1::Dkg::new(3, n, t, DkgScheme::Feldman, ctx, &mut rng);
+        assert_eq!(
+            dkgs[0].receive_commitment(&impostor.commitment().unwrap()),
+            Err(DkgError::BadShares(vec![3]))
+        );
+        assert_eq!(
+            dkgs[1].finish().err(),
+            Some(DkgError::MissingPolyCommitments(vec![3]))
+        );
+
+        let recovery_shares: Vec<RecoveryShare> = dkgs[..3]
+            .iter()
+            .flat_map(|d| d.recovery_shares(&[3]).unwrap())
+            .collect();
+        let mut bad_share = recovery_shares[0].clone();
+        bad_share.share += Scalar::one();
+        assert_eq!(
+            dkgs[1].receive_recovery_share(&bad_share),
+            Err(DkgError::BadShares(vec![0]))
+        );
+        for dkg in dkgs.iter_mut() {
+            for share in &recovery_shares {
+                dkg.receive_recovery_share(share).unwrap();
+            }
+        }
+        let key_packages: Vec<v1::KeyPackage> =
+            dkgs.iter_mut().map(|d| d.finish().unwrap()).collect();
+
+        // party 3's secret is public now, but it is still part of the group key
+        let group_key = commitments
+            .iter()
+            .fold(Point::zero(), |key, c| key + c.commitment.A[0]);
+        assert!(key_packages.iter().all(|k| k.group_key == group_key));
+        assert!(dkg_sign(&key_packages, ctx));
+    }
+
+    #[test]
+    fn refresh() {
+        let mut rng = OsRng::default();
+        let n = 4;
+        let t = 3;
+        let ctx = "chain 1".as_bytes();
+        let old_packages = run_dkg(n, t, ctx);
+
+        let mut refreshes: Vec<v1::Refresh> = old_packages
+            .iter()
+            .map(|k| v1::Refresh::new(k.clone(), &mut rng))
+            .collect();
+        let commitments: Vec<RefreshCommitment> =
+            refreshes.iter().map(|r| r.commitment()).collect();
+
+        // a commitment to a nonzero constant term would change the group key
+        let mut bad_commitment = commitments[1].clone();
+        bad_commitment.A[0] = G;
+        assert_eq!(
+            refreshes[0].receive_commitment(&bad_commitment),
+            Err(DkgError::BadPolyCommitments(vec![1]))
+        );
+
+        for refresh in refreshes.iter_mut() {
+            for commitment in &commitments {
+                refresh.receive_commitment(commitment).unwrap();
+            }
+        }
+        let shares: Vec<DkgShare> = refreshes
+            .iter_mut()
+            .flat_map(|r| r.shares().unwrap())
+            .collect();
+        assert_eq!(
+            refreshes[0].finish().err(),
+            Some(DkgError::MissingShares(vec![1, 2, 3]))
+        );
+        for share in &shares {
+            refreshes[share.key_id].receive_share(share).unwrap();
+        }
+        let key_packages: Vec<v1::KeyPackage> =
+            refreshes.iter_mut().map(|r| r.finish().unwrap()).collect();
+
+        for (old, new) in zip(&old_packages, &key_packages) {
+            assert_eq!(new.group_key, old.group_key);
+            assert!(new.state.private_key != old.state.private_key);
+            assert_eq!(new.state.private_key * G, new.public_keys[new.id]);
+            assert!(old.state.private_key * G != new.public_keys[old.id]);
+            assert_eq!(new.state.polynomial.data(), old.state.polynomial.data());
+        }
+        assert!(dkg_sign(&key_packages, ctx));
+
+        // an old share no longer interpolates to the group secret along with new ones
+        let signers = [0, 1, 3];
+        let secret = |keys: [Scalar; 3]| {
+            zip(&signers, keys).fold(Scalar::zero(), |s, (i, key)| {
+                s + compute::lambda(i, &signers) * key
+            })
+        };
+        let new_keys = signers.map(|i| key_packages[i].state.private_key);
+        let mut mixed_keys = new_keys;
+        mixed_keys[1] = old_packages[1].state.private_key;
+        assert_eq!(secret(new_keys) * G, key_packages[0].group_key);
+        assert!(secret(mixed_keys) * G != key_packages[0].group_key);
+
+        // the refreshed keys can still repair a lost share and reshare the group key
+        let mut repaired = key_packages.clone();
+        repaired[1].state = run_repair(&key_packages, 1, &[0, 2, 3], ctx).unwrap();
+        assert_eq!(
+            repaired[1].state.private_key,
+            key_packages[1].state.private_key
+        );
+        assert!(dkg_sign(&repaired, ctx));
+
+        let reshared = run_reshare(&key_packages, &[0, 1, 3], 5, 4, ctx);
+        assert_eq!(reshared[0].group_key, key_packages[0].group_key);
+        assert!(dkg_sign(&reshared, ctx));
+    }
+
+    #[test]
+    fn reshare() {
+        let mut rng = OsRng::default();
+        let ctx = "chain 1".as_bytes();
+        let old_packages = run_dkg(4, 3, ctx);
+        let old_parties: Vec<v1::Party> = old_packages
+            .iter()
+            .map(v1::Party::from_key_package)
+            .collect();
+        let group_key = old_packages[0].group_key;
+        let old_public_keys = &old_packages[0].public_keys;
+
+        // move the 3 of 4 group to a 4 of 5 group
+        let n = 5;
+        let t = 4;
+        let dealers = [0, 2, 3];
+        let key_packages = run_reshare(&old_packages, &dealers, n, t, ctx);
+        for key_package in &key_packages {
+            assert_eq!(key_package.group_key, group_key);
+            assert_eq!(key_package.threshold, t);
+        }
+        assert!(dkg_sign(&key_packages, ctx));
+
+        // fewer dealers than the old threshold, a repeated dealer or an unknown one is refused
+        let new_reshare = |old_t, dealers: &[usize]| {
+            v1::Reshare::new(0, n, t, old_t, dealers, &group_key, old_public_keys, ctx)
+        };
+        assert_eq!(
+            new_reshare(3, &[0, 2]).err(),
+            Some(DkgError::BelowThreshold(3, 2))
+        );
+        assert_eq!(
+            new_reshare(3, &[0, 2, 2]).err(),
+            Some(DkgError::DuplicateMessage(2))
+        );
+        assert_eq!(
+            new_reshare(3, &[0, 2, 4]).err(),
+            Some(DkgError::UnknownParty(4))
+        );
+
+        // a dealer which deals anything but its own part of the group secret is caught
+        let mut reshare = new_reshare(3, &dealers).unwrap();
+        let (bad_commitment, _) = old_parties[2].reshare(&[0, 1, 2], n, t, &mut rng);
+        assert_eq!(
+            reshare.receive_commitment(&bad_commitment),
+            Err(DkgError::BadPolyCommitments(vec![2]))
+        );
+        let (other_commitment, _) = old_parties[1].reshare(&[0, 1, 2], n, t, &mut rng);
+        assert_eq!(
+            reshare.receive_commitment(&other_commitment),
+            Err(DkgError::UnknownParty(1))
+        );
+
+        // and with an old threshold which is too low the dealers can't reconstruct the group key
+        let dealers = [0, 2];
+        let mut reshare = new_reshare(2, &dealers).unwrap();
+        let dealt: Vec<(DkgCommitment, Vec<DkgShare>)> = dealers
+            .iter()
+            .map(|i| old_parties[*i].reshare(&dealers, n, t, &mut rng))
+            .collect();
+        for (commitment, _) in &dealt {
+            reshare.receive_commitment(commitment).unwrap();
+        }
+        for (_, shares) in &dealt {
+            reshare.receive_share(&shares[0]).unwrap();
+        }
+        assert_eq!(reshare.finish().err(), Some(DkgError::BadGroupKey));
+    }
+
+    // rebuild the state of party victim from the key packages of helpers
+    #[allow(non_snake_case)]
+    fn run_repair(
+        key_packages: &[v1::KeyPackage],
+        victim: usize,
+        helpers: &[usize],
+        ctx: &[u8],
+    ) -> Result<v1::PartyState, DkgError> {
+        let mut rng = OsRng::default();
+        let parts: Vec<RepairShare> = helpers
+            .iter()
+            .flat_map(|i| {
+                v1::Party::from_key_package(&key_packages[*i])
+                    .repair_shares(victim, helpers, &mut rng)
+            })
+            .collect();
+        let sums: Vec<RepairSum> = helpers
+            .iter()
+            .map(|i| {
+                let received: Vec<RepairShare> = parts
+                    .iter()
+                    .filter(|p| p.recipient == *i)
+                    .cloned()
+                    .collect();
+                dkg::repair_sum(*i, helpers, victim, &received).unwrap()
+            })
+            .collect();
+        let A = &key_packages[0].poly_commitments;
+        v1::PartyState::repair(victim, helpers, &sums, A, ctx)
+    }
+
+    #[test]
+    fn repair() {
+        let ctx = "chain 1".as_bytes();
+        let mut key_packages = run_dkg(4, 3, ctx);
+
+        // party 1 lost its state, and parties 0, 2 and 3 help it
+        let victim = 1;
+        let state = run_repair(&key_packages, victim, &[0, 2, 3], ctx).unwrap();
+        assert_eq!(state.private_key, key_packages[victim].state.private_key);
+
+        // too few helpers can't recover the share
+        assert_eq!(
+            run_repair(&key_packages, victim, &[0, 2], ctx).err(),
+            Some(DkgError::BadShares(vec![0, 2]))
+        );
+        let poly_commitments = &key_packages[0].poly_commitments;
+        assert_eq!(
+            v1::PartyState::repair(victim, &[], &[], poly_commitments, ctx).err(),
+            Some(DkgError::NoHelpers)
+        );
+        assert_eq!(
+            v1::PartyState::repair(victim, &[0, 2, 3], &[], &[], ctx).err(),
+            Some(DkgError::NoPolyCommitments)
+        );
+
+        key_packages[victim].state = state;
+        assert!(dkg_sign(&key_packages, ctx));
+    }
+
+    #[test]
+    fn trusted_dealer() {
+        let mut rng = OsRng::default();
+        let ctx = "chain 1".as_bytes();
+        let (n, t) = (4, 3);
+
+        // split an existing key
+        let secret = Scalar::random(&mut rng);
+        let (commitment, shares, key_packages) = run_trusted_deal(secret, n, t, ctx);
+        assert_eq!(key_packages[0].group_key, secret * G);
+        assert!(dkg_sign(&key_packages, ctx));
+
+        // a share for another key id, or one that doesn't match the commitment
+        assert_eq!(
+            v1::KeyPackage::from_dealer(0, n, &commitment, &shares[1], ctx).err(),
+            Some(DkgError::WrongKeyId(1))
+        );
+        let mut bad_share = shares[0].clone();
+        bad_share.share += Scalar::one();
+        assert_eq!(
+            v1::KeyPackage::from_dealer(0, n, &commitment, &bad_share, ctx).err(),
+            Some(DkgError::BadShares(vec![0]))
+        );
+
+        // or have the dealer generate the key, and give a Signer all the shares
+        let (commitment, shares) = dkg::trusted_deal(None, n, t, ctx, &mut rng);
+        let state = v1::SignerState::from_dealer(n, &commitment, &shares, ctx).unwrap();
+        let signer = v1::Signer::load(&state);
+        assert_eq!(signer.group_key, commitment.commitment.A[0]);
+        assert_eq!(signer.parties.len(), n);
+    }
+
+    #[cfg(feature = "reconstruct")]
+    #[test]
+    fn reconstruct() {
+        let mut rng = OsRng::default();
+        let ctx = "chain 1".as_bytes();
+        let (n, t) = (4, 3);
+        let secret = Scalar::random(&mut rng);
+        let (_, _, key_packages) = run_trusted_deal(secret, n, t, ctx);
+        let public_keys = &key_packages[0].public_keys;
+        let group_key = &key_packages[0].group_key;
+        let states = |ids: &[usize]| -> Vec<(usize, v1::PartyState)> {
+            ids.iter()
+                .map(|i| (*i, key_packages[*i].state.clone()))
+                .collect()
+        };
+
+        assert_eq!(
+            v1::PartyState::reconstruct(&states(&[3, 0, 2]), public_keys, group_key),
+            Ok(secret)
+        );
+        assert_eq!(
+            v1::PartyState::reconstruct(&states(&[0, 2]), public_keys, group_key),
+            Err(ReconstructError::BadGroupKey)
+        );
+        assert_eq!(
+            v1::PartyState::reconstruct(&states(&[0, 2, 2]), public_keys, group_key),
+            Err(ReconstructError::DuplicateShare(2))
+        );
+
+        let mut bad_states = states(&[0, 1, 2]);
+        bad_states[1].1.private_key += Scalar::one();
+        assert_eq!(
+            v1::PartyState::reconstruct(&bad_states, public_keys, group_key),
+            Err(ReconstructError::BadShares(vec![1]))
+        );
+        bad_states[1].0 = n;
+        assert_eq!(
+            v1::PartyState::reconstruct(&bad_states, public_keys, group_key),
+            Err(ReconstructError::UnknownShare(n))
+        );
+    }
+
+    // sign with the last t parties of a group made by a Dkg, Refresh or Reshare
+    fn dkg_sign(key_packages: &[v1::KeyPackage], ctx: &[u8]) -> bool {
+        let mut rng = OsRng::default();
+        let msg = "It was many and many a year ago".as_bytes();
+        let n = key_packages.len();
+        let t = key_packages[0].threshold;
+
+        let mut parties: Vec<v1::Party> = key_packages
             .iter()
-            .map(|ids| v1::Signer::new(ids, N, T, &mut rng))
+            .map(v1::Party::from_key_package)
+            .collect();
+        for (i, party) in parties.iter().enumerate() {
+            assert_eq!(party.group_key, key_packages[0].group_key);
+            assert_eq!(party.public_key, key_packages[0].public_keys[i]);
+        }
+
+        let mut sig_agg = v1::SignatureAggregator::from_key_package(&key_packages[0]);
+
+        let nonces: Vec<(usize, PublicNonce)> = (n - t..n)
+            .map(|i| (i, parties[i].gen_nonce(&mut rng)))
+            .collect();
+        let package = SigningPackage::new(msg, &nonces, t).unwrap();
+        let sig_shares: Vec<SignatureShare> = package
+            .signers()
+            .iter()
+            .map(|i| SignatureShare {
+                id: *i,
+                z_i: parties[*i].sign(&package).unwrap(),
+                key_ids: vec![*i],
+            })
+            .collect();
+        let sig = sig_agg.sign(&package, &sig_shares).unwrap();
+        sig.verify(&sig_agg.key, msg, ctx)
+    }
+
+    #[test]
+    fn party_checks_package() {
+        let mut rng = OsRng::default();
+        let msg = "It was many and many a year ago".as_bytes();
+        let mut parties: Vec<v1::Party> =
+            (0..3).map(|i| v1::Party::new(i, 3, 2, &mut rng)).collect();
+        let nonces: Vec<(usize, PublicNonce)> = parties
+            .iter_mut()
+            .map(|p| (p.id, p.gen_nonce(&mut rng)))
+            .collect();
+
+        // the package threshold is whatever the coordinator said, but the party knows its own
+        let too_few = SigningPackage::new(msg, &nonces[..1], 1).unwrap();
+        assert_eq!(
+            parties[0].sign(&too_few),
+            Err(SignerError::BadSigningPackage(
+                SigningPackageError::BelowThreshold(2, 1)
+            ))
+        );
+
+        let other_keys =
+            SigningPackage::with_key_ids(msg, &nonces[..2], &[(0, vec![2]), (1, vec![1])], 2)
+                .unwrap();
+        assert_eq!(
+            parties[0].sign(&other_keys),
+            Err(SignerError::BadKeyIds(0, vec![2]))
+        );
+        let extra_keys =
+            SigningPackage::with_key_ids(msg, &nonces[..2], &[(0, vec![0, 2]), (1, vec![1])], 2)
+                .unwrap();
+        assert_eq!(
+            parties[0].sign(&extra_keys),
+            Err(SignerError::BadKeyIds(0, vec![0, 2]))
+        );
+
+        // none of that used up the nonce
+        let package = SigningPackage::new(msg, &nonces, 2).unwrap();
+        assert!(parties[0].sign(&package).is_ok());
+    }
+
+    #[test]
+    fn signing_package_errors() {
+        let mut rng = OsRng::default();
+        let msg = "It was many and many a year ago".as_bytes();
+        let mut parties: Vec<v1::Party> =
+            (0..3).map(|i| v1::Party::new(i, 3, 2, &mut rng)).collect();
+        let nonces: Vec<(usize, PublicNonce)> = parties
+            .iter_mut()
+            .map(|p| (p.id, p.gen_nonce(&mut rng)))
+            .collect();
+
+        // the package is in signer order however the nonces were listed
+        let reversed: Vec<(usize, PublicNonce)> = nonces.iter().cloned().rev().collect();
+        let package = SigningPackage::new(msg, &reversed, 3).unwrap();
+        assert_eq!(package, SigningPackage::new(msg, &nonces, 3).unwrap());
+        assert_eq!(package.signers(), vec![0, 1, 2]);
+
+        let mut duplicate = nonces.clone();
+        duplicate[2].0 = 0;
+        assert_eq!(
+            SigningPackage::new(msg, &duplicate, 2),
+            Err(SigningPackageError::DuplicateSigner(0))
+        );
+        assert_eq!(
+            SigningPackage::with_key_ids(msg, &nonces, &[(0, vec![0]), (1, vec![1, 2])], 2),
+            Err(SigningPackageError::MismatchedKeyIds(vec![2]))
+        );
+        assert_eq!(
+            SigningPackage::with_key_ids(
+                msg,
+                &nonces,
+                &[(0, vec![0]), (1, vec![1]), (2, vec![1])],
+                2
+            ),
+            Err(SigningPackageError::DuplicateKey(1))
+        );
+        assert_eq!(
+            SigningPackage::new(msg, &nonces[..2], 3),
+            Err(SigningPackageError::BelowThreshold(3, 2))
+        );
+
+        let mut identity = nonces.clone();
+        identity[1].1.E = Point::zero();
+        identity[2].1.D = Point::zero();
+        assert_eq!(
+            SigningPackage::new(msg, &identity, 3),
+            Err(SigningPackageError::IdentityNonces(vec![1, 2]))
+        );
+    }
+
+    #[allow(non_snake_case)]
+    #[test]
+    fn compute_secret_errors() {
+        let mut rng = OsRng::default();
+        let N: usize = 3;
+        let T: usize = 2;
+        let mut parties: Vec<v1::Party> =
+            (0..N).map(|i| v1::Party::new(i, N, T, &mut rng)).collect();
+        let A: Vec<PolyCommitment> = parties
+            .iter()
+            .map(|p| p.get_poly_commitment(&mut rng))
             .collect();
+        let shares: HashMap<usize, _> =
+            parties.iter().map(|p| (p.id, p.get_shares()[&0])).collect();
+
+        assert_eq!(
+            parties[0].compute_secret(shares.clone(), &A[..2]),
+            Err(DkgError::BadPolyCommitmentLen(3, 2))
+        );
+
+        let mut bad_A = A.clone();
+        bad_A[1].A[0] += G;
+        assert_eq!(
+            parties[0].compute_secret(shares.clone(), &bad_A),
+            Err(DkgError::BadPolyCommitments(vec![1]))
+        );
+
+        // an empty commitment, or one for a polynomial of the wrong degree
+        let mut bad_A = A.clone();
+        bad_A[1].A.clear();
+        assert!(!bad_A[1].verify(&[]));
+        assert_eq!(
+            parties[0].compute_secret(shares.clone(), &bad_A),
+            Err(DkgError::BadPolyCommitments(vec![1]))
+        );
+        let mut bad_A = A.clone();
+        bad_A[2].A.push(G);
+        assert_eq!(
+            parties[0].compute_secret(shares.clone(), &bad_A),
+            Err(DkgError::BadPolyCommitments(vec![2]))
+        );
+        assert_eq!(
+            v1::SignatureAggregator::new(N, T, bad_A).err(),
+            Some(AggregatorError::BadPolyCommitments(vec![2]))
+        );
+
+        let mut missing_shares = shares.clone();
+        missing_shares.remove(&2);
+        assert_eq!(
+            parties[0].compute_secret(missing_shares, &A),
+            Err(DkgError::MissingShares(vec![2]))
+        );
+
+        let mut bad_shares = shares.clone();
+        bad_shares.insert(1, shares[&1] + Scalar::one());
+        assert_eq!(
+            parties[0].compute_secret(bad_shares, &A),
+            Err(DkgError::BadShares(vec![1]))
+        );
+
+        assert!(parties[0].compute_secret(shares, &A).is_ok());
+    }
+
+    #[allow(non_snake_case)]
+    #[test]
+    fn aggregator_errors() {
+        let mut rng = OsRng::default();
+        let msg = "It was many and many a year ago".as_bytes();
+        let N: usize = 3;
+        let T: usize = 2;
+        let mut signers = vec![v1::Signer::new(&[0, 1, 2], N, T, &mut rng)];
 
         let A = dkg(&mut signers, &mut rng);
 
-        // signers [0,1,3] who have T keys
-        {
-            let mut signers = [signers[0].clone(), signers[1].clone(), signers[3].clone()].to_vec();
-            let mut sig_agg = v1::SignatureAggregator::new(N, T, A.clone());
+        assert!(matches!(
+            v1::SignatureAggregator::new(N, T, A[..2].to_vec()),
+            Err(AggregatorError::BadPolyCommitmentLen(3, 2))
+        ));
+
+        let mut bad_A = A.clone();
+        bad_A[2].A[0] += G;
+        assert!(matches!(
+            v1::SignatureAggregator::new(N, T, bad_A),
+            Err(AggregatorError::BadPolyCommitments(ids)) if ids == vec![2]
+        ));
+
+        let mut sig_agg = v1::SignatureAggregator::new(N, T, A).unwrap();
+        let (package, mut sig_shares) = sign(msg, &mut signers, T, &mut rng);
+
+        assert!(matches!(
+            sig_agg.sign(&package, &sig_shares[..2]),
+            Err(AggregatorError::MissingPartySigs(ids)) if ids == vec![2]
+        ));
+
+        // shares from parties outside the package, or sent twice, are bad
+        let mut extra_shares = sig_shares.clone();
+        extra_shares.push(sig_shares[0].clone());
+        assert!(matches!(
+            sig_agg.sign(&package, &extra_shares),
+            Err(AggregatorError::BadPartySigs(ids)) if ids == vec![0]
+        ));
+        let small_package =
+            SigningPackage::new(msg, &[(0, package.nonce(0).unwrap().clone())], 1).unwrap();
+        assert!(matches!(
+            sig_agg.sign(&small_package, &sig_shares),
+            Err(AggregatorError::BadPartySigs(ids)) if ids == vec![1, 2]
+        ));
+
+        sig_shares[1].z_i += Scalar::one();
+        assert!(matches!(
+            sig_agg.sign(&package, &sig_shares),
+            Err(AggregatorError::BadPartySigs(ids)) if ids == vec![1]
+        ));
+
+        sig_shares[2].z_i += Scalar::one();
+        assert!(matches!(
+            sig_agg.sign(&package, &sig_shares),
+            Err(AggregatorError::BadPartySigs(ids)) if ids == vec![1, 2]
+        ));
+    }
+
+    #[allow(non_snake_case)]
+    #[test]
+    fn dkg_complaints() {
+        let mut rng = OsRng::default();
+        let msg = "It was many and many a year ago".as_bytes();
+        let N: usize = 4;
+        let T: usize = 2;
+        let mut parties: Vec<v1::Party> =
+            (0..N).map(|i| v1::Party::new(i, N, T, &mut rng)).collect();
+        let A: Vec<PolyCommitment> = parties
+            .iter()
+            .map(|p| p.get_poly_commitment(&mut rng))
+            .collect();
+        let broadcast_shares: Vec<HashMap<usize, Scalar>> =
+            parties.iter().map(|p| p.get_shares()).collect();
+        let mut party_shares: Vec<HashMap<usize, Scalar>> = (0..N)
+            .map(|i| (0..N).map(|j| (j, broadcast_shares[j][&i])).collect())
+            .collect();
+
+        // party 1 sends a bad share to party 0
+        *party_shares[0].get_mut(&1).unwrap() += Scalar::one();
+        assert_eq!(
+            parties[0].compute_secret(party_shares[0].clone(), &A),
+            Err(DkgError::BadShares(vec![1]))
+        );
+
+        let complaints: Vec<Complaint> = parties
+            .iter()
+            .flat_map(|p| p.get_complaints(&party_shares[p.id], &A))
+            .collect();
+        assert_eq!(
+            complaints,
+            vec![Complaint {
+                accuser: 0,
+                accused: 1,
+                key_id: 0
+            }]
+        );
+
+        // an honest justification means the complaint was false, so the accuser is disqualified
+        let justifications: Vec<Justification> = parties
+            .iter()
+            .flat_map(|p| p.justify(&complaints))
+            .collect();
+        assert_eq!(justifications.len(), 1);
+        let qualified = v1::resolve_complaints(&A, &complaints, &justifications, &[]);
+        assert_eq!(qualified, [1, 2, 3].iter().cloned().collect());
+
+        // a bad or missing justification disqualifies the accused
+        let mut bad_justifications = justifications.clone();
+        bad_justifications[0].share += Scalar::one();
+        let qualified = v1::resolve_complaints(&A, &complaints, &bad_justifications, &[]);
+        assert_eq!(qualified, [0, 2, 3].iter().cloned().collect());
+        assert_eq!(qualified, v1::resolve_complaints(&A, &complaints, &[], &[]));
+
+        // complaints about other parties' shares are never answered, and disqualify the accuser
+        let forged: Vec<Complaint> = [2, 3]
+            .iter()
+            .map(|key_id| Complaint {
+                accuser: 0,
+                accused: 1,
+                key_id: *key_id,
+            })
+            .collect();
+        assert!(parties[1].justify(&forged).is_empty());
+        assert_eq!(
+            v1::resolve_complaints(&A, &forged, &[], &[]),
+            [1, 2, 3].iter().cloned().collect()
+        );
+
+        for party in parties.iter_mut() {
+            party
+                .compute_qualified_secret(&qualified, party_shares[party.id].clone(), &A)
+                .unwrap();
+        }
+        let mut sig_agg = v1::SignatureAggregator::new_qualified(N, T, A, &qualified, &[]).unwrap();
+
+        let signers = [0, 2, 3];
+        let nonces: Vec<(usize, PublicNonce)> = signers
+            .iter()
+            .map(|i| (*i, parties[*i].gen_nonce(&mut rng)))
+            .collect();
+        let package = SigningPackage::new(msg, &nonces, T).unwrap();
+        let sig_shares: Vec<SignatureShare> = signers
+            .iter()
+            .map(|i| SignatureShare {
+                id: *i,
+                z_i: parties[*i].sign(&package).unwrap(),
+                key_ids: vec![*i],
+            })
+            .collect();
+        let sig = sig_agg.sign(&package, &sig_shares).unwrap();
+
+        assert!(sig.verify(&sig_agg.key, msg, &sig_agg.context));
+    }
+
+    #[allow(non_snake_case)]
+    #[test]
+    fn encrypted_shares() {
+        let mut rng = OsRng::default();
+        let N: usize = 3;
+        let T: usize = 2;
+        let mut parties: Vec<v1::Party> =
+            (0..N).map(|i| v1::Party::new(i, N, T, &mut rng)).collect();
+        let A: Vec<PolyCommitment> = parties
+            .iter()
+            .map(|p| p.get_poly_commitment(&mut rng))
+            .collect();
+        let network_private_keys: Vec<Scalar> = (0..N).map(|_| Scalar::random(&mut rng)).collect();
+        let network_public_keys: HashMap<usize, Point> = network_private_keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| (i, key * G))
+            .collect();
+
+        let mut missing_public_keys = network_public_keys.clone();
+        missing_public_keys.remove(&2);
+        assert_eq!(
+            parties[0].get_encrypted_shares(
+                &network_private_keys[0],
+                &missing_public_keys,
+                &mut rng
+            ),
+            Err(DkgError::MissingPublicKeys(vec![2]))
+        );
+
+        let encrypted_shares: Vec<EncryptedShare> = parties
+            .iter()
+            .flat_map(|p| {
+                p.get_encrypted_shares(&network_private_keys[p.id], &network_public_keys, &mut rng)
+                    .unwrap()
+            })
+            .collect();
 
-            let (nonces, sig_shares) = sign(&msg, &mut signers, &mut rng);
-            let sig = sig_agg.sign(&msg, &nonces, &sig_shares);
+        // only the recipient can decrypt its shares
+        let mut wrong_private_keys = network_private_keys.clone();
+        wrong_private_keys.swap(0, 1);
+        assert_eq!(
+            parties[0].decrypt_shares(
+                &wrong_private_keys[0],
+                &network_public_keys,
+                &encrypted_shares
+            ),
+            Err(DkgError::BadEncryptedShares(vec![0, 1, 2]))
+        );
+
+        // a share can't be moved to a different recipient
+        let mut moved_shares = encrypted_shares.clone();
+        for es in moved_shares.iter_mut().filter(|es| es.sender == 2) {
+            es.key_id = (es.key_id + 1) % N;
+        }
+        assert_eq!(
+            parties[0].decrypt_shares(
+                &network_private_keys[0],
+                &network_public_keys,
+                &moved_shares
+            ),
+            Err(DkgError::BadEncryptedShares(vec![2]))
+        );
 
-            println!("Signature (R,z) = \n({},{})", sig.R, sig.z);
-            assert!(sig.verify(&sig_agg.key, &msg));
+        for i in 0..N {
+            let shares = parties[i]
+                .decrypt_shares(
+                    &network_private_keys[i],
+                    &network_public_keys,
+                    &encrypted_shares,
+                )
+                .unwrap();
+            assert_eq!(shares.len(), N);
+            assert!(parties[i].compute_secret(shares, &A).is_ok());
         }
     }
 }
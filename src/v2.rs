@@ -1,131 +1,158 @@
-use num_traits::{One, Zero};
+use core::iter::zip;
+use core::mem;
+use num_traits::Zero;
 use polynomial::Polynomial;
 use rand_core::{CryptoRng, RngCore};
 use secp256k1_math::{
     point::{Point, G},
     scalar::Scalar,
 };
-use sha3::{Digest, Sha3_256};
+use serde::{Deserialize, Serialize};
 
+use crate::bip340::{self, SchnorrProof};
+use crate::common::{
+    BatchSignatureShare, Nonce, PolyCommitment, PublicNonce, Signature, SignatureShare,
+    SigningPackage,
+};
+use crate::compute;
+use crate::dkg::{
+    self, Complaint, DkgCommitment, DkgRounds, DkgScheme, DkgShare, DkgState, EncryptedShare,
+    Justification, PedersenCommitment, RecoveryShare, RefreshCommitment, RepairShare, RepairSum,
+    Rounds,
+};
+#[cfg(feature = "reconstruct")]
+use crate::errors::ReconstructError;
+use crate::errors::{AggregatorError, DkgError, NoncePoolError, SignerError, SigningPackageError};
+use crate::nonce_pool::{NonceId, NoncePool, NonceStore};
+#[cfg(feature = "reconstruct")]
+use crate::reconstruct;
 use crate::schnorr::ID;
-use crate::util::hash_to_scalar;
+use crate::taproot;
+use crate::traits;
 use crate::vss::VSS;
 
 use hashbrown::{HashMap, HashSet};
+use std::collections::BTreeMap;
 
 pub type PubKeyMap = HashMap<usize, Point>;
 pub type PrivKeyMap = HashMap<usize, Scalar>;
 pub type SelectedSigners = HashMap<usize, HashSet<usize>>;
+pub type NonceIndices = HashMap<usize, usize>; // key is party_id, value is an index into its row of B
 
-#[allow(non_snake_case)]
-pub struct PolyCommitment {
-    pub party_id: ID,
-    pub A: Vec<Point>,
+// Sent by the aggregator to a party which is running out of unused nonces.  The party answers
+// with a fresh row of B from gen_nonces or gen_pool_nonces, which everyone passes to
+// set_party_nonces
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct NonceRequest {
+    pub party_id: usize,
+    pub num_nonces: u32,
 }
 
-impl PolyCommitment {
-    pub fn verify(&self) -> bool {
-        self.party_id.verify(&self.A[0])
-    }
+// the signing party ids in sorted order, which is the order their nonces are bound in
+fn get_party_ids(signers: &SelectedSigners) -> Vec<usize> {
+    let mut party_ids = Vec::from_iter(signers.keys().cloned());
+    party_ids.sort();
+    party_ids
 }
 
-#[derive(Clone)]
-pub struct Nonce {
-    d: Scalar,
-    e: Scalar,
+fn find_share(
+    shares: &HashMap<usize, Vec<(usize, Scalar)>>,
+    key_id: &usize,
+    sender: &usize,
+) -> Option<Scalar> {
+    shares
+        .get(key_id)?
+        .iter()
+        .find(|(i, _)| i == sender)
+        .map(|(_, s)| *s)
 }
 
-#[derive(Clone)]
+// Resolve the broadcast complaints and justifications into the set of qualified parties.
+// party_keys is the key ids of each party.  Parties with a bad proof of knowledge are never
+// qualified.
 #[allow(non_snake_case)]
-pub struct PublicNonce {
-    pub D: Point,
-    pub E: Point,
+pub fn resolve_complaints(
+    A: &[PolyCommitment],
+    complaints: &[Complaint],
+    justifications: &[Justification],
+    party_keys: &[HashSet<usize>],
+    ctx: &[u8],
+) -> HashSet<usize> {
+    let check_share = |j: &Justification| {
+        j.accused < A.len()
+            && compute::check_share(&id_to_scalar(&j.key_id), &j.share, &A[j.accused].A)
+    };
+    let mut qualified = dkg::resolve_complaints(
+        A.len(),
+        complaints,
+        justifications,
+        |c| owns_key(party_keys, c),
+        check_share,
+    );
+    qualified.retain(|i| A[*i].verify(ctx));
+    qualified
 }
 
-impl PublicNonce {
-    pub fn from(n: &Nonce) -> Self {
-        Self {
-            D: &n.d * G,
-            E: &n.e * G,
-        }
-    }
-}
-
-// The SA should get that as usual
-pub struct SignatureShare {
-    pub party_id: usize,
-    pub z_i: Scalar,
+// whether the accuser of a complaint owns the key_id it is about
+fn owns_key(party_keys: &[HashSet<usize>], complaint: &Complaint) -> bool {
+    party_keys
+        .get(complaint.accuser)
+        .is_some_and(|key_ids| key_ids.contains(&complaint.key_id))
 }
 
-#[allow(non_snake_case)]
-fn compute_binding(party_id: &Scalar, B: &Vec<PublicNonce>, msg: &[u8]) -> Scalar {
-    let mut hasher = Sha3_256::new();
-
-    hasher.update(party_id.as_bytes());
-    for b in B {
-        hasher.update(b.D.compress().as_bytes());
-        hasher.update(b.E.compress().as_bytes());
-    }
-    hasher.update(msg);
-
-    hash_to_scalar(&mut hasher)
+fn id_to_scalar(id: &usize) -> Scalar {
+    Scalar::from((id + 1) as u32)
 }
 
-#[allow(non_snake_case)]
-fn compute_challenge(publicKey: &Point, R: &Point, msg: &[u8]) -> Scalar {
-    let mut hasher = Sha3_256::new();
-
-    hasher.update(publicKey.compress().as_bytes());
-    hasher.update(R.compress().as_bytes());
-    hasher.update(msg);
-
-    hash_to_scalar(&mut hasher)
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PartyState {
+    pub key_ids: HashSet<usize>,
+    pub private_keys: PrivKeyMap,
+    pub polynomial: Polynomial<Scalar>,
+    pub context: Vec<u8>,
 }
 
-fn lambda(i: &usize, signers: &SelectedSigners) -> Scalar {
-    let mut lambda = Scalar::one();
-    let i_scalar = id_to_scalar(i);
-    for (_, h) in signers {
-        for j in h {
-            if i != j {
-                let j_scalar = id_to_scalar(j);
-                lambda *= j_scalar / (j_scalar - i_scalar);
-            }
+impl PartyState {
+    // Rebuild the state of the party which holds key_ids after it was lost, from the
+    // RepairSums of the helpers, see Party::repair_shares.  The party's Dkg polynomial can't be
+    // recovered, so a repaired party can sign but has nothing to deal in another Dkg
+    #[allow(non_snake_case)]
+    pub fn repair(
+        key_ids: HashSet<usize>,
+        helpers: &[usize],
+        sums: &[RepairSum],
+        A: &[PolyCommitment],
+        context: &[u8],
+    ) -> Result<Self, DkgError> {
+        dkg::check_repair(helpers, A)?;
+        let mut private_keys = PrivKeyMap::new();
+        for key_id in &key_ids {
+            private_keys.insert(*key_id, dkg::repair_key(*key_id, helpers, sums, A)?);
         }
+
+        Ok(Self {
+            key_ids,
+            private_keys,
+            polynomial: Polynomial::new(vec![Scalar::zero(); A[0].A.len()]),
+            context: context.to_vec(),
+        })
     }
-    lambda
-}
 
-// Is this the best way to return these values?
-// TODO: this fn needs a better name
-#[allow(non_snake_case)]
-fn compute_intermediate_values(
-    signers: &SelectedSigners, // only the keys are needed
-    B: &Vec<Vec<PublicNonce>>,
-    index: usize,
-    msg: &[u8],
-) -> (Vec<PublicNonce>, HashMap<usize, Point>, Point) {
-    let mut signer_vec = Vec::from_iter(signers.keys());
-    signer_vec.sort();
-    let B = signer_vec
-        .iter()
-        .map(|&party_id| B[*party_id][index].clone())
-        .collect();
-    let rho: Vec<Scalar> = signer_vec
-        .iter()
-        .map(|&party_id| compute_binding(&id_to_scalar(&party_id), &B, &msg))
-        .collect();
+    // Reconstruct the group secret from the states of parties holding at least threshold key
+    // ids.  This is for offline disaster recovery only, since it puts the whole key in one place
+    #[cfg(feature = "reconstruct")]
+    pub fn reconstruct(
+        states: &[PartyState],
+        public_keys: &PubKeyMap,
+        group_key: &Point,
+    ) -> Result<Scalar, ReconstructError> {
+        let shares: Vec<(usize, Scalar)> = states
+            .iter()
+            .flat_map(|state| state.private_keys.iter().map(|(k, s)| (*k, *s)))
+            .collect();
 
-    let mut Ris = HashMap::new();
-    for i in 0..signer_vec.len() {
-        Ris.insert(*signer_vec[i], &B[i].D + &rho[i] * &B[i].E);
+        reconstruct::secret(&shares, public_keys, group_key)
     }
-    let R = Ris.values().fold(Point::zero(), |R, R_i| R + R_i);
-    (B, Ris, R)
-}
-
-fn id_to_scalar(id: &usize) -> Scalar {
-    Scalar::from((id + 1) as u32)
 }
 
 #[derive(Clone)]
@@ -136,11 +163,13 @@ pub struct Party {
     pub public_keys: PubKeyMap, // key is key_id
     num_keys: usize,
     num_parties: usize,
+    threshold: usize,
     f: Polynomial<Scalar>, // one poly per party to simulate the sum of all their polys
     private_keys: PrivKeyMap, // key is key_id
     group_key: Point,
     nonces: Vec<Nonce>,
     B: Vec<Vec<PublicNonce>>, // received from other parties
+    context: Vec<u8>,
 }
 
 impl Party {
@@ -158,29 +187,101 @@ impl Party {
             key_ids: key_ids,
             num_keys: num_keys,
             num_parties: num_parties,
+            threshold,
             f: VSS::random_poly(threshold - 1, rng),
             private_keys: PrivKeyMap::new(),
             public_keys: PubKeyMap::new(),
             group_key: Point::zero(),
             nonces: Vec::new(),
             B: Vec::new(),
+            context: Vec::new(),
+        }
+    }
+
+    // the nonces are not saved, so a loaded party must generate new ones
+    pub fn load(
+        party_id: usize,
+        num_keys: usize,
+        num_parties: usize,
+        group_key: &Point,
+        state: &PartyState,
+    ) -> Self {
+        Self {
+            party_id,
+            key_ids: state.key_ids.clone(),
+            num_keys,
+            num_parties,
+            threshold: state.polynomial.data().len(),
+            f: state.polynomial.clone(),
+            private_keys: state.private_keys.clone(),
+            public_keys: state
+                .private_keys
+                .iter()
+                .map(|(key_id, key)| (*key_id, key * G))
+                .collect(),
+            group_key: *group_key,
+            nonces: Vec::new(),
+            B: Vec::new(),
+            context: state.context.clone(),
+        }
+    }
+
+    pub fn save(&self) -> PartyState {
+        PartyState {
+            key_ids: self.key_ids.clone(),
+            private_keys: self.private_keys.clone(),
+            polynomial: self.f.clone(),
+            context: self.context.clone(),
         }
     }
 
+    pub fn from_key_package(key_package: &KeyPackage) -> Self {
+        Self::load(
+            key_package.party_id,
+            key_package.num_keys,
+            key_package.num_parties,
+            &key_package.group_key,
+            &key_package.state,
+        )
+    }
+
+    // the application context (e.g. a chain id or group id) which is bound into every hash.
+    // All parties and the aggregator must use the same one
+    pub fn set_context(&mut self, context: &[u8]) {
+        self.context = context.to_vec();
+    }
+
     pub fn gen_nonces<RNG: RngCore + CryptoRng>(
         &mut self,
         num_nonces: u32,
         rng: &mut RNG,
     ) -> Vec<PublicNonce> {
+        let secret = self.nonce_secret();
         self.nonces = (0..num_nonces)
-            .map(|_| Nonce {
-                d: Scalar::random(rng),
-                e: Scalar::random(rng),
-            })
+            .map(|_| Nonce::random(&secret, &self.context, rng))
             .collect();
         self.nonces.iter().map(|n| PublicNonce::from(n)).collect()
     }
 
+    // Like gen_nonces, but the secret nonces go into a pool instead of this party, so they
+    // can only be used once even if the party is restored from an old snapshot.  The public
+    // nonces are this party's row of B, and sign_from_pool takes the batch id
+    pub fn gen_pool_nonces<S: NonceStore, RNG: RngCore + CryptoRng>(
+        &self,
+        num_nonces: u32,
+        pool: &mut NoncePool<S>,
+        rng: &mut RNG,
+    ) -> Result<(u64, Vec<PublicNonce>), NoncePoolError> {
+        pool.generate(num_nonces, &self.nonce_secret(), &self.context, rng)
+    }
+
+    // every key share goes into the hedge, not just one of them
+    fn nonce_secret(&self) -> Scalar {
+        self.private_keys
+            .values()
+            .fold(Scalar::zero(), |secret, key| secret + key)
+    }
+
     #[allow(non_snake_case)]
     pub fn set_group_nonces(&mut self, B: Vec<Vec<PublicNonce>>) {
         self.B = B;
@@ -195,13 +296,85 @@ impl Party {
     #[allow(non_snake_case)]
     pub fn get_poly_commitment<RNG: RngCore + CryptoRng>(&self, rng: &mut RNG) -> PolyCommitment {
         PolyCommitment {
-            party_id: ID::new(&id_to_scalar(&self.party_id), &self.f.data()[0], rng),
+            id: ID::new(
+                &id_to_scalar(&self.party_id),
+                &self.f.data()[0],
+                &self.context,
+                rng,
+            ),
             A: (0..self.f.data().len())
                 .map(|i| &self.f.data()[i] * G)
                 .collect(),
         }
     }
 
+    // Deal this party's part of the group secret to a new committee with num_keys key_ids and
+    // the given threshold, see Reshare.  key_ids are all the key_ids held by the old parties
+    // which deal, and there must be at least the old threshold of them.  They must include all
+    // of this party's key_ids
+    pub fn reshare<RNG: RngCore + CryptoRng>(
+        &self,
+        key_ids: &[usize],
+        num_keys: usize,
+        threshold: usize,
+        rng: &mut RNG,
+    ) -> Result<(DkgCommitment, Vec<DkgShare>), DkgError> {
+        let mut secret = Scalar::zero();
+        for key_id in &self.key_ids {
+            match self.private_keys.get(key_id) {
+                Some(private_key) if key_ids.contains(key_id) => {
+                    secret += compute::lambda(key_id, key_ids) * private_key
+                }
+                _ => return Err(DkgError::WrongKeyId(*key_id)),
+            }
+        }
+        Ok(dkg::deal(
+            self.party_id,
+            &secret,
+            num_keys,
+            threshold,
+            &self.context,
+            rng,
+        ))
+    }
+
+    // Round one of repairing the lost share for key_id, or of enrolling a new key_id, see
+    // KeyPackage::enroll.  helpers are the party ids which help, with the key_ids each of them
+    // holds, in the form SigningPackage::with_key_ids takes them, and they must hold at least
+    // the threshold of key_ids.  Each helper gets one part, and turns the parts it gets into a
+    // RepairSum with dkg::repair_sum.  This party must be one of the helpers, with key_ids it
+    // holds
+    pub fn repair_shares<RNG: RngCore + CryptoRng>(
+        &self,
+        key_id: usize,
+        helpers: &[(usize, Vec<usize>)],
+        rng: &mut RNG,
+    ) -> Result<Vec<RepairShare>, DkgError> {
+        let own_key_ids = match helpers.iter().find(|(i, _)| *i == self.party_id) {
+            Some((_, key_ids)) => key_ids,
+            None => return Err(DkgError::UnknownParty(self.party_id)),
+        };
+        let x = id_to_scalar(&key_id);
+        let key_ids: Vec<usize> = helpers.iter().flat_map(|(_, k)| k.clone()).collect();
+        let mut contribution = Scalar::zero();
+        for i in own_key_ids {
+            match self.private_keys.get(i) {
+                Some(private_key) => {
+                    contribution += compute::lambda_at(i, &key_ids, &x) * private_key
+                }
+                None => return Err(DkgError::WrongKeyId(*i)),
+            }
+        }
+        let helper_ids: Vec<usize> = helpers.iter().map(|(i, _)| *i).collect();
+        Ok(dkg::split_repair(
+            self.party_id,
+            key_id,
+            &contribution,
+            &helper_ids,
+            rng,
+        ))
+    }
+
     pub fn get_shares(&self) -> Vec<(usize, Scalar)> {
         let mut shares = Vec::new();
         for i in 0..self.num_keys as usize {
@@ -210,6 +383,82 @@ impl Party {
         shares
     }
 
+    // encrypt each share to the network public key of its key_id's owner,
+    // so all of them can be broadcast
+    pub fn get_encrypted_shares<RNG: RngCore + CryptoRng>(
+        &self,
+        network_private_key: &Scalar,
+        network_public_keys: &PubKeyMap, // key is key_id
+        rng: &mut RNG,
+    ) -> Result<Vec<EncryptedShare>, DkgError> {
+        let missing_keys: Vec<usize> = (0..self.num_keys)
+            .filter(|i| !network_public_keys.contains_key(i))
+            .collect();
+        if !missing_keys.is_empty() {
+            return Err(DkgError::MissingPublicKeys(missing_keys));
+        }
+
+        let mut encrypted_shares = Vec::new();
+        for (key_id, share) in self.get_shares() {
+            let encrypted_share = EncryptedShare::new(
+                self.party_id,
+                key_id,
+                &share,
+                network_private_key,
+                &network_public_keys[&key_id],
+                rng,
+            )
+            .ok_or(DkgError::EncryptionFailure(key_id))?;
+            encrypted_shares.push(encrypted_share);
+        }
+        Ok(encrypted_shares)
+    }
+
+    // decrypt the shares sent to this party's key_ids, ready to be passed to compute_secret
+    pub fn decrypt_shares(
+        &self,
+        network_private_key: &Scalar,
+        network_public_keys: &PubKeyMap, // key is party_id
+        encrypted_shares: &[EncryptedShare],
+    ) -> Result<HashMap<usize, Vec<(usize, Scalar)>>, DkgError> {
+        let mut shares: HashMap<usize, Vec<(usize, Scalar)>> = HashMap::new();
+        let mut missing_keys = HashSet::new();
+        let mut bad_shares = HashSet::new();
+        for encrypted_share in encrypted_shares
+            .iter()
+            .filter(|es| self.key_ids.contains(&es.key_id))
+        {
+            let sender = encrypted_share.sender;
+            match network_public_keys.get(&sender) {
+                Some(public_key) => {
+                    match encrypted_share.decrypt(network_private_key, public_key) {
+                        Some(share) => shares
+                            .entry(encrypted_share.key_id)
+                            .or_default()
+                            .push((sender, share)),
+                        None => {
+                            bad_shares.insert(sender);
+                        }
+                    }
+                }
+                None => {
+                    missing_keys.insert(sender);
+                }
+            }
+        }
+        if !missing_keys.is_empty() {
+            let mut missing_keys = Vec::from_iter(missing_keys);
+            missing_keys.sort();
+            return Err(DkgError::MissingPublicKeys(missing_keys));
+        }
+        if !bad_shares.is_empty() {
+            let mut bad_shares = Vec::from_iter(bad_shares);
+            bad_shares.sort();
+            return Err(DkgError::BadEncryptedShares(bad_shares));
+        }
+        Ok(shares)
+    }
+
     // TODO: Maybe this should be private? If receive_share is keeping track
     // of which it receives, then this could be called when it has N shares from unique ids
     #[allow(non_snake_case)]
@@ -217,73 +466,958 @@ impl Party {
         &mut self,
         shares: HashMap<usize, Vec<(usize, Scalar)>>,
         A: &[PolyCommitment],
-    ) -> &PubKeyMap {
-        // TODO: return error with a list of missing shares
-        assert!(shares.len() == self.key_ids.len());
+    ) -> Result<&PubKeyMap, DkgError> {
+        let qualified = (0..self.num_parties).collect();
+        self.compute_qualified_secret(&qualified, shares, A)
+    }
 
-        for Ai in A {
-            assert!(Ai.verify()); // checks a0 proof
-            self.group_key += Ai.A[0].clone();
+    // compute the secrets using only the shares and commitments from the qualified parties,
+    // which is the set left over after resolve_complaints
+    #[allow(non_snake_case)]
+    pub fn compute_qualified_secret(
+        &mut self,
+        qualified: &HashSet<usize>,
+        shares: HashMap<usize, Vec<(usize, Scalar)>>,
+        A: &[PolyCommitment],
+    ) -> Result<&PubKeyMap, DkgError> {
+        if A.len() != self.num_parties {
+            return Err(DkgError::BadPolyCommitmentLen(self.num_parties, A.len()));
+        }
+
+        let mut qualified = Vec::from_iter(qualified.iter().cloned());
+        qualified.sort();
+
+        let bad_ids: Vec<usize> = qualified
+            .iter()
+            .cloned()
+            .filter(|i| {
+                *i >= self.num_parties
+                    || A[*i].A.len() != self.threshold
+                    || !A[*i].verify(&self.context)
+            })
+            .collect();
+        if !bad_ids.is_empty() {
+            return Err(DkgError::BadPolyCommitments(bad_ids));
+        }
+
+        let mut missing_shares = HashSet::new();
+        let mut bad_shares = HashSet::new();
+        for key_id in &self.key_ids {
+            for sender in &qualified {
+                match find_share(&shares, key_id, sender) {
+                    Some(s) => {
+                        if !compute::check_share(&id_to_scalar(key_id), &s, &A[*sender].A) {
+                            bad_shares.insert(*sender);
+                        }
+                    }
+                    None => {
+                        missing_shares.insert(*sender);
+                    }
+                }
+            }
+        }
+        if !missing_shares.is_empty() {
+            let mut missing_shares = Vec::from_iter(missing_shares);
+            missing_shares.sort();
+            return Err(DkgError::MissingShares(missing_shares));
+        }
+        if !bad_shares.is_empty() {
+            let mut bad_shares = Vec::from_iter(bad_shares);
+            bad_shares.sort();
+            return Err(DkgError::BadShares(bad_shares));
+        }
+
+        self.group_key = Point::zero();
+        for i in &qualified {
+            self.group_key += A[*i].A[0];
         }
 
         for key_id in &self.key_ids {
-            assert!(shares[key_id].len() == self.num_parties);
-            self.private_keys.insert(*key_id, Scalar::zero());
-
-            for (sender, s) in &shares[key_id] {
-                let Ai = &A[*sender];
-                assert!(
-                    s * G
-                        == (0..Ai.A.len()).fold(Point::zero(), |s, j| s
-                            + (id_to_scalar(key_id) ^ j) * Ai.A[j])
-                );
-                self.private_keys
-                    .insert(*key_id, self.private_keys[key_id] + s);
+            let mut private_key = Scalar::zero();
+            for sender in &qualified {
+                if let Some(s) = find_share(&shares, key_id, sender) {
+                    private_key += s;
+                }
+            }
+            self.private_keys.insert(*key_id, private_key);
+            self.public_keys.insert(*key_id, private_key * G);
+        }
+
+        Ok(&self.public_keys)
+    }
+
+    // complain about every (sender, key_id) share which is missing or fails to verify
+    #[allow(non_snake_case)]
+    pub fn get_complaints(
+        &self,
+        shares: &HashMap<usize, Vec<(usize, Scalar)>>,
+        A: &[PolyCommitment],
+    ) -> Vec<Complaint> {
+        let mut key_ids = Vec::from_iter(self.key_ids.iter().cloned());
+        key_ids.sort();
+
+        let mut complaints = Vec::new();
+        for key_id in &key_ids {
+            for (sender, Ai) in A.iter().enumerate() {
+                let is_valid = match find_share(shares, key_id, &sender) {
+                    Some(s) => compute::check_share(&id_to_scalar(key_id), &s, &Ai.A),
+                    None => false,
+                };
+                if !is_valid {
+                    complaints.push(Complaint {
+                        accuser: self.party_id,
+                        accused: sender,
+                        key_id: *key_id,
+                    });
+                }
+            }
+        }
+        complaints
+    }
+
+    // reveal the disputed shares for every complaint against this party.  party_keys is the
+    // key ids of each party, and only a share the accuser owns is ever revealed, or forged
+    // complaints for t key ids would reveal the whole polynomial
+    pub fn justify(
+        &self,
+        complaints: &[Complaint],
+        party_keys: &[HashSet<usize>],
+    ) -> Vec<Justification> {
+        complaints
+            .iter()
+            .filter(|c| c.accused == self.party_id && owns_key(party_keys, c))
+            .map(|c| Justification {
+                accused: self.party_id,
+                key_id: c.key_id,
+                share: self.f.eval(id_to_scalar(&c.key_id)),
+            })
+            .collect()
+    }
+
+    // the nonce is used up, so a second package with it is refused
+    pub fn sign(&mut self, package: &SigningPackage) -> Result<Scalar, SignerError> {
+        self.sign_with_tweak(package, None)
+    }
+
+    // the shares aggregate to a BIP-340 signature
+    pub fn sign_bip340(&mut self, package: &SigningPackage) -> Result<Scalar, SignerError> {
+        self.sign_with_tweak(package, Some(Scalar::zero()))
+    }
+
+    // sign for the taproot output key of the group key, see taproot::output_key
+    pub fn sign_taproot(
+        &mut self,
+        package: &SigningPackage,
+        merkle_root: Option<[u8; 32]>,
+    ) -> Result<Scalar, SignerError> {
+        let tweak = taproot::tweak(&self.group_key, merkle_root).ok_or(SignerError::BadTweak)?;
+        self.sign_with_tweak(package, Some(tweak))
+    }
+
+    fn sign_with_tweak(
+        &mut self,
+        package: &SigningPackage,
+        tweak: Option<Scalar>,
+    ) -> Result<Scalar, SignerError> {
+        let public_nonce = self.check_package(package)?;
+        let index = self.nonce_index(public_nonce)?;
+        let nonce = mem::replace(&mut self.nonces[index], Nonce::zero());
+
+        Ok(self.sign_with_nonce(package, &nonce, tweak))
+    }
+
+    // Used nonces are zeroed in place, so the rest keep their place in this party's row of B,
+    // and a used or unknown nonce is never found
+    fn nonce_index(&self, public_nonce: &PublicNonce) -> Result<usize, SignerError> {
+        self.nonces
+            .iter()
+            .position(|n| !n.is_zero() && PublicNonce::from(n) == *public_nonce)
+            .ok_or(SignerError::BadPublicNonce(self.party_id))
+    }
+
+    // Sign every package in a batch from SignatureAggregator::signing_packages, each with its
+    // own nonce, and return all the shares together
+    pub fn sign_batch(
+        &mut self,
+        packages: &[SigningPackage],
+    ) -> Result<BatchSignatureShare, SignerError> {
+        self.sign_batch_with_tweak(packages, None)
+    }
+
+    pub fn sign_batch_bip340(
+        &mut self,
+        packages: &[SigningPackage],
+    ) -> Result<BatchSignatureShare, SignerError> {
+        self.sign_batch_with_tweak(packages, Some(Scalar::zero()))
+    }
+
+    pub fn sign_batch_taproot(
+        &mut self,
+        packages: &[SigningPackage],
+        merkle_root: Option<[u8; 32]>,
+    ) -> Result<BatchSignatureShare, SignerError> {
+        let tweak = taproot::tweak(&self.group_key, merkle_root).ok_or(SignerError::BadTweak)?;
+        self.sign_batch_with_tweak(packages, Some(tweak))
+    }
+
+    // Two messages signed with one nonce leak the private keys, so nothing is signed if any
+    // nonce is in the batch twice, or was used before.  Every nonce is found before any is
+    // used up, so a refused batch leaves them all unused
+    fn sign_batch_with_tweak(
+        &mut self,
+        packages: &[SigningPackage],
+        tweak: Option<Scalar>,
+    ) -> Result<BatchSignatureShare, SignerError> {
+        SigningPackage::check_batch(packages)?;
+
+        let mut indices = Vec::new();
+        for package in packages {
+            let index = self.nonce_index(self.check_package(package)?)?;
+            if indices.contains(&index) {
+                return Err(SignerError::DuplicateNonce(self.party_id));
             }
-            self.public_keys
-                .insert(*key_id, self.private_keys[key_id] * G);
-            println!(
-                "Party {} key_id {} secret {}",
-                self.party_id, key_id, self.private_keys[key_id]
-            );
+            indices.push(index);
         }
 
-        &self.public_keys
+        let z_i = zip(packages, indices)
+            .map(|(package, index)| {
+                let nonce = mem::replace(&mut self.nonces[index], Nonce::zero());
+                self.sign_with_nonce(package, &nonce, tweak)
+            })
+            .collect();
+        let key_ids = packages
+            .first()
+            .map_or(Vec::new(), |p| p.signer_key_ids(self.party_id).to_vec());
+
+        Ok(BatchSignatureShare {
+            id: self.party_id,
+            z_i,
+            key_ids,
+        })
+    }
+
+    // Like sign, but with a nonce from a batch made by gen_pool_nonces.  The nonce must be in
+    // this party's row of B, and is durably marked used before the share is computed
+    pub fn sign_from_pool<S: NonceStore>(
+        &self,
+        package: &SigningPackage,
+        batch: u64,
+        pool: &mut NoncePool<S>,
+    ) -> Result<Scalar, SignerError> {
+        self.sign_from_pool_with_tweak(package, batch, pool, None)
     }
 
+    pub fn sign_bip340_from_pool<S: NonceStore>(
+        &self,
+        package: &SigningPackage,
+        batch: u64,
+        pool: &mut NoncePool<S>,
+    ) -> Result<Scalar, SignerError> {
+        self.sign_from_pool_with_tweak(package, batch, pool, Some(Scalar::zero()))
+    }
+
+    pub fn sign_taproot_from_pool<S: NonceStore>(
+        &self,
+        package: &SigningPackage,
+        merkle_root: Option<[u8; 32]>,
+        batch: u64,
+        pool: &mut NoncePool<S>,
+    ) -> Result<Scalar, SignerError> {
+        let tweak = taproot::tweak(&self.group_key, merkle_root).ok_or(SignerError::BadTweak)?;
+        self.sign_from_pool_with_tweak(package, batch, pool, Some(tweak))
+    }
+
+    fn sign_from_pool_with_tweak<S: NonceStore>(
+        &self,
+        package: &SigningPackage,
+        batch: u64,
+        pool: &mut NoncePool<S>,
+        tweak: Option<Scalar>,
+    ) -> Result<Scalar, SignerError> {
+        let public_nonce = self.check_package(package)?;
+        let index = self
+            .B
+            .get(self.party_id)
+            .and_then(|b| b.iter().position(|n| n == public_nonce))
+            .ok_or(SignerError::BadPublicNonce(self.party_id))?;
+        let id = NonceId { batch, index };
+        if pool.public_nonce(&id)? != *public_nonce {
+            return Err(SignerError::BadPublicNonce(self.party_id));
+        }
+
+        let nonce = pool.take(&id)?;
+        Ok(self.sign_with_nonce(package, &nonce, tweak))
+    }
+
+    // Refuse a package which leaves this party out, has it sign with no keys or keys it does
+    // not own, or has too few keys to make a signature.  Returns this party's public nonce,
+    // which the caller must check is one it generated
+    fn check_package<'a>(
+        &self,
+        package: &'a SigningPackage,
+    ) -> Result<&'a PublicNonce, SignerError> {
+        let public_nonce = package
+            .nonce(self.party_id)
+            .ok_or_else(|| SignerError::MissingPublicNonces(vec![self.party_id]))?;
+
+        let key_ids = package.signer_key_ids(self.party_id);
+        if key_ids.is_empty() || key_ids.iter().any(|k| !self.key_ids.contains(k)) {
+            return Err(SignerError::BadKeyIds(self.party_id, key_ids.to_vec()));
+        }
+
+        let num_keys = package.key_ids().len();
+        if num_keys < self.threshold {
+            return Err(SigningPackageError::BelowThreshold(self.threshold, num_keys).into());
+        }
+
+        Ok(public_nonce)
+    }
+
+    // BIP-340 uses the even Y versions of R and the (tweaked) group key, so with a tweak the
+    // nonce and key parts are negated to match
     #[allow(non_snake_case)]
-    pub fn sign(&self, msg: &[u8], signers: &SelectedSigners, nonce_index: usize) -> Scalar {
-        let (B, _R_vec, R) = compute_intermediate_values(&signers, &self.B, nonce_index, &msg);
-        let c = compute_challenge(&self.group_key, &R, &msg);
-        let nonce = &self.nonces[nonce_index]; // TODO: needs to check that index exists
+    fn sign_with_nonce(
+        &self,
+        package: &SigningPackage,
+        nonce: &Nonce,
+        tweak: Option<Scalar>,
+    ) -> Scalar {
+        let ctx = &self.context;
+        let msg = package.msg();
+        let key_ids = package.key_ids();
+        let (_R_vec, R) = compute::intermediate(package, ctx);
+        let (c, r_sign, key_sign) =
+            compute::tweaked_challenge(&self.group_key, &R, msg, tweak, ctx);
 
-        let mut z = &nonce.d + &nonce.e * compute_binding(&id_to_scalar(&self.party_id), &B, &msg);
-        for key_id in signers[&self.party_id].iter() {
-            z += c * &self.private_keys[key_id] * lambda(&key_id, signers);
+        let id = id_to_scalar(&self.party_id);
+        let rho = compute::binding(&id, &package.nonces(), msg, ctx);
+        let mut z = r_sign * (nonce.d + nonce.e * rho);
+        for key_id in package.signer_key_ids(self.party_id) {
+            z += key_sign * c * self.private_keys[key_id] * compute::lambda(key_id, key_ids);
         }
         z
     }
 }
 
-#[allow(non_snake_case)]
-pub struct Signature {
-    pub R: Point,
-    pub z: Scalar,
+impl traits::DkgParticipant for Party {
+    fn get_poly_commitments<RNG: RngCore + CryptoRng>(&self, rng: &mut RNG) -> Vec<PolyCommitment> {
+        vec![self.get_poly_commitment(rng)]
+    }
+
+    fn get_shares(&self) -> HashMap<usize, HashMap<usize, Scalar>> {
+        let mut shares = HashMap::new();
+        shares.insert(self.party_id, Party::get_shares(self).into_iter().collect());
+        shares
+    }
+
+    #[allow(non_snake_case)]
+    fn compute_secrets(
+        &mut self,
+        shares: &HashMap<usize, HashMap<usize, Scalar>>,
+        A: &[PolyCommitment],
+    ) -> Result<(), DkgError> {
+        let mut key_shares: HashMap<usize, Vec<(usize, Scalar)>> = HashMap::new();
+        for (sender, sender_shares) in shares {
+            for key_id in &self.key_ids {
+                if let Some(s) = sender_shares.get(key_id) {
+                    key_shares.entry(*key_id).or_default().push((*sender, *s));
+                }
+            }
+        }
+        self.compute_secret(key_shares, A)?;
+        Ok(())
+    }
+}
+
+// Everything a party has at the end of a Dkg, Refresh or Reshare.  The public keys and poly
+// commitments are the same for every party, and are what a SignatureAggregator is made from
+#[derive(Clone, Deserialize, Serialize)]
+pub struct KeyPackage {
+    pub party_id: usize,
+    pub num_keys: usize,
+    pub num_parties: usize,
+    pub threshold: usize,
+    pub group_key: Point,
+    pub public_keys: PubKeyMap, // every key_id's public key
+    pub poly_commitments: Vec<PolyCommitment>,
+    pub state: PartyState,
+}
+
+impl KeyPackage {
+    // Add new key_ids to the group, which must be the next unused ones.  Every party does this
+    // when key_ids are enrolled, except the new owner, which uses enroll instead
+    pub fn add_key_ids(&mut self, key_ids: &[usize]) -> Result<(), DkgError> {
+        self.check_new_key_ids(key_ids)?;
+        for key_id in key_ids {
+            let public_key = dkg::public_key(*key_id, &self.poly_commitments);
+            self.public_keys.insert(*key_id, public_key);
+        }
+        self.num_keys += key_ids.len();
+        Ok(())
+    }
+
+    // Take ownership of new key_ids without changing the group key.  Helpers holding at least
+    // the threshold of key_ids evaluate the group polynomial at each new key_id the same way
+    // they repair a lost share, see Party::repair_shares, and the private keys are checked
+    // against the poly commitments
+    pub fn enroll(
+        &mut self,
+        key_ids: &[usize],
+        helpers: &[usize],
+        sums: &[RepairSum],
+    ) -> Result<(), DkgError> {
+        self.check_new_key_ids(key_ids)?;
+        let mut private_keys = Vec::new();
+        for key_id in key_ids {
+            let private_key = dkg::repair_key(*key_id, helpers, sums, &self.poly_commitments)?;
+            private_keys.push((*key_id, private_key));
+        }
+
+        self.add_key_ids(key_ids)?;
+        for (key_id, private_key) in private_keys {
+            self.state.key_ids.insert(key_id);
+            self.state.private_keys.insert(key_id, private_key);
+        }
+        Ok(())
+    }
+
+    // the KeyPackage of the party which holds key_ids from a trusted dealer, see
+    // dkg::trusted_deal.  There must be a share for each of key_ids
+    #[allow(non_snake_case)]
+    pub fn from_dealer(
+        party_id: usize,
+        key_ids: HashSet<usize>,
+        num_keys: usize,
+        num_parties: usize,
+        commitment: &DkgCommitment,
+        shares: &[DkgShare],
+        context: &[u8],
+    ) -> Result<Self, DkgError> {
+        let mut private_keys = PrivKeyMap::new();
+        for share in shares {
+            if !key_ids.contains(&share.key_id) || share.key_id >= num_keys {
+                return Err(DkgError::WrongKeyId(share.key_id));
+            }
+            let private_key = dkg::check_dealt_share(commitment, share, share.key_id, context)?;
+            private_keys.insert(share.key_id, private_key);
+        }
+        if private_keys.len() != key_ids.len() {
+            return Err(DkgError::MissingShares(vec![0]));
+        }
+
+        let A = vec![commitment.commitment.clone()];
+        let threshold = A[0].A.len();
+        Ok(Self {
+            party_id,
+            num_keys,
+            num_parties,
+            threshold,
+            group_key: A[0].A[0],
+            public_keys: (0..num_keys)
+                .map(|key_id| (key_id, dkg::public_key(key_id, &A)))
+                .collect(),
+            poly_commitments: A,
+            state: PartyState {
+                key_ids,
+                private_keys,
+                // the dealer's polynomial is the only one
+                polynomial: Polynomial::new(vec![Scalar::zero(); threshold]),
+                context: context.to_vec(),
+            },
+        })
+    }
+
+    fn check_new_key_ids(&self, key_ids: &[usize]) -> Result<(), DkgError> {
+        match zip(self.num_keys.., key_ids).find(|(next, key_id)| *next != **key_id) {
+            Some((_, key_id)) => Err(DkgError::WrongKeyId(*key_id)),
+            None => Ok(()),
+        }
+    }
+}
+
+// One party's side of a DKG ceremony.  Every party broadcasts its commitment, sends the
+// owner of each key_id its share once it has all the commitments, and finishes once it has
+// every share for its own key_ids.  In a Pedersen Dkg the first broadcast is a
+// PedersenCommitment, and the commitment is only broadcast in the extraction round, once every
+// share is in.  A Dkg can be saved and resumed in any state
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Dkg {
+    pub party_id: usize,
+    num_keys: usize,
+    num_parties: usize,
+    party: PartyState,
+    blinding: Option<Polynomial<Scalar>>,
+    rounds: DkgRounds,
+}
+
+impl Dkg {
+    // context is the application context, see Party::set_context
+    #[allow(clippy::too_many_arguments)]
+    pub fn new<RNG: RngCore + CryptoRng>(
+        party_id: usize,
+        key_ids: HashSet<usize>,
+        num_keys: usize,
+        num_parties: usize,
+        threshold: usize,
+        scheme: DkgScheme,
+        context: &[u8],
+        rng: &mut RNG,
+    ) -> Self {
+        let mut party = Party::new(party_id, key_ids, num_keys, num_parties, threshold, rng);
+        party.set_context(context);
+        let commitment = party.get_poly_commitment(rng);
+
+        let blinding = match scheme {
+            DkgScheme::Feldman => None,
+            DkgScheme::Pedersen => Some(VSS::random_poly(threshold - 1, rng)),
+        };
+        let pedersen_commitment = blinding
+            .as_ref()
+            .map(|b| PedersenCommitment::new(party_id, &party.f, b));
+        let rounds = DkgRounds::new(
+            party_id,
+            num_parties,
+            party.key_ids.iter().cloned(),
+            commitment,
+            pedersen_commitment,
+            context,
+        );
+
+        Self {
+            party_id,
+            num_keys,
+            num_parties,
+            party: party.save(),
+            blinding,
+            rounds,
+        }
+    }
+
+    pub fn scheme(&self) -> DkgScheme {
+        self.rounds.scheme()
+    }
+
+    pub fn state(&self) -> DkgState {
+        self.rounds.state()
+    }
+
+    fn party(&self) -> Party {
+        Party::load(
+            self.party_id,
+            self.num_keys,
+            self.num_parties,
+            &Point::zero(),
+            &self.party,
+        )
+    }
+
+    // the parties which have not sent everything for the current round
+    pub fn missing(&self) -> Vec<usize> {
+        self.rounds.missing()
+    }
+
+    // the round one broadcast of a Pedersen Dkg
+    pub fn pedersen_commitment(&self) -> Option<PedersenCommitment> {
+        self.rounds.pedersen_commitment()
+    }
+
+    // In a Pedersen Dkg the commitment is only revealed once every share is in, and asking
+    // for it then starts the extraction round
+    pub fn commitment(&mut self) -> Result<DkgCommitment, DkgError> {
+        self.rounds.commitment()
+    }
+
+    // a commitment which was already received can be received again
+    pub fn receive_pedersen_commitment(
+        &mut self,
+        msg: &PedersenCommitment,
+    ) -> Result<(), DkgError> {
+        self.rounds.receive_pedersen_commitment(msg)
+    }
+
+    // a commitment which was already received can be received again
+    pub fn receive_commitment(&mut self, msg: &DkgCommitment) -> Result<(), DkgError> {
+        self.rounds.receive_commitment(msg)
+    }
+
+    // Once every commitment is in, the shares to send to the owners of the other key_ids.
+    // The shares for this party's key_ids are kept, and the shares can be sent again until the
+    // Dkg is done
+    pub fn shares(&mut self) -> Result<Vec<DkgShare>, DkgError> {
+        self.rounds.shares(
+            &self.party.polynomial,
+            self.blinding.as_ref(),
+            self.num_keys,
+        )
+    }
+
+    // a share which was already received can be received again
+    pub fn receive_share(&mut self, msg: &DkgShare) -> Result<(), DkgError> {
+        self.rounds.receive_share(msg)
+    }
+
+    // In the extraction round of a Pedersen Dkg, the shares from dealers whose commitments are
+    // missing or were refused, to broadcast so every party can rebuild them
+    pub fn recovery_shares(&self, dealers: &[usize]) -> Result<Vec<RecoveryShare>, DkgError> {
+        self.rounds.recovery_shares(dealers)
+    }
+
+    // a recovery share which was already received can be received again
+    pub fn receive_recovery_share(&mut self, msg: &RecoveryShare) -> Result<(), DkgError> {
+        self.rounds.receive_recovery_share(msg)
+    }
+
+    #[allow(non_snake_case)]
+    pub fn finish(&mut self) -> Result<KeyPackage, DkgError> {
+        let A = self.rounds.check_finish()?;
+        let shares = self
+            .rounds
+            .received_shares()
+            .iter()
+            .map(|(key_id, s)| (*key_id, s.iter().map(|(i, s)| (*i, *s)).collect()))
+            .collect();
+        let mut party = self.party();
+        party.compute_secret(shares, &A)?;
+
+        self.rounds.done();
+        Ok(KeyPackage {
+            party_id: self.party_id,
+            num_keys: self.num_keys,
+            num_parties: self.num_parties,
+            threshold: self.rounds.threshold(),
+            group_key: party.group_key,
+            public_keys: (0..self.num_keys)
+                .map(|key_id| (key_id, dkg::public_key(key_id, &A)))
+                .collect(),
+            poly_commitments: A,
+            state: party.save(),
+        })
+    }
+}
+
+// One party's side of a proactive refresh of a KeyPackage.  It runs like a Dkg, but every
+// party deals a polynomial with a zero constant term and adds the shares it receives to the
+// private keys for its key_ids.  The group key is unchanged, but shares from before the
+// refresh no longer combine with the new ones.  Every party must take part
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Refresh {
+    key_package: KeyPackage,
+    polynomial: Polynomial<Scalar>,
+    rounds: Rounds<Vec<Point>>,
 }
 
-impl Signature {
-    // verify: R' = z * G + -c * publicKey, pass if R' == R
+impl Refresh {
+    #[allow(non_snake_case)]
+    pub fn new<RNG: RngCore + CryptoRng>(key_package: KeyPackage, rng: &mut RNG) -> Self {
+        let polynomial = VSS::random_zero_poly(key_package.threshold - 1, rng);
+        let A = polynomial.data().iter().map(|a| a * G).collect();
+
+        let party_id = key_package.party_id;
+        let senders = (0..key_package.num_parties).collect();
+        let key_ids = key_package.state.key_ids.iter().cloned();
+        Self {
+            polynomial,
+            rounds: Rounds::new(senders, key_ids).with_commitment(party_id, A),
+            key_package,
+        }
+    }
+
+    pub fn state(&self) -> DkgState {
+        self.rounds.state()
+    }
+
+    // the parties which have not sent everything for the current round
+    pub fn missing(&self) -> Vec<usize> {
+        self.rounds.missing()
+    }
+
+    pub fn commitment(&self) -> RefreshCommitment {
+        RefreshCommitment {
+            sender: self.key_package.party_id,
+            A: self.rounds.commitments()[&self.key_package.party_id].clone(),
+        }
+    }
+
+    // a commitment which was already received can be received again
+    pub fn receive_commitment(&mut self, msg: &RefreshCommitment) -> Result<(), DkgError> {
+        let threshold = self.key_package.threshold;
+        self.rounds
+            .receive_commitment(DkgState::Commitments, msg.sender, &msg.A, |_| {
+                dkg::check_refresh_commitment(msg, threshold)
+            })
+    }
+
+    // Once every commitment is in, the shares to send to the owners of the other key_ids.
+    // The shares for this party's key_ids are kept, and the shares can be sent again until the
+    // Refresh is done
+    pub fn shares(&mut self) -> Result<Vec<DkgShare>, DkgError> {
+        self.rounds.start_shares()?;
+        let (party_id, num_keys) = (self.key_package.party_id, self.key_package.num_keys);
+        Ok(self.rounds.deal(party_id, &self.polynomial, None, num_keys))
+    }
+
+    // a share which was already received can be received again
+    pub fn receive_share(&mut self, msg: &DkgShare) -> Result<(), DkgError> {
+        self.rounds.receive_feldman_share(msg)
+    }
+
+    // the refreshed KeyPackage, with the same group key
+    #[allow(non_snake_case)]
+    pub fn finish(&mut self) -> Result<KeyPackage, DkgError> {
+        self.rounds.check_finish()?;
+
+        let mut key_package = self.key_package.clone();
+        let state = &mut key_package.state;
+        for key_id in self.rounds.shares().keys() {
+            let private_key = state.private_keys.entry(*key_id).or_insert(Scalar::zero());
+            *private_key += self.rounds.share_sum(*key_id);
+        }
+        // state.polynomial is left as the Dkg polynomial, whose shares were already sent.  The
+        // refresh is only in the private keys, public keys and poly commitments
+        let commitments = self.rounds.commitments();
+        for (key_id, public_key) in key_package.public_keys.iter_mut() {
+            let id = id_to_scalar(key_id);
+            *public_key = commitments
+                .values()
+                .fold(*public_key, |p, R| p + compute::poly(&id, R));
+        }
+        key_package.poly_commitments =
+            dkg::refresh_poly_commitments(&key_package.poly_commitments, commitments);
+
+        self.rounds.done();
+        Ok(key_package)
+    }
+}
+
+// One new party's side of a Reshare, which moves the group secret to a new committee with its
+// own num_keys, num_parties and threshold.  Old parties holding at least the old threshold of
+// key_ids deal their part of the secret with Party::reshare, and each dealer's commitment is
+// checked against the old public keys of its key_ids, so the new committee has the same group
+// key.  Shares are refused until every commitment is in
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Reshare {
+    pub party_id: usize,
+    key_ids: HashSet<usize>,
+    num_keys: usize,
+    num_parties: usize,
+    threshold: usize,
+    group_key: Point,
+    dealer_keys: BTreeMap<usize, Point>, // each dealer's old public keys times their lambdas
+    context: Vec<u8>,
+    rounds: Rounds<PolyCommitment>,
+}
+
+impl Reshare {
+    // dealers are the old party ids which deal with the key_ids each of them holds, in the
+    // form SigningPackage::with_key_ids takes them, and they must hold at least old_threshold
+    // key_ids
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        party_id: usize,
+        key_ids: HashSet<usize>,
+        num_keys: usize,
+        num_parties: usize,
+        threshold: usize,
+        old_threshold: usize,
+        dealers: &[(usize, Vec<usize>)],
+        group_key: &Point,
+        old_public_keys: &PubKeyMap,
+        context: &[u8],
+    ) -> Result<Self, DkgError> {
+        dkg::check_dealers(dealers, old_threshold)?;
+        let dealer_key_ids: Vec<usize> = dealers
+            .iter()
+            .flat_map(|(_, key_ids)| key_ids.clone())
+            .collect();
+        let missing_keys: Vec<usize> = dealer_key_ids
+            .iter()
+            .filter(|key_id| !old_public_keys.contains_key(*key_id))
+            .cloned()
+            .collect();
+        if !missing_keys.is_empty() {
+            return Err(DkgError::MissingPublicKeys(missing_keys));
+        }
+        let dealer_keys: BTreeMap<usize, Point> = dealers
+            .iter()
+            .map(|(i, key_ids)| {
+                let key = key_ids.iter().fold(Point::zero(), |key, key_id| {
+                    key + compute::lambda(key_id, &dealer_key_ids) * old_public_keys[key_id]
+                });
+                (*i, key)
+            })
+            .collect();
+
+        Ok(Self {
+            party_id,
+            rounds: Rounds::new(
+                dealer_keys.keys().cloned().collect(),
+                key_ids.iter().cloned(),
+            ),
+            key_ids,
+            num_keys,
+            num_parties,
+            threshold,
+            group_key: *group_key,
+            dealer_keys,
+            context: context.to_vec(),
+        })
+    }
+
+    pub fn state(&self) -> DkgState {
+        self.rounds.state()
+    }
+
+    // the dealers which have not sent everything for the current round
+    pub fn missing(&self) -> Vec<usize> {
+        self.rounds.missing()
+    }
+
+    // a commitment which was already received can be received again
+    pub fn receive_commitment(&mut self, msg: &DkgCommitment) -> Result<(), DkgError> {
+        let (threshold, context) = (self.threshold, &self.context);
+        let dealer_key = self.dealer_keys.get(&msg.sender);
+        self.rounds.receive_commitment(
+            DkgState::Commitments,
+            msg.sender,
+            &msg.commitment,
+            |c| dkg::check_commitment(msg, threshold, context) && dealer_key == Some(&c.A[0]),
+        )?;
+        if self.rounds.missing().is_empty() {
+            self.rounds.start_shares()?;
+        }
+        Ok(())
+    }
+
+    // a share which was already received can be received again
+    pub fn receive_share(&mut self, msg: &DkgShare) -> Result<(), DkgError> {
+        self.rounds.receive_feldman_share(msg)
+    }
+
+    // The KeyPackage for the new committee.  Its poly commitments are the dealers', so a
+    // SignatureAggregator for it must be made with SignatureAggregator::from_key_package
     #[allow(non_snake_case)]
-    pub fn verify(&self, public_key: &Point, msg: &[u8]) -> bool {
-        let c = compute_challenge(&public_key, &self.R, &msg);
-        let R = &self.z * G + (-c) * public_key;
+    pub fn finish(&mut self) -> Result<KeyPackage, DkgError> {
+        self.rounds.check_finish()?;
 
-        println!("Verification R = {}", R);
+        // with fewer key_ids than the old threshold the dealer keys don't interpolate
+        let A: Vec<PolyCommitment> = self.rounds.commitments().values().cloned().collect();
+        let group_key = A.iter().fold(Point::zero(), |key, Ai| key + Ai.A[0]);
+        if group_key != self.group_key {
+            return Err(DkgError::BadGroupKey);
+        }
+
+        let private_keys = self
+            .key_ids
+            .iter()
+            .map(|key_id| (*key_id, self.rounds.share_sum(*key_id)))
+            .collect();
 
-        R == self.R
+        self.rounds.done();
+        Ok(KeyPackage {
+            party_id: self.party_id,
+            num_keys: self.num_keys,
+            num_parties: self.num_parties,
+            threshold: self.threshold,
+            group_key,
+            public_keys: (0..self.num_keys)
+                .map(|key_id| (key_id, dkg::public_key(key_id, &A)))
+                .collect(),
+            poly_commitments: A,
+            state: PartyState {
+                key_ids: self.key_ids.clone(),
+                private_keys,
+                // a new party dealt no polynomial of its own
+                polynomial: Polynomial::new(vec![Scalar::zero(); self.threshold]),
+                context: self.context.clone(),
+            },
+        })
     }
 }
 
+// Warning: the trait gen_nonces replaces the pregenerated nonces with a single fresh one
+impl traits::Signer for Party {
+    fn get_ids(&self) -> Vec<usize> {
+        vec![self.party_id]
+    }
+
+    fn get_key_ids(&self) -> Vec<usize> {
+        let mut key_ids = Vec::from_iter(self.key_ids.iter().cloned());
+        key_ids.sort();
+        key_ids
+    }
+
+    fn get_signer_key_ids(&self) -> Vec<(usize, Vec<usize>)> {
+        vec![(self.party_id, traits::Signer::get_key_ids(self))]
+    }
+
+    fn gen_nonces<RNG: RngCore + CryptoRng>(&mut self, rng: &mut RNG) -> Vec<PublicNonce> {
+        Party::gen_nonces(self, 1, rng)
+    }
+
+    fn sign(&mut self, package: &SigningPackage) -> Result<Vec<SignatureShare>, SignerError> {
+        self.sign_shares(package, None)
+    }
+
+    fn sign_bip340(
+        &mut self,
+        package: &SigningPackage,
+    ) -> Result<Vec<SignatureShare>, SignerError> {
+        self.sign_shares(package, Some(Scalar::zero()))
+    }
+
+    fn sign_taproot(
+        &mut self,
+        package: &SigningPackage,
+        merkle_root: Option<[u8; 32]>,
+    ) -> Result<Vec<SignatureShare>, SignerError> {
+        let tweak = taproot::tweak(&self.group_key, merkle_root).ok_or(SignerError::BadTweak)?;
+        self.sign_shares(package, Some(tweak))
+    }
+}
+
+impl Party {
+    fn sign_shares(
+        &mut self,
+        package: &SigningPackage,
+        tweak: Option<Scalar>,
+    ) -> Result<Vec<SignatureShare>, SignerError> {
+        Ok(vec![SignatureShare {
+            id: self.party_id,
+            z_i: self.sign_with_tweak(package, tweak)?,
+            key_ids: package.signer_key_ids(self.party_id).to_vec(),
+        }])
+    }
+}
+
+// A share from outside the package or a second share from a signer is bad, and is reported
+// before any share is checked so it can't hide among the good ones
+fn check_party_sigs<I: Iterator<Item = usize>>(
+    party_ids: &[usize],
+    sig_ids: I,
+) -> Result<(), AggregatorError> {
+    let mut received = vec![false; party_ids.len()];
+    let mut bad_ids = Vec::new();
+    for id in sig_ids {
+        match party_ids.iter().position(|i| *i == id) {
+            Some(pos) if !received[pos] => received[pos] = true,
+            _ => bad_ids.push(id),
+        }
+    }
+    if !bad_ids.is_empty() {
+        bad_ids.sort();
+        return Err(AggregatorError::BadPartySigs(bad_ids));
+    }
+
+    let missing_ids: Vec<usize> = zip(party_ids, &received)
+        .filter(|(_, received)| !**received)
+        .map(|(id, _)| *id)
+        .collect();
+    if !missing_ids.is_empty() {
+        return Err(AggregatorError::MissingPartySigs(missing_ids));
+    }
+    Ok(())
+}
+
 #[allow(non_snake_case)]
 pub struct SignatureAggregator {
     pub num_keys: usize,
@@ -293,8 +1427,9 @@ pub struct SignatureAggregator {
     pub B: Vec<Vec<PublicNonce>>, // outer vector is N-long, inner vector is T-long
     pub group_key: Point,       // the group's combined public key
     pub public_keys: PubKeyMap, // the public key for each point
-    nonce_ctr: usize,
-    num_nonces: usize,
+    pub context: Vec<u8>,
+    pub min_nonces: usize, // parties with no more unused nonces than this are sent a NonceRequest
+    nonce_ctrs: Vec<usize>, // the next unused index into each party's row of B
 }
 
 impl SignatureAggregator {
@@ -306,88 +1441,570 @@ impl SignatureAggregator {
         A: Vec<PolyCommitment>,
         B: Vec<Vec<PublicNonce>>,
         public_keys: PubKeyMap,
-    ) -> Self {
-        assert!(A.len() == num_parties);
-        for A_i in &A {
-            assert!(A_i.verify());
+    ) -> Result<Self, AggregatorError> {
+        let qualified = (0..num_parties).collect();
+        Self::new_qualified(
+            num_keys,
+            num_parties,
+            threshold,
+            A,
+            B,
+            public_keys,
+            &qualified,
+            &[],
+        )
+    }
+
+    // the group key is only computed over the parties which survived the complaint round.
+    // context is the application context the parties were given with Party::set_context
+    #[allow(non_snake_case)]
+    pub fn new_qualified(
+        num_keys: usize,
+        num_parties: usize,
+        threshold: usize,
+        A: Vec<PolyCommitment>,
+        B: Vec<Vec<PublicNonce>>,
+        public_keys: PubKeyMap,
+        qualified: &HashSet<usize>,
+        context: &[u8],
+    ) -> Result<Self, AggregatorError> {
+        if A.len() != num_parties {
+            return Err(AggregatorError::BadPolyCommitmentLen(num_parties, A.len()));
+        }
+
+        let mut qualified = Vec::from_iter(qualified.iter().cloned());
+        qualified.sort();
+
+        let bad_ids: Vec<usize> = qualified
+            .iter()
+            .cloned()
+            .filter(|i| *i >= num_parties || A[*i].A.len() != threshold || !A[*i].verify(context))
+            .collect();
+        if !bad_ids.is_empty() {
+            return Err(AggregatorError::BadPolyCommitments(bad_ids));
         }
 
-        let mut key = Point::new(); // TODO: Compute pub key from A
-        for A_i in &A {
-            key += &A_i.A[0];
+        let mut key = Point::new();
+        for i in &qualified {
+            key += &A[*i].A[0];
         }
-        println!("SA groupKey {}", key);
 
-        assert!(B.len() == num_parties);
-        let num_nonces = B[0].len();
-        for b in &B {
-            assert!(num_nonces == b.len());
+        if B.len() != num_parties {
+            return Err(AggregatorError::BadNonceLen(num_parties, B.len()));
         }
 
-        Self {
-            num_keys: num_keys,
-            num_parties: num_parties,
-            threshold: threshold,
-            A: A,
-            B: B,
+        Ok(Self {
+            num_keys,
+            num_parties,
+            threshold,
+            A,
+            B,
             group_key: key,
-            public_keys: public_keys,
-            nonce_ctr: 0,
-            num_nonces: num_nonces,
-        }
+            public_keys,
+            context: context.to_vec(),
+            min_nonces: 1,
+            nonce_ctrs: vec![0; num_parties],
+        })
     }
 
+    // the aggregator for the group in a KeyPackage, which works for a Reshare as well as a Dkg
     #[allow(non_snake_case)]
+    pub fn from_key_package(
+        key_package: &KeyPackage,
+        B: Vec<Vec<PublicNonce>>,
+    ) -> Result<Self, AggregatorError> {
+        let num_parties = key_package.num_parties;
+        if B.len() != num_parties {
+            return Err(AggregatorError::BadNonceLen(num_parties, B.len()));
+        }
+
+        Ok(Self {
+            num_keys: key_package.num_keys,
+            num_parties,
+            threshold: key_package.threshold,
+            A: key_package.poly_commitments.clone(),
+            B,
+            group_key: key_package.group_key,
+            public_keys: key_package.public_keys.clone(),
+            context: key_package.state.context.clone(),
+            min_nonces: 1,
+            nonce_ctrs: vec![0; num_parties],
+        })
+    }
+
+    // Add the public keys of key_ids enrolled with KeyPackage::enroll, which must be the next
+    // unused ones
+    pub fn add_key_ids(&mut self, key_ids: &[usize]) -> Result<(), AggregatorError> {
+        if let Some((_, key_id)) =
+            zip(self.num_keys.., key_ids).find(|(next, key_id)| *next != **key_id)
+        {
+            return Err(AggregatorError::BadKeyId(*key_id));
+        }
+
+        for key_id in key_ids {
+            let public_key = dkg::public_key(*key_id, &self.A);
+            self.public_keys.insert(*key_id, public_key);
+        }
+        self.num_keys += key_ids.len();
+        Ok(())
+    }
+
+    // a package with the next unused nonce of each signing party
+    pub fn signing_package(
+        &self,
+        msg: &[u8],
+        signers: &SelectedSigners,
+    ) -> Result<SigningPackage, AggregatorError> {
+        let mut packages = self.signing_packages(&[msg], signers)?;
+        Ok(packages.remove(0))
+    }
+
+    // a package for each message, which use the next unused nonces of each signing party in
+    // turn, to be signed with Party::sign_batch
+    pub fn signing_packages(
+        &self,
+        msgs: &[&[u8]],
+        signers: &SelectedSigners,
+    ) -> Result<Vec<SigningPackage>, AggregatorError> {
+        let party_ids = get_party_ids(signers);
+        let bad_party_ids: Vec<usize> = party_ids
+            .iter()
+            .cloned()
+            .filter(|i| *i >= self.B.len())
+            .collect();
+        if !bad_party_ids.is_empty() {
+            return Err(AggregatorError::BadPartySigs(bad_party_ids));
+        }
+        let out_of_nonces: Vec<usize> = party_ids
+            .iter()
+            .cloned()
+            .filter(|i| self.nonce_ctrs[*i] + msgs.len() > self.B[*i].len())
+            .collect();
+        if !out_of_nonces.is_empty() {
+            return Err(AggregatorError::OutOfNonces(out_of_nonces));
+        }
+
+        let key_ids: Vec<(usize, Vec<usize>)> = signers
+            .iter()
+            .map(|(i, k)| (*i, k.iter().cloned().collect()))
+            .collect();
+        let mut packages = Vec::new();
+        for (j, msg) in msgs.iter().enumerate() {
+            let nonces: Vec<(usize, PublicNonce)> = party_ids
+                .iter()
+                .map(|i| (*i, self.B[*i][self.nonce_ctrs[*i] + j].clone()))
+                .collect();
+            packages.push(SigningPackage::with_key_ids(
+                msg,
+                &nonces,
+                &key_ids,
+                self.threshold,
+            )?);
+        }
+        Ok(packages)
+    }
+
+    // sig_shares has one share per party, each covering all of its signing key_ids
     pub fn sign(
         &mut self,
-        msg: &[u8],
-        sig_shares: &[SignatureShare], // one per party and each contains vectors for all their pts
-        signers: &SelectedSigners,     // the list of party_ids
-    ) -> Signature {
-        let (_B, Ris, R) = compute_intermediate_values(&signers, &self.B, self.nonce_ctr, &msg);
+        package: &SigningPackage,
+        sig_shares: &[SignatureShare],
+    ) -> Result<Signature, AggregatorError> {
+        self.sign_next(package, sig_shares, None)
+    }
 
+    // aggregate shares from Party::sign_bip340
+    pub fn sign_bip340(
+        &mut self,
+        package: &SigningPackage,
+        sig_shares: &[SignatureShare],
+    ) -> Result<SchnorrProof, AggregatorError> {
+        let sig = self.sign_next(package, sig_shares, Some(Scalar::zero()))?;
+        Ok(SchnorrProof::new(&sig))
+    }
+
+    // aggregate shares from Party::sign_taproot, which verify against
+    // taproot::output_key(&self.group_key, merkle_root)
+    pub fn sign_taproot(
+        &mut self,
+        package: &SigningPackage,
+        sig_shares: &[SignatureShare],
+        merkle_root: Option<[u8; 32]>,
+    ) -> Result<SchnorrProof, AggregatorError> {
+        let tweak =
+            taproot::tweak(&self.group_key, merkle_root).ok_or(AggregatorError::BadTweak)?;
+        let sig = self.sign_next(package, sig_shares, Some(tweak))?;
+        Ok(SchnorrProof::new(&sig))
+    }
+
+    // the package must be from signing_package, and not already signed
+    fn sign_next(
+        &mut self,
+        package: &SigningPackage,
+        sig_shares: &[SignatureShare],
+        tweak: Option<Scalar>,
+    ) -> Result<Signature, AggregatorError> {
+        let party_ids = package.signers();
+        let bad_nonces: Vec<usize> = party_ids
+            .iter()
+            .cloned()
+            .filter(|i| {
+                self.B.get(*i).and_then(|b| b.get(self.nonce_ctrs[*i])) != package.nonce(*i)
+            })
+            .collect();
+        if !bad_nonces.is_empty() {
+            return Err(AggregatorError::BadPackageNonces(bad_nonces));
+        }
+
+        // the shares were released, so the nonces are burned even if some were bad.
+        // Parties which did not sign keep their next nonce
+        for i in &party_ids {
+            self.nonce_ctrs[*i] += 1;
+        }
+
+        self.aggregate(package, sig_shares, tweak)
+    }
+
+    // The traits::Aggregator has no nonce round, so the nonces in each package are added to B
+    // as they are signed, and the cursors move past them the same way.  A package which uses
+    // any of them again is refused
+    fn sign_unscheduled(
+        &mut self,
+        package: &SigningPackage,
+        sig_shares: &[SignatureShare],
+        tweak: Option<Scalar>,
+    ) -> Result<Signature, AggregatorError> {
+        let party_ids = package.signers();
+        let used_nonces: Vec<usize> = party_ids
+            .iter()
+            .cloned()
+            .filter(|i| {
+                *i >= self.B.len()
+                    || package
+                        .nonce(*i)
+                        .is_some_and(|nonce| self.B[*i].contains(nonce))
+            })
+            .collect();
+        if !used_nonces.is_empty() {
+            return Err(AggregatorError::BadPackageNonces(used_nonces));
+        }
+
+        for i in &party_ids {
+            if let Some(nonce) = package.nonce(*i) {
+                self.B[*i].push(nonce.clone());
+            }
+        }
+        self.sign_next(package, sig_shares, tweak)
+    }
+
+    // check every share so all the bad parties can be reported at once,
+    // then sum the shares into the group signature
+    #[allow(non_snake_case)]
+    fn aggregate(
+        &self,
+        package: &SigningPackage,
+        sig_shares: &[SignatureShare],
+        tweak: Option<Scalar>,
+    ) -> Result<Signature, AggregatorError> {
+        let ctx = &self.context;
+        let msg = package.msg();
+        let party_ids = package.signers();
+        let key_ids = package.key_ids();
+        check_party_sigs(&party_ids, sig_shares.iter().map(|sig| sig.id))?;
+
+        let (R_vec, R) = compute::intermediate(package, ctx);
         let mut z = Scalar::zero();
-        let c = compute_challenge(&self.group_key, &R, &msg); // only needed for checking z_i
+        let (c, r_sign, key_sign) =
+            compute::tweaked_challenge(&self.group_key, &R, msg, tweak, ctx);
+
+        let mut bad_party_sigs = Vec::new();
         for sig in sig_shares {
-            assert!(
-                sig.z_i * G
-                    == Ris[&sig.party_id]
-                        + signers[&sig.party_id]
-                            .iter()
-                            .fold(Point::zero(), |p, k| p + lambda(&k, signers)
-                                * c
-                                * self.public_keys[k])
-            );
+            let is_valid = match party_ids.iter().position(|i| *i == sig.id) {
+                Some(pos) => {
+                    sig.key_ids == package.signer_key_ids(sig.id)
+                        && sig.key_ids.iter().all(|k| self.public_keys.contains_key(k))
+                        && sig.z_i * G
+                            == r_sign * R_vec[pos]
+                                + sig.key_ids.iter().fold(Point::zero(), |p, k| {
+                                    p + key_sign
+                                        * compute::lambda(k, key_ids)
+                                        * c
+                                        * self.public_keys[k]
+                                })
+                }
+                None => false,
+            };
+            if !is_valid {
+                bad_party_sigs.push(sig.id);
+            }
             z += sig.z_i;
         }
-        self.update_nonce();
+        if !bad_party_sigs.is_empty() {
+            bad_party_sigs.sort();
+            return Err(AggregatorError::BadPartySigs(bad_party_sigs));
+        }
+
+        let verified = match tweak {
+            Some(t) => {
+                // the tweak is public, so its part of the signature is added here
+                let tweaked_key = taproot::tweaked_public_key(&self.group_key, &t);
+                z += c * t * bip340::parity(&tweaked_key);
+                SchnorrProof::new(&Signature { R: r_sign * R, z })
+                    .verify(&bip340::x_only(&tweaked_key), msg)
+            }
+            None => Signature { R, z }.verify(&self.group_key, msg, ctx),
+        };
+        let sig = Signature { R: r_sign * R, z };
+        if !verified {
+            return Err(AggregatorError::BadGroupSig);
+        }
+        Ok(sig)
+    }
+
+    // aggregate the shares from Party::sign_batch into a signature for each package
+    pub fn sign_batch<RNG: RngCore + CryptoRng>(
+        &mut self,
+        packages: &[SigningPackage],
+        sig_shares: &[BatchSignatureShare],
+        rng: &mut RNG,
+    ) -> Result<Vec<Signature>, AggregatorError> {
+        self.sign_batch_next(packages, sig_shares, None, rng)
+    }
 
-        let sig = Signature { R: R, z: z };
-        assert!(sig.verify(&self.group_key, msg));
-        sig
+    pub fn sign_batch_bip340<RNG: RngCore + CryptoRng>(
+        &mut self,
+        packages: &[SigningPackage],
+        sig_shares: &[BatchSignatureShare],
+        rng: &mut RNG,
+    ) -> Result<Vec<SchnorrProof>, AggregatorError> {
+        let sigs = self.sign_batch_next(packages, sig_shares, Some(Scalar::zero()), rng)?;
+        Ok(sigs.iter().map(SchnorrProof::new).collect())
     }
 
-    pub fn get_nonce_ctr(&self) -> usize {
-        self.nonce_ctr
+    pub fn sign_batch_taproot<RNG: RngCore + CryptoRng>(
+        &mut self,
+        packages: &[SigningPackage],
+        sig_shares: &[BatchSignatureShare],
+        merkle_root: Option<[u8; 32]>,
+        rng: &mut RNG,
+    ) -> Result<Vec<SchnorrProof>, AggregatorError> {
+        let tweak =
+            taproot::tweak(&self.group_key, merkle_root).ok_or(AggregatorError::BadTweak)?;
+        let sigs = self.sign_batch_next(packages, sig_shares, Some(tweak), rng)?;
+        Ok(sigs.iter().map(SchnorrProof::new).collect())
     }
 
-    fn update_nonce(&mut self) {
-        self.nonce_ctr += 1;
-        if self.nonce_ctr == self.num_nonces {
-            // TODO: Should this kick off the re-generation process?
-            println!("This is the last available nonce! Need to generate more!");
+    // the packages must be from signing_packages, and not already signed
+    fn sign_batch_next<RNG: RngCore + CryptoRng>(
+        &mut self,
+        packages: &[SigningPackage],
+        sig_shares: &[BatchSignatureShare],
+        tweak: Option<Scalar>,
+        rng: &mut RNG,
+    ) -> Result<Vec<Signature>, AggregatorError> {
+        SigningPackage::check_batch(packages)?;
+        let party_ids = match packages.first() {
+            Some(package) => package.signers(),
+            None => return Ok(Vec::new()),
+        };
+
+        let bad_nonces: Vec<usize> = party_ids
+            .iter()
+            .cloned()
+            .filter(|i| {
+                packages.iter().enumerate().any(|(j, package)| {
+                    self.B.get(*i).and_then(|b| b.get(self.nonce_ctrs[*i] + j)) != package.nonce(*i)
+                })
+            })
+            .collect();
+        if !bad_nonces.is_empty() {
+            return Err(AggregatorError::BadPackageNonces(bad_nonces));
         }
+
+        for i in &party_ids {
+            self.nonce_ctrs[*i] += packages.len();
+        }
+
+        self.aggregate_batch(packages, sig_shares, tweak, rng)
+    }
+
+    // Each party's shares are checked together, as one random linear combination over the
+    // batch.  The Lagrange coefficients are the same in every package, so each key is only
+    // multiplied once however many messages there are
+    #[allow(non_snake_case)]
+    fn aggregate_batch<RNG: RngCore + CryptoRng>(
+        &self,
+        packages: &[SigningPackage],
+        sig_shares: &[BatchSignatureShare],
+        tweak: Option<Scalar>,
+        rng: &mut RNG,
+    ) -> Result<Vec<Signature>, AggregatorError> {
+        let ctx = &self.context;
+        let party_ids = packages[0].signers();
+        let key_ids = packages[0].key_ids();
+        check_party_sigs(&party_ids, sig_shares.iter().map(|sig| sig.id))?;
+
+        // R_vec, R, c, r_sign and key_sign for each package
+        let challenges: Vec<(Vec<Point>, Point, Scalar, Scalar, Scalar)> = packages
+            .iter()
+            .map(|package| {
+                let (R_vec, R) = compute::intermediate(package, ctx);
+                let (c, r_sign, key_sign) =
+                    compute::tweaked_challenge(&self.group_key, &R, package.msg(), tweak, ctx);
+                (R_vec, R, c, r_sign, key_sign)
+            })
+            .collect();
+
+        let mut bad_party_sigs = Vec::new();
+        for sig in sig_shares {
+            let is_valid = match party_ids.iter().position(|i| *i == sig.id) {
+                Some(pos) => {
+                    sig.z_i.len() == packages.len()
+                        && sig.key_ids == packages[0].signer_key_ids(sig.id)
+                        && sig.key_ids.iter().all(|k| self.public_keys.contains_key(k))
+                        && {
+                            let weights: Vec<Scalar> =
+                                sig.z_i.iter().map(|_| Scalar::random(rng)).collect();
+                            let mut z = Scalar::zero();
+                            let mut R = Point::zero();
+                            let mut key_weight = Scalar::zero();
+                            for ((a, z_i), (R_vec, _, c, r_sign, key_sign)) in
+                                zip(zip(&weights, &sig.z_i), &challenges)
+                            {
+                                z += a * z_i;
+                                R += (a * r_sign) * R_vec[pos];
+                                key_weight += a * key_sign * c;
+                            }
+                            let key = sig.key_ids.iter().fold(Point::zero(), |p, k| {
+                                p + compute::lambda(k, key_ids) * self.public_keys[k]
+                            });
+                            z * G == R + key_weight * key
+                        }
+                }
+                None => false,
+            };
+            if !is_valid {
+                bad_party_sigs.push(sig.id);
+            }
+        }
+        if !bad_party_sigs.is_empty() {
+            bad_party_sigs.sort();
+            return Err(AggregatorError::BadPartySigs(bad_party_sigs));
+        }
+
+        // every share is good and there is exactly one from each signer, so the sums are good
+        // signatures without verifying each one
+        let mut sigs = Vec::new();
+        for (j, (_, R, c, r_sign, _)) in challenges.iter().enumerate() {
+            let mut z = sig_shares
+                .iter()
+                .fold(Scalar::zero(), |z, sig| z + sig.z_i[j]);
+            if let Some(t) = tweak {
+                let tweaked_key = taproot::tweaked_public_key(&self.group_key, &t);
+                z += c * t * bip340::parity(&tweaked_key);
+            }
+            sigs.push(Signature { R: r_sign * R, z });
+        }
+        Ok(sigs)
+    }
+
+    // the index into its row of B which each signing party uses in the next round
+    pub fn get_nonce_indices(&self, signers: &SelectedSigners) -> NonceIndices {
+        signers
+            .keys()
+            .filter(|i| **i < self.nonce_ctrs.len())
+            .map(|i| (*i, self.nonce_ctrs[*i]))
+            .collect()
+    }
+
+    // the public nonces of party_id which have not been used yet
+    pub fn get_unused_nonces(&self, party_id: usize) -> &[PublicNonce] {
+        match self.B.get(party_id) {
+            Some(b) => &b[self.nonce_ctrs[party_id].min(b.len())..],
+            None => &[],
+        }
+    }
+
+    // a request for a new row of B from every party which is running low
+    pub fn nonce_requests(&self) -> Vec<NonceRequest> {
+        (0..self.B.len())
+            .filter(|i| self.get_unused_nonces(*i).len() <= self.min_nonces)
+            .map(|i| NonceRequest {
+                party_id: i,
+                num_nonces: self.B[i].len().max(1) as u32,
+            })
+            .collect()
     }
 
     #[allow(non_snake_case)]
     pub fn set_party_nonces(&mut self, i: usize, B: Vec<PublicNonce>) {
         self.B[i] = B;
+        self.nonce_ctrs[i] = 0;
     }
 
     #[allow(non_snake_case)]
     pub fn set_group_nonces(&mut self, B: Vec<Vec<PublicNonce>>) {
+        self.nonce_ctrs = vec![0; B.len()];
         self.B = B;
-        self.nonce_ctr = 0;
-        self.num_nonces = self.B.len();
+    }
+}
+
+impl traits::Aggregator for SignatureAggregator {
+    // the public key of each key_id is computed from the poly commitments, and the
+    // public nonces come in the signing package rather than being pregenerated
+    #[allow(non_snake_case)]
+    fn new(
+        num_keys: usize,
+        threshold: usize,
+        A: Vec<PolyCommitment>,
+    ) -> Result<Self, AggregatorError> {
+        let num_parties = A.len();
+        let public_keys = (0..num_keys)
+            .map(|key_id| {
+                let id = id_to_scalar(&key_id);
+                let key = A
+                    .iter()
+                    .fold(Point::zero(), |p, Ai| p + compute::poly(&id, &Ai.A));
+                (key_id, key)
+            })
+            .collect();
+
+        SignatureAggregator::new(
+            num_keys,
+            num_parties,
+            threshold,
+            A,
+            vec![Vec::new(); num_parties],
+            public_keys,
+        )
+    }
+
+    fn sign(
+        &mut self,
+        package: &SigningPackage,
+        sig_shares: &[SignatureShare],
+    ) -> Result<Signature, AggregatorError> {
+        self.sign_unscheduled(package, sig_shares, None)
+    }
+
+    fn sign_bip340(
+        &mut self,
+        package: &SigningPackage,
+        sig_shares: &[SignatureShare],
+    ) -> Result<SchnorrProof, AggregatorError> {
+        let sig = self.sign_unscheduled(package, sig_shares, Some(Scalar::zero()))?;
+        Ok(SchnorrProof::new(&sig))
+    }
+
+    fn sign_taproot(
+        &mut self,
+        package: &SigningPackage,
+        sig_shares: &[SignatureShare],
+        merkle_root: Option<[u8; 32]>,
+    ) -> Result<SchnorrProof, AggregatorError> {
+        let tweak =
+            taproot::tweak(&self.group_key, merkle_root).ok_or(AggregatorError::BadTweak)?;
+        let sig = self.sign_unscheduled(package, sig_shares, Some(tweak))?;
+        Ok(SchnorrProof::new(&sig))
     }
 }
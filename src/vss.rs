@@ -1,3 +1,4 @@
+use num_traits::Zero;
 use polynomial::Polynomial;
 use rand_core::{CryptoRng, RngCore};
 use secp256k1_math::scalar::Scalar;
@@ -9,4 +10,15 @@ impl VSS {
         let params: Vec<Scalar> = (0..n + 1).map(|_| Scalar::random(rng)).collect();
         Polynomial::new(params)
     }
+
+    // shares of a polynomial with a zero constant term can be added to existing shares
+    // without changing the secret
+    pub fn random_zero_poly<RNG: RngCore + CryptoRng>(
+        n: usize,
+        rng: &mut RNG,
+    ) -> Polynomial<Scalar> {
+        let mut params = Self::random_poly(n, rng).data().to_vec();
+        params[0] = Scalar::zero();
+        Polynomial::new(params)
+    }
 }